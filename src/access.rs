@@ -0,0 +1,281 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Source Network Access Control
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    proxy::client_ip,
+    routes::auth::is_mutating,
+    util::{Error, ErrorKind}
+};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{uri::Origin, Method},
+    Data, Request
+};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    str::FromStr
+};
+
+/// A network in CIDR notation, such as `192.168.1.0/24` or `fd00::/8`. A
+/// single address without a prefix length is treated as a network containing
+/// only that address.
+#[derive(Copy, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8
+}
+
+impl FromStr for Cidr {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Cidr, ()> {
+        let mut parts = s.splitn(2, '/');
+        let network = IpAddr::from_str(parts.next().unwrap_or_default()).map_err(|_| ())?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match parts.next() {
+            Some(prefix_len) => u8::from_str(prefix_len).map_err(|_| ())?,
+            None => max_len
+        };
+
+        if prefix_len > max_len {
+            return Err(());
+        }
+
+        Ok(Cidr {
+            network,
+            prefix_len
+        })
+    }
+}
+
+impl Cidr {
+    /// Check if the address is in the network. IPv4-mapped IPv6 addresses
+    /// (ex. `::ffff:10.0.0.1`) are treated as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, to_canonical(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 32) as u32;
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 128);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false
+        }
+    }
+}
+
+/// Get the mask for a prefix of `prefix_len` bits in an address of `bits`
+/// bits. The mask is in the lowest `bits` bits of the result.
+fn prefix_mask(prefix_len: u8, bits: u8) -> u128 {
+    if prefix_len == 0 {
+        0
+    } else {
+        (!0u128 >> (128 - bits)) & (!0u128 << (bits - prefix_len))
+    }
+}
+
+/// Convert IPv4-mapped IPv6 addresses to IPv4 addresses
+fn to_canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => match ipv6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => IpAddr::V4(Ipv4Addr::new(
+                (high >> 8) as u8,
+                high as u8,
+                (low >> 8) as u8,
+                low as u8
+            )),
+            _ => ip
+        },
+        ip => ip
+    }
+}
+
+/// Allow and deny lists of networks. A client is permitted if it is not in
+/// the deny list, and the allow list is empty or contains the client.
+#[derive(Clone, Default)]
+pub struct AccessList {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>
+}
+
+impl AccessList {
+    /// Check if the client is permitted. If the client's address is not
+    /// known, it is only permitted if the lists are empty.
+    pub fn permits(&self, ip: Option<IpAddr>) -> bool {
+        match ip {
+            Some(ip) => {
+                !self.deny.iter().any(|cidr| cidr.contains(ip))
+                    && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip)))
+            }
+            None => self.allow.is_empty() && self.deny.is_empty()
+        }
+    }
+}
+
+/// A fairing which checks the client's address against the configured access
+/// lists before the request is routed. Mutating requests (`POST`, `PUT`,
+/// `PATCH`, `DELETE`) are checked against the write list, and all other
+/// requests against the read list. Denied requests are rerouted to
+/// [`access_denied`].
+///
+/// [`access_denied`]: fn.access_denied.html
+pub struct AccessControl {
+    read: AccessList,
+    write: AccessList,
+    denied_route: String
+}
+
+impl AccessControl {
+    /// Create the fairing with the read and write access lists. The API must
+    /// be mounted at `api_mount`.
+    pub fn new(read: AccessList, write: AccessList, api_mount: &str) -> AccessControl {
+        AccessControl {
+            read,
+            write,
+            denied_route: format!("{}/access_denied", api_mount)
+        }
+    }
+
+    /// Check if the request is permitted
+    fn permits(&self, request: &Request) -> bool {
+        let list = if is_mutating(request.method()) {
+            &self.write
+        } else {
+            &self.read
+        };
+
+        list.permits(client_ip(request))
+    }
+}
+
+impl Fairing for AccessControl {
+    fn info(&self) -> Info {
+        Info {
+            name: "Access Control",
+            kind: Kind::Request
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        if self.permits(request) {
+            return;
+        }
+
+        // Fairings can not respond to a request directly, so send it to a
+        // route which always fails
+        request.set_method(Method::Get);
+        request.set_uri(Origin::parse_owned(self.denied_route.clone()).unwrap());
+    }
+}
+
+/// The route which requests denied by [`AccessControl`] are sent to
+///
+/// [`AccessControl`]: struct.AccessControl.html
+#[get("/access_denied")]
+pub fn access_denied() -> Error {
+    Error::from(ErrorKind::Forbidden)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AccessList, Cidr};
+    use std::{net::IpAddr, str::FromStr};
+
+    /// Parse an IP address
+    fn ip(ip: &str) -> IpAddr {
+        IpAddr::from_str(ip).unwrap()
+    }
+
+    /// Parse a list of networks
+    fn cidrs(cidrs: &[&str]) -> Vec<Cidr> {
+        cidrs
+            .iter()
+            .map(|cidr| Cidr::from_str(cidr).unwrap())
+            .collect()
+    }
+
+    /// Networks are parsed with and without a prefix length
+    #[test]
+    fn parse() {
+        assert!(Cidr::from_str("10.0.0.0/8").is_ok());
+        assert!(Cidr::from_str("10.1.1.1").is_ok());
+        assert!(Cidr::from_str("fd00::/8").is_ok());
+        assert!(Cidr::from_str("::/0").is_ok());
+        assert!(Cidr::from_str("10.0.0.0/33").is_err());
+        assert!(Cidr::from_str("fd00::/129").is_err());
+        assert!(Cidr::from_str("10.0.0/8").is_err());
+        assert!(Cidr::from_str("10.0.0.0/").is_err());
+        assert!(Cidr::from_str("").is_err());
+    }
+
+    /// IPv4 networks contain the addresses matching the prefix
+    #[test]
+    fn contains_ipv4() {
+        let cidr = Cidr::from_str("192.168.10.0/23").unwrap();
+
+        assert!(cidr.contains(ip("192.168.10.1")));
+        assert!(cidr.contains(ip("192.168.11.255")));
+        assert!(!cidr.contains(ip("192.168.12.1")));
+        assert!(!cidr.contains(ip("fd00::1")));
+        assert!(cidr.contains(ip("::ffff:192.168.10.1")));
+        assert!(Cidr::from_str("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(Cidr::from_str("10.1.1.1").unwrap().contains(ip("10.1.1.1")));
+        assert!(!Cidr::from_str("10.1.1.1").unwrap().contains(ip("10.1.1.2")));
+    }
+
+    /// IPv6 networks contain the addresses matching the prefix
+    #[test]
+    fn contains_ipv6() {
+        let cidr = Cidr::from_str("fd00:1234::/32").unwrap();
+
+        assert!(cidr.contains(ip("fd00:1234::1")));
+        assert!(cidr.contains(ip("fd00:1234:ffff::1")));
+        assert!(!cidr.contains(ip("fd00:1235::1")));
+        assert!(!cidr.contains(ip("10.0.0.1")));
+    }
+
+    /// Empty lists permit everyone
+    #[test]
+    fn empty_list_permits() {
+        let list = AccessList::default();
+
+        assert!(list.permits(Some(ip("10.1.1.1"))));
+        assert!(list.permits(None));
+    }
+
+    /// Only clients in the allow list are permitted, unless they are denied
+    #[test]
+    fn allow_and_deny() {
+        let list = AccessList {
+            allow: cidrs(&["10.0.0.0/8"]),
+            deny: cidrs(&["10.0.50.0/24"])
+        };
+
+        assert!(list.permits(Some(ip("10.1.1.1"))));
+        assert!(!list.permits(Some(ip("10.0.50.1"))));
+        assert!(!list.permits(Some(ip("192.168.1.1"))));
+        assert!(!list.permits(None));
+    }
+
+    /// With only a deny list, everyone else is permitted
+    #[test]
+    fn deny_only() {
+        let list = AccessList {
+            allow: Vec::new(),
+            deny: cidrs(&["192.168.1.0/24"])
+        };
+
+        assert!(list.permits(Some(ip("10.1.1.1"))));
+        assert!(!list.permits(Some(ip("192.168.1.20"))));
+    }
+}
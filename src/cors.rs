@@ -0,0 +1,117 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Cross-Origin Resource Sharing
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    Data, Request, Response, Rocket
+};
+use rocket_cors::{AllowedOrigins, Cors};
+
+/// A fairing which applies CORS to cross-origin requests. Browsers also send
+/// the `Origin` header with some same-origin requests (ex. a `POST` from the
+/// web interface), so those requests are passed through without checking the
+/// allowed origins.
+pub struct CorsControl(Cors);
+
+impl CorsControl {
+    /// Create the fairing with the origins which are allowed to make
+    /// cross-origin requests. Credentials are allowed, so this must never be
+    /// all origins.
+    pub fn new(allowed_origins: AllowedOrigins) -> CorsControl {
+        CorsControl(Cors {
+            allowed_origins,
+            allow_credentials: true,
+            ..Cors::default()
+        })
+    }
+}
+
+impl Fairing for CorsControl {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Attach | Kind::Request | Kind::Response
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        self.0.on_attach(rocket)
+    }
+
+    fn on_request(&self, request: &mut Request, data: &Data) {
+        if is_cross_origin(request) {
+            self.0.on_request(request, data);
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        if is_cross_origin(request) {
+            self.0.on_response(request, response);
+        }
+    }
+}
+
+/// Check if the request has an `Origin` header which does not match the host
+/// the request was sent to
+fn is_cross_origin(request: &Request) -> bool {
+    let origin = match request.headers().get_one("Origin") {
+        Some(origin) => origin,
+        None => return false
+    };
+    let host = request.headers().get_one("Host").unwrap_or_default();
+
+    // Remove the scheme from the origin to get the host and port
+    let origin_host = origin.splitn(2, "://").nth(1).unwrap_or(origin);
+
+    !origin_host.eq_ignore_ascii_case(host)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+    use rocket::http::{Header, Status};
+    use serde_json::Value;
+
+    /// Requests from origins which are not allowed are rejected without CORS
+    /// headers
+    #[test]
+    fn unlisted_origin() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .header(Header::new("Host", "pi.hole"))
+            .header(Header::new("Origin", "https://example.com"))
+            .expect_status(Status::Forbidden)
+            .expect_no_header("Access-Control-Allow-Origin")
+            .expect_no_header("Access-Control-Allow-Credentials")
+            .expect_json(json!({
+                "error": {
+                    "key": "forbidden",
+                    "message": "Forbidden",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// Requests from the same origin are not checked against the allowed
+    /// origins
+    #[test]
+    fn same_origin() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .header(Header::new("Host", "pi.hole:8080"))
+            .header(Header::new("Origin", "http://pi.hole:8080"))
+            .expect_no_header("Access-Control-Allow-Origin")
+            .expect_json(json!({
+                "status": "success"
+            }))
+            .test();
+    }
+}
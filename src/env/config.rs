@@ -9,17 +9,22 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    access::{AccessList, Cidr},
     env::PiholeFile,
+    ftl::{FtlQueryType, SimulatorOptions, SocketOptions},
     util::{Error, ErrorKind}
 };
 use failure::{err_msg, Fail, ResultExt};
+use regex::Regex;
 use rocket::config::LoggingLevel;
+use rocket_cors::AllowedOrigins;
 use std::{
     fs::File,
     io::{self, prelude::*},
     net::Ipv4Addr,
     path::Path,
-    str::FromStr
+    str::FromStr,
+    time::Duration
 };
 use toml;
 
@@ -29,7 +34,21 @@ pub struct Config {
     #[serde(default)]
     general: General,
     #[serde(default)]
-    file_locations: Files
+    file_locations: Files,
+    #[serde(default)]
+    auth: Auth,
+    #[serde(default)]
+    cors: Cors,
+    #[serde(default)]
+    access: Access,
+    #[serde(default)]
+    tls: Tls,
+    #[serde(default)]
+    ftl: Ftl,
+    #[serde(default)]
+    simulator: Simulator,
+    #[serde(default)]
+    live: Live
 }
 
 impl Config {
@@ -65,7 +84,20 @@ impl Config {
 
     /// Check if the config settings are valid
     pub fn is_valid(&self) -> bool {
-        self.general.is_valid() && self.file_locations.is_valid()
+        self.general.is_valid()
+            && self.file_locations.is_valid()
+            && self.auth.is_valid()
+            && self.cors.is_valid()
+            && self.access.is_valid()
+            && self.tls.is_valid()
+            && self.ftl.is_valid()
+            && self.simulator.is_valid()
+            && self.live.is_valid()
+            // The live update server can not share the API's port
+            && (!self.live.enabled || self.live.port as usize != self.general.port)
+            // The live update server only speaks plain `ws://`, which browsers
+            // block on pages served over HTTPS
+            && (!self.live.enabled || !self.tls_enabled())
     }
 
     /// Get the configured location of a file
@@ -83,7 +115,12 @@ impl Config {
             PiholeFile::Gravity => &self.file_locations.gravity,
             PiholeFile::GravityBackup => &self.file_locations.gravity_backup,
             PiholeFile::BlackList => &self.file_locations.black_list,
-            PiholeFile::BlackListBackup => &self.file_locations.black_list_backup
+            PiholeFile::BlackListBackup => &self.file_locations.black_list_backup,
+            PiholeFile::ApiTokens => &self.file_locations.api_tokens,
+            PiholeFile::ApiTotp => &self.file_locations.api_totp,
+            PiholeFile::ApiAuditLog => &self.file_locations.api_audit_log,
+            PiholeFile::ApiAuditLogRotated => &self.file_locations.api_audit_log_rotated,
+            PiholeFile::ApiWebhooks => &self.file_locations.api_webhooks
         }
     }
 
@@ -99,6 +136,135 @@ impl Config {
         LoggingLevel::from_str(&self.general.log_level)
             .map_err(|e| Error::from(err_msg(e).context(ErrorKind::ConfigParsingError)))
     }
+
+    /// Get the networks of the reverse proxies which are trusted to report
+    /// the client's address
+    pub fn trusted_proxies(&self) -> Vec<Cidr> {
+        self.general.parse_trusted_proxies().unwrap_or_default()
+    }
+
+    /// Get the path prefix which the web interface and API are served under
+    /// (ex. `/pihole`). This is empty if they are served from the root.
+    pub fn url_prefix(&self) -> &str {
+        &self.general.url_prefix
+    }
+
+    /// How many seconds a session can go unused before it expires
+    pub fn session_idle_timeout(&self) -> u64 {
+        self.auth.session_idle_timeout
+    }
+
+    /// How many seconds a session can last, even if it is in use
+    pub fn session_absolute_timeout(&self) -> u64 {
+        self.auth.session_absolute_timeout
+    }
+
+    /// If clients can log in by sending the password hash instead of the
+    /// password
+    pub fn legacy_hash_login(&self) -> bool {
+        self.auth.legacy_hash_login
+    }
+
+    /// Get the origins which are allowed to make cross-origin requests. If no
+    /// origins are configured, only same-origin requests are allowed.
+    pub fn allowed_origins(&self) -> AllowedOrigins {
+        // Invalid origins are rejected when the config is validated, but allow
+        // no origins just in case
+        self.cors
+            .parse_origins()
+            .unwrap_or_else(|| AllowedOrigins::some::<&str>(&[]).0)
+    }
+
+    /// Get the networks which can make read requests
+    pub fn read_access(&self) -> AccessList {
+        self.access.read.parse().unwrap_or_default()
+    }
+
+    /// Get the networks which can make mutating requests
+    pub fn write_access(&self) -> AccessList {
+        self.access.write.parse().unwrap_or_default()
+    }
+
+    /// If the API is served over HTTPS
+    pub fn tls_enabled(&self) -> bool {
+        !self.tls.certificate.is_empty()
+    }
+
+    /// Get the locations of the TLS certificate and private key. If TLS is not
+    /// enabled, `None` is returned.
+    pub fn tls_files(&self) -> Option<(&str, &str)> {
+        if self.tls_enabled() {
+            Some((&self.tls.certificate, &self.tls.key))
+        } else {
+            None
+        }
+    }
+
+    /// If a self-signed certificate should be generated when the certificate
+    /// does not exist
+    pub fn tls_self_signed(&self) -> bool {
+        self.tls.self_signed
+    }
+
+    /// How often (in seconds) the certificate files are checked for changes
+    pub fn tls_reload_interval(&self) -> u64 {
+        self.tls.reload_interval
+    }
+
+    /// Get the options used to connect to FTL's socket
+    pub fn ftl_socket_options(&self) -> SocketOptions {
+        SocketOptions {
+            path: self.ftl.socket.clone(),
+            connect_timeout: Duration::from_millis(self.ftl.connect_timeout),
+            read_timeout: Duration::from_millis(self.ftl.read_timeout),
+            retries: self.ftl.retries,
+            pool_size: self.ftl.pool_size
+        }
+    }
+
+    /// Get the location of the shared memory snapshot to use instead of FTL's
+    /// shared memory, if one is configured
+    pub fn ftl_snapshot(&self) -> Option<&str> {
+        if self.ftl.snapshot.is_empty() {
+            None
+        } else {
+            Some(&self.ftl.snapshot)
+        }
+    }
+
+    /// If FTL should be simulated instead of connecting to it
+    pub fn simulator_enabled(&self) -> bool {
+        self.simulator.enabled
+    }
+
+    /// Get the options used to generate the simulated FTL data
+    pub fn simulator_options(&self) -> SimulatorOptions {
+        SimulatorOptions {
+            seed: self.simulator.seed,
+            clients: self.simulator.clients,
+            domains: self.simulator.domains,
+            upstreams: self.simulator.upstreams,
+            queries_per_minute: self.simulator.queries_per_minute,
+            block_ratio: self.simulator.block_ratio,
+            // Invalid query types are rejected when the config is validated
+            query_types: self.simulator.parse_query_types().unwrap_or_default()
+        }
+    }
+
+    /// If dashboard updates should be pushed to clients over WebSockets
+    pub fn live_enabled(&self) -> bool {
+        self.live.enabled
+    }
+
+    /// The port of the live update WebSocket server
+    pub fn live_port(&self) -> u16 {
+        self.live.port
+    }
+
+    /// How often the live update data is checked for changes
+    pub fn live_update_interval(&self) -> Duration {
+        Duration::from_millis(self.live.update_interval)
+    }
 }
 
 /// Defines the deserialization of the "file_locations" section of the config
@@ -130,7 +296,17 @@ pub struct Files {
     #[serde(default = "default_black_list")]
     black_list: String,
     #[serde(default = "default_black_list_backup")]
-    black_list_backup: String
+    black_list_backup: String,
+    #[serde(default = "default_api_tokens")]
+    api_tokens: String,
+    #[serde(default = "default_api_totp")]
+    api_totp: String,
+    #[serde(default = "default_api_audit_log")]
+    api_audit_log: String,
+    #[serde(default = "default_api_audit_log_rotated")]
+    api_audit_log_rotated: String,
+    #[serde(default = "default_api_webhooks")]
+    api_webhooks: String
 }
 
 impl Default for Files {
@@ -148,7 +324,12 @@ impl Default for Files {
             gravity: default_gravity(),
             gravity_backup: default_gravity_backup(),
             black_list: default_black_list(),
-            black_list_backup: default_black_list_backup()
+            black_list_backup: default_black_list_backup(),
+            api_tokens: default_api_tokens(),
+            api_totp: default_api_totp(),
+            api_audit_log: default_api_audit_log(),
+            api_audit_log_rotated: default_api_audit_log_rotated(),
+            api_webhooks: default_api_webhooks()
         }
     }
 }
@@ -168,7 +349,12 @@ impl Files {
             &self.gravity,
             &self.gravity_backup,
             &self.black_list,
-            &self.black_list_backup
+            &self.black_list_backup,
+            &self.api_tokens,
+            &self.api_totp,
+            &self.api_audit_log,
+            &self.api_audit_log_rotated,
+            &self.api_webhooks
         ]
         .iter()
         .all(|file| Path::new(file).is_absolute())
@@ -197,6 +383,11 @@ default!(default_gravity, Gravity);
 default!(default_gravity_backup, GravityBackup);
 default!(default_black_list, BlackList);
 default!(default_black_list_backup, BlackListBackup);
+default!(default_api_tokens, ApiTokens);
+default!(default_api_totp, ApiTotp);
+default!(default_api_audit_log, ApiAuditLog);
+default!(default_api_audit_log_rotated, ApiAuditLogRotated);
+default!(default_api_webhooks, ApiWebhooks);
 
 /// General config settings
 #[derive(Deserialize, Clone)]
@@ -206,7 +397,11 @@ struct General {
     #[serde(default = "default_port")]
     port: usize,
     #[serde(default = "default_log_level")]
-    log_level: String
+    log_level: String,
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+    #[serde(default)]
+    url_prefix: String
 }
 
 impl Default for General {
@@ -214,7 +409,9 @@ impl Default for General {
         General {
             address: default_address(),
             port: default_port(),
-            log_level: default_log_level()
+            log_level: default_log_level(),
+            trusted_proxies: Vec::new(),
+            url_prefix: String::new()
         }
     }
 }
@@ -227,6 +424,19 @@ impl General {
                 "debug" | "normal" | "critical" => true,
                 _ => false
             }
+            && self.parse_trusted_proxies().is_some()
+            && Regex::new(r"^(/[A-Za-z0-9._~-]+)*$")
+                .unwrap()
+                .is_match(&self.url_prefix)
+    }
+
+    /// Parse the trusted proxy networks. If any of them are invalid, `None` is
+    /// returned.
+    fn parse_trusted_proxies(&self) -> Option<Vec<Cidr>> {
+        self.trusted_proxies
+            .iter()
+            .map(|cidr| Cidr::from_str(cidr).ok())
+            .collect()
     }
 }
 
@@ -242,9 +452,366 @@ fn default_log_level() -> String {
     "critical".to_owned()
 }
 
+/// Authentication config settings
+#[derive(Deserialize, Clone)]
+struct Auth {
+    #[serde(default = "default_session_idle_timeout")]
+    session_idle_timeout: u64,
+    #[serde(default = "default_session_absolute_timeout")]
+    session_absolute_timeout: u64,
+    #[serde(default)]
+    legacy_hash_login: bool
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Auth {
+            session_idle_timeout: default_session_idle_timeout(),
+            session_absolute_timeout: default_session_absolute_timeout(),
+            legacy_hash_login: false
+        }
+    }
+}
+
+impl Auth {
+    fn is_valid(&self) -> bool {
+        self.session_idle_timeout > 0 && self.session_absolute_timeout >= self.session_idle_timeout
+    }
+}
+
+fn default_session_idle_timeout() -> u64 {
+    // 30 minutes
+    30 * 60
+}
+
+fn default_session_absolute_timeout() -> u64 {
+    // 1 day
+    24 * 60 * 60
+}
+
+/// Cross-origin resource sharing (CORS) config settings
+#[derive(Deserialize, Clone, Default)]
+struct Cors {
+    #[serde(default)]
+    allowed_origins: Vec<String>
+}
+
+impl Cors {
+    fn is_valid(&self) -> bool {
+        self.parse_origins().is_some()
+    }
+
+    /// Parse the allowed origins. If any of them are invalid, `None` is
+    /// returned.
+    fn parse_origins(&self) -> Option<AllowedOrigins> {
+        let origins: Vec<&str> = self.allowed_origins.iter().map(String::as_str).collect();
+        let (allowed_origins, failed_origins) = AllowedOrigins::some(&origins);
+
+        if failed_origins.is_empty() {
+            Some(allowed_origins)
+        } else {
+            None
+        }
+    }
+}
+
+/// Source network access config settings. Mutating requests are checked
+/// against the `write` lists, and all other requests against the `read`
+/// lists.
+#[derive(Deserialize, Clone, Default)]
+struct Access {
+    #[serde(default)]
+    read: AccessLists,
+    #[serde(default)]
+    write: AccessLists
+}
+
+impl Access {
+    fn is_valid(&self) -> bool {
+        self.read.parse().is_some() && self.write.parse().is_some()
+    }
+}
+
+/// The allow and deny lists of networks, in CIDR notation
+#[derive(Deserialize, Clone, Default)]
+struct AccessLists {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>
+}
+
+impl AccessLists {
+    /// Parse the networks. If any of them are invalid, `None` is returned.
+    fn parse(&self) -> Option<AccessList> {
+        let parse_all = |cidrs: &[String]| {
+            cidrs
+                .iter()
+                .map(|cidr| Cidr::from_str(cidr).ok())
+                .collect::<Option<Vec<Cidr>>>()
+        };
+
+        Some(AccessList {
+            allow: parse_all(&self.allow)?,
+            deny: parse_all(&self.deny)?
+        })
+    }
+}
+
+/// HTTPS config settings. If no certificate is configured, the API is served
+/// over plain HTTP.
+#[derive(Deserialize, Clone)]
+struct Tls {
+    #[serde(default)]
+    certificate: String,
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    self_signed: bool,
+    #[serde(default = "default_tls_reload_interval")]
+    reload_interval: u64
+}
+
+impl Default for Tls {
+    fn default() -> Self {
+        Tls {
+            certificate: String::new(),
+            key: String::new(),
+            self_signed: false,
+            reload_interval: default_tls_reload_interval()
+        }
+    }
+}
+
+impl Tls {
+    fn is_valid(&self) -> bool {
+        if self.certificate.is_empty() && self.key.is_empty() {
+            // A self-signed certificate needs somewhere to be saved
+            return !self.self_signed;
+        }
+
+        Path::new(&self.certificate).is_absolute()
+            && Path::new(&self.key).is_absolute()
+            && self.certificate != self.key
+            && self.reload_interval > 0
+    }
+}
+
+fn default_tls_reload_interval() -> u64 {
+    60
+}
+
+/// FTL connection config settings. The timeouts are in milliseconds. If a
+/// snapshot file is set, shared memory is loaded from the snapshot instead of
+/// FTL.
+#[derive(Deserialize, Clone)]
+struct Ftl {
+    #[serde(default = "default_ftl_socket")]
+    socket: String,
+    #[serde(default = "default_ftl_connect_timeout")]
+    connect_timeout: u64,
+    #[serde(default = "default_ftl_read_timeout")]
+    read_timeout: u64,
+    #[serde(default = "default_ftl_retries")]
+    retries: u32,
+    #[serde(default = "default_ftl_pool_size")]
+    pool_size: usize,
+    #[serde(default)]
+    snapshot: String
+}
+
+impl Default for Ftl {
+    fn default() -> Self {
+        Ftl {
+            socket: default_ftl_socket(),
+            connect_timeout: default_ftl_connect_timeout(),
+            read_timeout: default_ftl_read_timeout(),
+            retries: default_ftl_retries(),
+            pool_size: default_ftl_pool_size(),
+            snapshot: String::new()
+        }
+    }
+}
+
+impl Ftl {
+    fn is_valid(&self) -> bool {
+        Path::new(&self.socket).is_absolute()
+            && self.connect_timeout > 0
+            && self.read_timeout > 0
+            // Limit the retries so that the exponential backoff stays short
+            && self.retries <= 10
+            && (self.snapshot.is_empty() || Path::new(&self.snapshot).is_absolute())
+    }
+}
+
+fn default_ftl_socket() -> String {
+    SocketOptions::default().path
+}
+
+fn default_ftl_connect_timeout() -> u64 {
+    SocketOptions::default().connect_timeout.as_millis() as u64
+}
+
+fn default_ftl_read_timeout() -> u64 {
+    SocketOptions::default().read_timeout.as_millis() as u64
+}
+
+fn default_ftl_retries() -> u32 {
+    SocketOptions::default().retries
+}
+
+fn default_ftl_pool_size() -> usize {
+    SocketOptions::default().pool_size
+}
+
+/// FTL simulator config settings. The simulator generates clients, domains,
+/// upstreams and queries in place of FTL, for demos and frontend development.
+#[derive(Deserialize, Clone)]
+struct Simulator {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_simulator_seed")]
+    seed: u64,
+    #[serde(default = "default_simulator_clients")]
+    clients: usize,
+    #[serde(default = "default_simulator_domains")]
+    domains: usize,
+    #[serde(default = "default_simulator_upstreams")]
+    upstreams: usize,
+    #[serde(default = "default_simulator_queries_per_minute")]
+    queries_per_minute: u32,
+    #[serde(default = "default_simulator_block_ratio")]
+    block_ratio: f64,
+    #[serde(default = "default_simulator_query_types")]
+    query_types: Vec<String>
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator {
+            enabled: false,
+            seed: default_simulator_seed(),
+            clients: default_simulator_clients(),
+            domains: default_simulator_domains(),
+            upstreams: default_simulator_upstreams(),
+            queries_per_minute: default_simulator_queries_per_minute(),
+            block_ratio: default_simulator_block_ratio(),
+            query_types: default_simulator_query_types()
+        }
+    }
+}
+
+impl Simulator {
+    fn is_valid(&self) -> bool {
+        self.clients > 0
+            && self.clients <= 10_000
+            // At least one allowed and one blocked domain is needed
+            && self.domains >= 2
+            && self.domains <= 100_000
+            && self.upstreams > 0
+            && self.upstreams <= 100
+            && self.queries_per_minute > 0
+            && self.queries_per_minute <= 60_000
+            && self.block_ratio >= 0.0
+            && self.block_ratio <= 1.0
+            && self.parse_query_types().is_some()
+    }
+
+    /// Parse the query type names (ex. `AAAA`). If any of them are invalid or
+    /// there are none, `None` is returned.
+    fn parse_query_types(&self) -> Option<Vec<FtlQueryType>> {
+        if self.query_types.is_empty() {
+            return None;
+        }
+
+        self.query_types
+            .iter()
+            .map(|name| {
+                FtlQueryType::variants()
+                    .iter()
+                    .cloned()
+                    .find(|query_type| query_type.get_name().eq_ignore_ascii_case(name))
+            })
+            .collect()
+    }
+}
+
+fn default_simulator_seed() -> u64 {
+    SimulatorOptions::default().seed
+}
+
+fn default_simulator_clients() -> usize {
+    SimulatorOptions::default().clients
+}
+
+fn default_simulator_domains() -> usize {
+    SimulatorOptions::default().domains
+}
+
+fn default_simulator_upstreams() -> usize {
+    SimulatorOptions::default().upstreams
+}
+
+fn default_simulator_queries_per_minute() -> u32 {
+    SimulatorOptions::default().queries_per_minute
+}
+
+fn default_simulator_block_ratio() -> f64 {
+    SimulatorOptions::default().block_ratio
+}
+
+fn default_simulator_query_types() -> Vec<String> {
+    SimulatorOptions::default()
+        .query_types
+        .into_iter()
+        .map(FtlQueryType::get_name)
+        .collect()
+}
+
+/// Live update config settings. When enabled, a WebSocket server is started
+/// on its own port (using the API's address) which pushes dashboard updates
+/// to subscribed clients. The update interval is in milliseconds. The server
+/// does not use TLS, so live updates can not be enabled together with TLS.
+#[derive(Deserialize, Clone)]
+struct Live {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_live_port")]
+    port: u16,
+    #[serde(default = "default_live_update_interval")]
+    update_interval: u64
+}
+
+impl Default for Live {
+    fn default() -> Self {
+        Live {
+            enabled: false,
+            port: default_live_port(),
+            update_interval: default_live_update_interval()
+        }
+    }
+}
+
+impl Live {
+    fn is_valid(&self) -> bool {
+        self.port > 0 && self.update_interval > 0
+    }
+}
+
+fn default_live_port() -> u16 {
+    8081
+}
+
+fn default_live_update_interval() -> u64 {
+    1000
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Config, Files, General};
+    use super::{
+        Access, AccessLists, Auth, Config, Cors, Files, Ftl, General, Live, Simulator, Tls
+    };
+    use crate::ftl::FtlQueryType;
 
     #[test]
     fn valid_config() {
@@ -299,4 +866,248 @@ mod test {
         };
         assert!(!general.is_valid());
     }
+
+    #[test]
+    fn valid_auth() {
+        let auth = Auth::default();
+        assert!(auth.is_valid());
+    }
+
+    #[test]
+    fn invalid_auth_idle_timeout() {
+        let auth = Auth {
+            session_idle_timeout: 0,
+            ..Auth::default()
+        };
+        assert!(!auth.is_valid());
+    }
+
+    #[test]
+    fn invalid_auth_absolute_timeout() {
+        let auth = Auth {
+            session_idle_timeout: 600,
+            session_absolute_timeout: 60,
+            ..Auth::default()
+        };
+        assert!(!auth.is_valid());
+    }
+
+    #[test]
+    fn valid_cors() {
+        let cors = Cors {
+            allowed_origins: vec![
+                "http://pi.hole".to_owned(),
+                "https://10.0.0.1:8080".to_owned(),
+            ]
+        };
+        assert!(cors.is_valid());
+    }
+
+    #[test]
+    fn invalid_cors_origin() {
+        let cors = Cors {
+            allowed_origins: vec!["not an origin".to_owned()]
+        };
+        assert!(!cors.is_valid());
+    }
+
+    #[test]
+    fn default_cors_origins() {
+        assert!(!Config::default().allowed_origins().is_all());
+    }
+
+    #[test]
+    fn valid_access() {
+        let access = Access {
+            read: AccessLists {
+                allow: vec!["10.0.0.0/8".to_owned(), "fd00::/8".to_owned()],
+                deny: vec!["10.0.50.1".to_owned()]
+            },
+            write: AccessLists {
+                allow: vec!["10.0.10.0/24".to_owned()],
+                deny: Vec::new()
+            }
+        };
+        assert!(access.is_valid());
+    }
+
+    #[test]
+    fn invalid_access_network() {
+        let access = Access {
+            write: AccessLists {
+                allow: vec!["10.0.10.0/33".to_owned()],
+                deny: Vec::new()
+            },
+            ..Access::default()
+        };
+        assert!(!access.is_valid());
+    }
+
+    #[test]
+    fn parse_access() {
+        let config: Config = toml::from_str(
+            "[access.read]\nallow = [\"10.0.0.0/8\"]\n[access.write]\ndeny = [\"0.0.0.0/0\"]"
+        )
+        .unwrap();
+
+        assert!(config.is_valid());
+        assert_eq!(config.read_access().allow.len(), 1);
+        assert!(config.read_access().deny.is_empty());
+        assert!(config.write_access().allow.is_empty());
+        assert_eq!(config.write_access().deny.len(), 1);
+    }
+
+    #[test]
+    fn valid_tls() {
+        let tls = Tls {
+            certificate: "/etc/pihole/API_cert.pem".to_owned(),
+            key: "/etc/pihole/API_key.pem".to_owned(),
+            self_signed: true,
+            ..Tls::default()
+        };
+        assert!(Tls::default().is_valid());
+        assert!(tls.is_valid());
+    }
+
+    #[test]
+    fn invalid_tls_missing_key() {
+        let tls = Tls {
+            certificate: "/etc/pihole/API_cert.pem".to_owned(),
+            ..Tls::default()
+        };
+        assert!(!tls.is_valid());
+    }
+
+    #[test]
+    fn invalid_tls_self_signed_without_files() {
+        let tls = Tls {
+            self_signed: true,
+            ..Tls::default()
+        };
+        assert!(!tls.is_valid());
+    }
+
+    #[test]
+    fn valid_general_proxy() {
+        let general = General {
+            trusted_proxies: vec!["127.0.0.1".to_owned(), "10.0.0.0/24".to_owned()],
+            url_prefix: "/pihole".to_owned(),
+            ..General::default()
+        };
+        assert!(general.is_valid());
+    }
+
+    #[test]
+    fn invalid_general_trusted_proxy() {
+        let general = General {
+            trusted_proxies: vec!["localhost".to_owned()],
+            ..General::default()
+        };
+        assert!(!general.is_valid());
+    }
+
+    #[test]
+    fn invalid_general_url_prefix() {
+        for prefix in &["pihole", "/pihole/", "/pi hole", "/"] {
+            let general = General {
+                url_prefix: (*prefix).to_owned(),
+                ..General::default()
+            };
+            assert!(!general.is_valid());
+        }
+    }
+
+    #[test]
+    fn valid_ftl() {
+        let ftl = Ftl::default();
+        assert!(ftl.is_valid());
+    }
+
+    #[test]
+    fn invalid_ftl_socket() {
+        let ftl = Ftl {
+            socket: "FTL.sock".to_owned(),
+            ..Ftl::default()
+        };
+        assert!(!ftl.is_valid());
+    }
+
+    #[test]
+    fn invalid_ftl_timeout() {
+        let ftl = Ftl {
+            read_timeout: 0,
+            ..Ftl::default()
+        };
+        assert!(!ftl.is_valid());
+    }
+
+    #[test]
+    fn invalid_live() {
+        let live = Live {
+            update_interval: 0,
+            ..Live::default()
+        };
+        assert!(Live::default().is_valid());
+        assert!(!live.is_valid());
+    }
+
+    #[test]
+    fn invalid_live_port() {
+        let config: Config =
+            toml::from_str("[general]\nport = 8081\n[live]\nenabled = true\nport = 8081").unwrap();
+        assert!(!config.is_valid());
+    }
+
+    #[test]
+    fn invalid_live_with_tls() {
+        let config: Config = toml::from_str(
+            "[tls]\ncertificate = \"/etc/pihole/cert.pem\"\nkey = \"/etc/pihole/key.pem\"\n\
+             [live]\nenabled = true"
+        )
+        .unwrap();
+        assert!(!config.is_valid());
+    }
+
+    #[test]
+    fn invalid_ftl_snapshot() {
+        let ftl = Ftl {
+            snapshot: "snapshot.json".to_owned(),
+            ..Ftl::default()
+        };
+        assert!(!ftl.is_valid());
+    }
+
+    #[test]
+    fn invalid_simulator() {
+        let simulator = Simulator {
+            block_ratio: 1.5,
+            ..Simulator::default()
+        };
+        assert!(!simulator.is_valid());
+
+        let simulator = Simulator {
+            query_types: vec!["MX".to_owned()],
+            ..Simulator::default()
+        };
+        assert!(!simulator.is_valid());
+    }
+
+    #[test]
+    fn simulator_options() {
+        let config: Config = toml::from_str(
+            "[simulator]
+seed = 42
+query_types = [\"a\", \"TXT\"]"
+        )
+        .unwrap();
+
+        assert!(config.is_valid());
+
+        let options = config.simulator_options();
+        assert_eq!(options.seed, 42);
+        assert_eq!(
+            options.query_types,
+            vec![FtlQueryType::A, FtlQueryType::TXT]
+        );
+    }
 }
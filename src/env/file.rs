@@ -23,7 +23,12 @@ pub enum PiholeFile {
     Gravity,
     GravityBackup,
     BlackList,
-    BlackListBackup
+    BlackListBackup,
+    ApiTokens,
+    ApiTotp,
+    ApiAuditLog,
+    ApiAuditLogRotated,
+    ApiWebhooks
 }
 
 impl PiholeFile {
@@ -42,7 +47,12 @@ impl PiholeFile {
             PiholeFile::Gravity => "/etc/pihole/gravity.list",
             PiholeFile::GravityBackup => "/etc/pihole/gravity.list.bck",
             PiholeFile::BlackList => "/etc/pihole/black.list",
-            PiholeFile::BlackListBackup => "/etc/pihole/black.list.bck"
+            PiholeFile::BlackListBackup => "/etc/pihole/black.list.bck",
+            PiholeFile::ApiTokens => "/etc/pihole/API_tokens.json",
+            PiholeFile::ApiTotp => "/etc/pihole/API_totp.json",
+            PiholeFile::ApiAuditLog => "/etc/pihole/API_audit.log",
+            PiholeFile::ApiAuditLogRotated => "/etc/pihole/API_audit.log.1",
+            PiholeFile::ApiWebhooks => "/etc/pihole/API_webhooks.json"
         }
     }
 }
@@ -0,0 +1,603 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Socket Response Decoding
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{ftl::FtlConnection, util::Error};
+use base64;
+use rmp::{
+    decode::{self, MarkerReadError},
+    Marker
+};
+use serde_json::Value;
+use std::{
+    cmp,
+    collections::HashMap,
+    hash::Hash,
+    io::{self, Read}
+};
+
+/// The most elements to allocate space for before they are read. Lengths are
+/// sent by FTL, so they are not trusted until the data has arrived.
+const MAX_PREALLOCATE: usize = 1024;
+
+/// A MessagePack value sent by FTL
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum FtlValue {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Binary(Vec<u8>),
+    Array(Vec<FtlValue>),
+    Map(Vec<(FtlValue, FtlValue)>)
+}
+
+impl FtlValue {
+    /// Get the value as a signed integer, if it is an integer in range
+    fn as_i64(&self) -> Option<i64> {
+        match *self {
+            FtlValue::Int(value) => Some(value),
+            FtlValue::UInt(value) if value <= i64::max_value() as u64 => Some(value as i64),
+            _ => None
+        }
+    }
+
+    /// Get the value as an unsigned integer, if it is an integer in range
+    fn as_u64(&self) -> Option<u64> {
+        match *self {
+            FtlValue::UInt(value) => Some(value),
+            FtlValue::Int(value) if value >= 0 => Some(value as u64),
+            _ => None
+        }
+    }
+
+    /// Convert the value to JSON. Binary data is base64 encoded, and map keys
+    /// which are not strings are converted to strings.
+    pub fn into_json(self) -> Value {
+        match self {
+            FtlValue::Nil => Value::Null,
+            FtlValue::Bool(value) => Value::Bool(value),
+            FtlValue::Int(value) => Value::from(value),
+            FtlValue::UInt(value) => Value::from(value),
+            FtlValue::Float(value) => Value::from(value),
+            FtlValue::Str(value) => Value::String(value),
+            FtlValue::Binary(value) => Value::String(base64::encode(&value)),
+            FtlValue::Array(values) => {
+                Value::Array(values.into_iter().map(FtlValue::into_json).collect())
+            }
+            FtlValue::Map(pairs) => Value::Object(
+                pairs
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            FtlValue::Str(key) => key,
+                            key => key.into_json().to_string()
+                        };
+
+                        (key, value.into_json())
+                    })
+                    .collect()
+            )
+        }
+    }
+}
+
+/// The reasons a value could not be decoded
+pub enum DecodeError {
+    /// The end of message (EOM) marker was read instead of a value
+    Eom,
+    /// The data could not be read
+    Io(io::Error),
+    /// The data is not valid MessagePack, or uses an unsupported type
+    Invalid
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(error: io::Error) -> Self {
+        DecodeError::Io(error)
+    }
+}
+
+/// Read the next value. FTL's end of message marker (`0xc1`, reserved in
+/// MessagePack) results in `DecodeError::Eom`, even inside an array or map.
+pub fn read_value<R: Read>(reader: &mut R) -> Result<FtlValue, DecodeError> {
+    let marker = decode::read_marker(reader).map_err(|MarkerReadError(e)| DecodeError::Io(e))?;
+
+    Ok(match marker {
+        Marker::Reserved => return Err(DecodeError::Eom),
+        Marker::Null => FtlValue::Nil,
+        Marker::True => FtlValue::Bool(true),
+        Marker::False => FtlValue::Bool(false),
+        Marker::FixPos(value) => FtlValue::UInt(value as u64),
+        Marker::U8 => FtlValue::UInt(read_bytes::<_, [u8; 1]>(reader)?[0] as u64),
+        Marker::U16 => FtlValue::UInt(u16::from_be_bytes(read_bytes(reader)?) as u64),
+        Marker::U32 => FtlValue::UInt(u32::from_be_bytes(read_bytes(reader)?) as u64),
+        Marker::U64 => FtlValue::UInt(u64::from_be_bytes(read_bytes(reader)?)),
+        Marker::FixNeg(value) => FtlValue::Int(value as i64),
+        Marker::I8 => FtlValue::Int(read_bytes::<_, [u8; 1]>(reader)?[0] as i8 as i64),
+        Marker::I16 => FtlValue::Int(i16::from_be_bytes(read_bytes(reader)?) as i64),
+        Marker::I32 => FtlValue::Int(i32::from_be_bytes(read_bytes(reader)?) as i64),
+        Marker::I64 => FtlValue::Int(i64::from_be_bytes(read_bytes(reader)?)),
+        Marker::F32 => {
+            FtlValue::Float(f32::from_bits(u32::from_be_bytes(read_bytes(reader)?)) as f64)
+        }
+        Marker::F64 => FtlValue::Float(f64::from_bits(u64::from_be_bytes(read_bytes(reader)?))),
+        Marker::FixStr(len) => read_str(reader, len as usize)?,
+        Marker::Str8 => read_str(reader, read_len8(reader)?)?,
+        Marker::Str16 => read_str(reader, read_len16(reader)?)?,
+        Marker::Str32 => read_str(reader, read_len32(reader)?)?,
+        Marker::Bin8 => FtlValue::Binary(read_data(reader, read_len8(reader)?)?),
+        Marker::Bin16 => FtlValue::Binary(read_data(reader, read_len16(reader)?)?),
+        Marker::Bin32 => FtlValue::Binary(read_data(reader, read_len32(reader)?)?),
+        Marker::FixArray(len) => read_array(reader, len as usize)?,
+        Marker::Array16 => read_array(reader, read_len16(reader)?)?,
+        Marker::Array32 => read_array(reader, read_len32(reader)?)?,
+        Marker::FixMap(len) => read_map(reader, len as usize)?,
+        Marker::Map16 => read_map(reader, read_len16(reader)?)?,
+        Marker::Map32 => read_map(reader, read_len32(reader)?)?,
+        // FTL does not send extension types
+        _ => return Err(DecodeError::Invalid)
+    })
+}
+
+/// Read a fixed number of bytes, such as the data of a number
+fn read_bytes<R: Read, B: AsMut<[u8]> + Default>(reader: &mut R) -> Result<B, DecodeError> {
+    let mut bytes = B::default();
+    reader.read_exact(bytes.as_mut())?;
+    Ok(bytes)
+}
+
+/// Read a one byte length
+fn read_len8<R: Read>(reader: &mut R) -> Result<usize, DecodeError> {
+    Ok(read_bytes::<_, [u8; 1]>(reader)?[0] as usize)
+}
+
+/// Read a two byte length
+fn read_len16<R: Read>(reader: &mut R) -> Result<usize, DecodeError> {
+    Ok(u16::from_be_bytes(read_bytes(reader)?) as usize)
+}
+
+/// Read a four byte length
+fn read_len32<R: Read>(reader: &mut R) -> Result<usize, DecodeError> {
+    Ok(u32::from_be_bytes(read_bytes(reader)?) as usize)
+}
+
+/// Read `len` bytes of data
+fn read_data<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut data = Vec::with_capacity(cmp::min(len, MAX_PREALLOCATE));
+    reader.take(len as u64).read_to_end(&mut data)?;
+
+    if data.len() != len {
+        return Err(DecodeError::Io(io::Error::from(
+            io::ErrorKind::UnexpectedEof
+        )));
+    }
+
+    Ok(data)
+}
+
+/// Read a UTF-8 string of `len` bytes
+fn read_str<R: Read>(reader: &mut R, len: usize) -> Result<FtlValue, DecodeError> {
+    String::from_utf8(read_data(reader, len)?)
+        .map(FtlValue::Str)
+        .map_err(|_| DecodeError::Invalid)
+}
+
+/// Read an array of `len` values
+fn read_array<R: Read>(reader: &mut R, len: usize) -> Result<FtlValue, DecodeError> {
+    let mut values = Vec::with_capacity(cmp::min(len, MAX_PREALLOCATE));
+
+    for _ in 0..len {
+        values.push(read_value(reader)?);
+    }
+
+    Ok(FtlValue::Array(values))
+}
+
+/// Read a map of `len` key-value pairs
+fn read_map<R: Read>(reader: &mut R, len: usize) -> Result<FtlValue, DecodeError> {
+    let mut pairs = Vec::with_capacity(cmp::min(len, MAX_PREALLOCATE));
+
+    for _ in 0..len {
+        let key = read_value(reader)?;
+        let value = read_value(reader)?;
+        pairs.push((key, value));
+    }
+
+    Ok(FtlValue::Map(pairs))
+}
+
+/// A type which can be converted from a value sent by FTL
+pub trait FtlDecode: Sized {
+    /// Convert the value, or return `None` if it is the wrong type
+    fn from_value(value: FtlValue) -> Option<Self>;
+}
+
+/// A response made of a fixed sequence of values. Use `ftl_response!` to
+/// declare the layout of a response.
+pub trait FtlResponse: Sized {
+    /// Read the values of the response, not including the EOM
+    fn read_values(con: &mut FtlConnection) -> Result<Self, Error>;
+}
+
+/// Binary data sent by FTL. This is separate from `Vec<u8>`, which is
+/// decoded from an array of integers.
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct FtlBinary(pub Vec<u8>);
+
+impl FtlDecode for FtlValue {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl FtlDecode for bool {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        match value {
+            FtlValue::Bool(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+impl FtlDecode for i64 {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        value.as_i64()
+    }
+}
+
+impl FtlDecode for u64 {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        value.as_u64()
+    }
+}
+
+/// Implement `FtlDecode` for integers smaller than 64 bits, checking that the
+/// value is in range
+macro_rules! decode_int {
+    ($($signed:ty),* ; $($unsigned:ty),*) => {
+        $(
+            impl FtlDecode for $signed {
+                fn from_value(value: FtlValue) -> Option<Self> {
+                    value
+                        .as_i64()
+                        .filter(|&value| {
+                            value >= <$signed>::min_value() as i64
+                                && value <= <$signed>::max_value() as i64
+                        })
+                        .map(|value| value as $signed)
+                }
+            }
+        )*
+        $(
+            impl FtlDecode for $unsigned {
+                fn from_value(value: FtlValue) -> Option<Self> {
+                    value
+                        .as_u64()
+                        .filter(|&value| value <= <$unsigned>::max_value() as u64)
+                        .map(|value| value as $unsigned)
+                }
+            }
+        )*
+    };
+}
+
+decode_int!(i8, i16, i32, isize; u8, u16, u32, usize);
+
+impl FtlDecode for f64 {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        match value {
+            FtlValue::Float(value) => Some(value),
+            FtlValue::Int(value) => Some(value as f64),
+            FtlValue::UInt(value) => Some(value as f64),
+            _ => None
+        }
+    }
+}
+
+impl FtlDecode for f32 {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        f64::from_value(value).map(|value| value as f32)
+    }
+}
+
+impl FtlDecode for String {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        match value {
+            FtlValue::Str(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+impl FtlDecode for FtlBinary {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        match value {
+            FtlValue::Binary(value) => Some(FtlBinary(value)),
+            _ => None
+        }
+    }
+}
+
+/// `nil` is decoded as `None`
+impl<T: FtlDecode> FtlDecode for Option<T> {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        match value {
+            FtlValue::Nil => Some(None),
+            value => T::from_value(value).map(Some)
+        }
+    }
+}
+
+impl<T: FtlDecode> FtlDecode for Vec<T> {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        match value {
+            FtlValue::Array(values) => values.into_iter().map(T::from_value).collect(),
+            _ => None
+        }
+    }
+}
+
+impl<K: FtlDecode + Eq + Hash, V: FtlDecode> FtlDecode for HashMap<K, V> {
+    fn from_value(value: FtlValue) -> Option<Self> {
+        match value {
+            FtlValue::Map(pairs) => pairs
+                .into_iter()
+                .map(|(key, value)| Some((K::from_value(key)?, V::from_value(value)?)))
+                .collect(),
+            _ => None
+        }
+    }
+}
+
+/// Declare a struct whose fields are sent by FTL in order, and implement
+/// [`FtlResponse`] for it. Attributes are passed through, so the struct can
+/// also derive `Serialize` to be used in a reply.
+///
+/// ```ignore
+/// ftl_response! {
+///     #[derive(Serialize)]
+///     struct DatabaseStats {
+///         queries: i32,
+///         filesize: i64,
+///         sqlite_version: String
+///     }
+/// }
+///
+/// let stats: DatabaseStats = ftl.connect("dbstats")?.read_response()?;
+/// ```
+///
+/// [`FtlResponse`]: trait.FtlResponse.html
+macro_rules! ftl_response {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $field_type:ty),*
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($(#[$field_attr])* $field_vis $field: $field_type),*
+        }
+
+        impl $crate::ftl::FtlResponse for $name {
+            fn read_values(
+                con: &mut $crate::ftl::FtlConnection
+            ) -> Result<Self, $crate::util::Error> {
+                // Struct fields are evaluated in the order they are written
+                Ok($name {
+                    $($field: con.read()?),*
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_value, DecodeError, FtlBinary, FtlDecode, FtlValue};
+    use crate::{ftl::FtlConnectionType, testing::write_eom, util::ErrorKind};
+    use rmp::encode;
+    use std::{collections::HashMap, io::Cursor};
+
+    /// Decode a single value from the data
+    fn decode(data: Vec<u8>) -> FtlValue {
+        match read_value(&mut Cursor::new(data)) {
+            Ok(value) => value,
+            Err(_) => panic!("Failed to decode the value")
+        }
+    }
+
+    /// Create a test connection which responds to `test` with the data
+    fn test_connection(data: Vec<u8>) -> FtlConnectionType {
+        let mut map = HashMap::new();
+        map.insert("test".to_owned(), data);
+        FtlConnectionType::Test(map)
+    }
+
+    /// Scalar types are decoded
+    #[test]
+    fn scalars() {
+        let mut data = Vec::new();
+        encode::write_nil(&mut data).unwrap();
+        assert_eq!(decode(data), FtlValue::Nil);
+
+        let mut data = Vec::new();
+        encode::write_bool(&mut data, true).unwrap();
+        assert_eq!(decode(data), FtlValue::Bool(true));
+
+        let mut data = Vec::new();
+        encode::write_u64(&mut data, u64::max_value()).unwrap();
+        assert_eq!(decode(data), FtlValue::UInt(u64::max_value()));
+
+        let mut data = Vec::new();
+        encode::write_i16(&mut data, -300).unwrap();
+        assert_eq!(decode(data), FtlValue::Int(-300));
+
+        let mut data = Vec::new();
+        encode::write_f32(&mut data, 1.5).unwrap();
+        assert_eq!(decode(data), FtlValue::Float(1.5));
+
+        let mut data = Vec::new();
+        encode::write_f64(&mut data, -0.25).unwrap();
+        assert_eq!(decode(data), FtlValue::Float(-0.25));
+
+        let mut data = Vec::new();
+        encode::write_bin(&mut data, &[1, 2, 3]).unwrap();
+        assert_eq!(decode(data), FtlValue::Binary(vec![1, 2, 3]));
+    }
+
+    /// Arrays and maps are decoded with their contents
+    #[test]
+    fn collections() {
+        let mut data = Vec::new();
+        encode::write_array_len(&mut data, 2).unwrap();
+        encode::write_str(&mut data, "a").unwrap();
+        encode::write_map_len(&mut data, 1).unwrap();
+        encode::write_uint(&mut data, 1).unwrap();
+        encode::write_nil(&mut data).unwrap();
+
+        assert_eq!(
+            decode(data),
+            FtlValue::Array(vec![
+                FtlValue::Str("a".to_owned()),
+                FtlValue::Map(vec![(FtlValue::UInt(1), FtlValue::Nil)])
+            ])
+        );
+    }
+
+    /// The EOM marker is reported, even inside of an array
+    #[test]
+    fn eom() {
+        let mut data = Vec::new();
+        encode::write_array_len(&mut data, 2).unwrap();
+        encode::write_i32(&mut data, 1).unwrap();
+        write_eom(&mut data);
+
+        match read_value(&mut Cursor::new(data)) {
+            Err(DecodeError::Eom) => (),
+            _ => panic!("Expected an EOM")
+        }
+    }
+
+    /// Truncated data is an error, and does not allocate the claimed length
+    #[test]
+    fn truncated() {
+        let mut data = Vec::new();
+        encode::write_str_len(&mut data, u32::max_value()).unwrap();
+        data.extend_from_slice(b"abc");
+
+        match read_value(&mut Cursor::new(data)) {
+            Err(DecodeError::Io(_)) => (),
+            _ => panic!("Expected an IO error")
+        }
+    }
+
+    /// Values are converted to the requested type when in range
+    #[test]
+    fn conversion() {
+        assert_eq!(u8::from_value(FtlValue::Int(200)), Some(200));
+        assert_eq!(u8::from_value(FtlValue::UInt(256)), None);
+        assert_eq!(i32::from_value(FtlValue::Int(-5)), Some(-5));
+        assert_eq!(u32::from_value(FtlValue::Int(-5)), None);
+        assert_eq!(i64::from_value(FtlValue::UInt(u64::max_value())), None);
+        assert_eq!(f64::from_value(FtlValue::UInt(3)), Some(3.0));
+        assert_eq!(String::from_value(FtlValue::Int(1)), None);
+        assert_eq!(Option::<bool>::from_value(FtlValue::Nil), Some(None));
+        assert_eq!(
+            Vec::<u16>::from_value(FtlValue::Array(vec![FtlValue::UInt(1), FtlValue::UInt(2)])),
+            Some(vec![1, 2])
+        );
+        assert_eq!(
+            FtlBinary::from_value(FtlValue::Binary(vec![0xc1])),
+            Some(FtlBinary(vec![0xc1]))
+        );
+    }
+
+    ftl_response! {
+        #[derive(Debug, PartialEq)]
+        struct TestResponse {
+            count: u32,
+            name: String,
+            enabled: bool,
+            upstreams: HashMap<String, f64>
+        }
+    }
+
+    /// Responses are read in the declared order, followed by an EOM
+    #[test]
+    fn response() {
+        let mut data = Vec::new();
+        encode::write_u32(&mut data, 7).unwrap();
+        encode::write_str(&mut data, "test").unwrap();
+        encode::write_bool(&mut data, false).unwrap();
+        encode::write_map_len(&mut data, 1).unwrap();
+        encode::write_str(&mut data, "8.8.8.8").unwrap();
+        encode::write_f32(&mut data, 0.5).unwrap();
+        write_eom(&mut data);
+
+        let ftl = test_connection(data);
+        let mut upstreams = HashMap::new();
+        upstreams.insert("8.8.8.8".to_owned(), 0.5);
+
+        assert_eq!(
+            ftl.connect("test")
+                .unwrap()
+                .read_response::<TestResponse>()
+                .unwrap(),
+            TestResponse {
+                count: 7,
+                name: "test".to_owned(),
+                enabled: false,
+                upstreams
+            }
+        );
+    }
+
+    /// A value of the wrong type is a read error
+    #[test]
+    fn wrong_type() {
+        let mut data = Vec::new();
+        encode::write_str(&mut data, "7").unwrap();
+        write_eom(&mut data);
+
+        let ftl = test_connection(data);
+
+        assert_eq!(
+            ftl.connect("test")
+                .unwrap()
+                .read::<u32>()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::FtlReadError
+        );
+    }
+
+    /// An early EOM is reported as an EOM error
+    #[test]
+    fn early_eom() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        let ftl = test_connection(data);
+
+        assert_eq!(
+            ftl.connect("test")
+                .unwrap()
+                .read::<u32>()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::FtlEomError
+        );
+    }
+}
@@ -12,10 +12,14 @@ use crate::{ftl::memory_model::FtlLock, util::Error};
 use libc::{self, pthread_mutex_lock, pthread_mutex_unlock};
 use shmem::{Map, Object};
 use std::{
+    cmp,
     collections::VecDeque,
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex
+    },
     thread,
-    time::Duration
+    time::{Duration, Instant}
 };
 
 /// The filename of the shared memory, used to connect to the shared memory
@@ -33,6 +37,48 @@ fn open_shm_lock() -> Result<Map<FtlLock>, Error> {
     Ok(Map::new(Object::open(FTL_SHM_LOCK)?)?)
 }
 
+/// Timing statistics for one part of the shared memory lock's use
+#[derive(Copy, Clone, Default)]
+pub struct LockTiming {
+    /// How many times were measured
+    pub count: u64,
+    /// The total of all the times
+    pub total: Duration,
+    /// The longest time
+    pub max: Duration,
+    /// The most recent time
+    pub last: Duration
+}
+
+impl LockTiming {
+    /// Add a time to the statistics
+    pub fn record(&mut self, time: Duration) {
+        self.count += 1;
+        self.total += time;
+        self.max = cmp::max(self.max, time);
+        self.last = time;
+    }
+
+    /// Get the average time, or zero if nothing was measured
+    pub fn average(&self) -> Duration {
+        if self.count == 0 {
+            Duration::from_secs(0)
+        } else {
+            self.total / cmp::min(self.count, u32::max_value() as u64) as u32
+        }
+    }
+}
+
+/// Statistics about the shared memory lock
+#[derive(Copy, Clone, Default)]
+pub struct LockStats {
+    /// How long lock requests waited before they got a read lock. This
+    /// includes waiting for FTL to take and release the lock.
+    pub wait: LockTiming,
+    /// How long the shared memory lock was held each time it was taken
+    pub hold: LockTiming
+}
+
 /// The type of action that the lock thread is requested to perform.
 #[derive(Debug, PartialEq)]
 pub enum RequestType {
@@ -44,7 +90,10 @@ pub enum RequestType {
 /// of open read locks.
 pub struct LockThread {
     pub(self) lock_count: usize,
-    pub(self) wait_queue: VecDeque<Sender<LockResponse>>
+    pub(self) wait_queue: VecDeque<Sender<LockResponse>>,
+    /// When the shared memory lock was taken, if it is held
+    locked_at: Option<Instant>,
+    stats: Arc<Mutex<LockStats>>
 }
 
 impl LockThread {
@@ -53,9 +102,17 @@ impl LockThread {
     ///
     /// [`handle_requests`]: #method.handle_requests
     pub fn new() -> LockThread {
+        Self::with_stats(Arc::default())
+    }
+
+    /// Create a LockThread which records how long the shared memory lock is
+    /// held in `stats`
+    pub fn with_stats(stats: Arc<Mutex<LockStats>>) -> LockThread {
         LockThread {
             lock_count: 0,
-            wait_queue: VecDeque::new()
+            wait_queue: VecDeque::new(),
+            locked_at: None,
+            stats
         }
     }
 
@@ -103,6 +160,8 @@ impl LockThread {
                 sender.send(Ok(ret)).unwrap();
                 return;
             }
+
+            self.locked_at = Some(Instant::now());
         }
 
         self.lock_count += 1;
@@ -134,6 +193,10 @@ impl LockThread {
             sender.send(Ok(0)).unwrap();
         }
 
+        if let Some(locked_at) = self.locked_at.take() {
+            self.stats.lock().unwrap().hold.record(locked_at.elapsed());
+        }
+
         // If FTL is waiting for the lock, let it get the lock before going
         // through the queued lock requests.
         LockThread::wait_for_ftl(shm_lock);
@@ -413,6 +476,30 @@ mod test {
         destroy_lock(ftl_lock.lock);
     }
 
+    /// The time the shared memory lock was held is recorded when it is
+    /// unlocked
+    #[test]
+    fn hold_time() {
+        let mut lock_thread = LockThread::new();
+        let mut ftl_lock = FtlLock {
+            lock: PTHREAD_MUTEX_INITIALIZER,
+            ftl_waiting_for_lock: false
+        };
+        let (sender, receiver) = channel();
+
+        lock_thread.lock(&mut ftl_lock, sender.clone());
+        assert_eq!(lock_thread.stats.lock().unwrap().hold.count, 0);
+
+        lock_thread.unlock(&mut ftl_lock, sender);
+        assert_eq!(lock_thread.stats.lock().unwrap().hold.count, 1);
+
+        // Both requests succeeded
+        assert_eq!(receiver.try_recv().unwrap().unwrap(), 0);
+        assert_eq!(receiver.try_recv().unwrap().unwrap(), 0);
+
+        destroy_lock(ftl_lock.lock);
+    }
+
     /// Handle queued lock requests after unlocking the shared memory lock.
     #[test]
     fn unlock_with_queued_requests() {
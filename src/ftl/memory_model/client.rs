@@ -8,12 +8,12 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::ftl::memory_model::{over_time::OVERTIME_SLOTS, strings::FtlStrings};
+use crate::ftl::memory_model::{
+    is_bool, over_time::OVERTIME_SLOTS, records::FtlRecord, strings::FtlStrings, MAGIC_BYTE
+};
 use libc;
 use std::hash::{Hash, Hasher};
 
-#[cfg(test)]
-use crate::ftl::memory_model::MAGIC_BYTE;
 #[cfg(test)]
 use std::fmt::{
     self, {Debug, Formatter}
@@ -36,7 +36,7 @@ pub struct ClientReply {
 /// generics feature is required. That feature is still WIP:
 /// https://github.com/rust-lang/rfcs/blob/master/text/2000-const-generics.md
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct FtlClient {
     magic: libc::c_uchar,
     pub query_count: libc::c_int,
@@ -44,13 +44,89 @@ pub struct FtlClient {
     ip_str_id: libc::c_ulonglong,
     name_str_id: libc::c_ulonglong,
     is_name_unknown: bool,
+    #[serde(with = "crate::ftl::memory_model::over_time::over_time_slots")]
     pub over_time: [libc::c_int; OVERTIME_SLOTS],
-    last_query_time: libc::time_t,
-    arp_query_count: libc::c_uint
+    pub last_query_time: libc::time_t,
+    pub arp_query_count: libc::c_uint
+}
+
+/// The client struct stored in version 3 of shared memory, which does not
+/// have the last query time or ARP query count. This is the `clientsData`
+/// struct in FTL's `datastructure.h` from the releases which set
+/// `SHARED_MEMORY_VERSION` to 3.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct FtlClientV3 {
+    magic: libc::c_uchar,
+    query_count: libc::c_int,
+    blocked_count: libc::c_int,
+    ip_str_id: libc::c_ulonglong,
+    name_str_id: libc::c_ulonglong,
+    is_name_unknown: bool,
+    over_time: [libc::c_int; OVERTIME_SLOTS]
+}
+
+/// A valid client, used to find the offsets of the fields of a client in
+/// shared memory
+static CLIENT_LAYOUT: FtlClient = FtlClient {
+    magic: MAGIC_BYTE,
+    query_count: 0,
+    blocked_count: 0,
+    ip_str_id: 0,
+    name_str_id: 0,
+    is_name_unknown: false,
+    over_time: [0; OVERTIME_SLOTS],
+    last_query_time: 0,
+    arp_query_count: 0
+};
+
+impl FtlRecord for FtlClient {
+    unsafe fn is_intact(record: *const u8) -> bool {
+        let layout = &CLIENT_LAYOUT;
+
+        is_bool(record, layout, &layout.is_name_unknown)
+    }
+}
+
+/// A valid version 3 client, used to find the offsets of its fields in shared
+/// memory
+static CLIENT_V3_LAYOUT: FtlClientV3 = FtlClientV3 {
+    magic: MAGIC_BYTE,
+    query_count: 0,
+    blocked_count: 0,
+    ip_str_id: 0,
+    name_str_id: 0,
+    is_name_unknown: false,
+    over_time: [0; OVERTIME_SLOTS]
+};
+
+impl FtlRecord for FtlClientV3 {
+    unsafe fn is_intact(record: *const u8) -> bool {
+        let layout = &CLIENT_V3_LAYOUT;
+
+        is_bool(record, layout, &layout.is_name_unknown)
+    }
+}
+
+impl From<FtlClientV3> for FtlClient {
+    /// Convert the client to the current layout. The missing fields are set
+    /// to zero.
+    fn from(client: FtlClientV3) -> Self {
+        FtlClient {
+            magic: client.magic,
+            query_count: client.query_count,
+            blocked_count: client.blocked_count,
+            ip_str_id: client.ip_str_id,
+            name_str_id: client.name_str_id,
+            is_name_unknown: client.is_name_unknown,
+            over_time: client.over_time,
+            last_query_time: 0,
+            arp_query_count: 0
+        }
+    }
 }
 
 impl FtlClient {
-    #[cfg(test)]
     pub fn new(
         query_count: usize,
         blocked_count: usize,
@@ -84,6 +160,14 @@ impl FtlClient {
         self
     }
 
+    /// Check that the record has the magic byte, and its strings start inside
+    /// the first `strings_len` bytes of string memory
+    pub fn is_valid(&self, strings_len: usize) -> bool {
+        self.magic == MAGIC_BYTE
+            && (self.ip_str_id as usize) < strings_len
+            && (self.name_str_id as usize) < strings_len
+    }
+
     /// Get the IP address of the client
     pub fn get_ip<'a>(&self, strings: &'a FtlStrings) -> &'a str {
         strings.get_str(self.ip_str_id as usize).unwrap_or_default()
@@ -175,3 +259,18 @@ impl Debug for FtlClient {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{FtlClient, FtlClientV3};
+    use std::mem;
+
+    /// The client structs have the same size as in FTL, so the records in
+    /// shared memory line up
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn layout_size() {
+        assert_eq!(mem::size_of::<FtlClient>(), 656);
+        assert_eq!(mem::size_of::<FtlClientV3>(), 640);
+    }
+}
@@ -14,7 +14,7 @@ use rocket::{http::RawStr, request::FromFormValue};
 /// The FTL counters stored in shared memory
 #[repr(C)]
 #[cfg_attr(test, derive(Default))]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct FtlCounters {
     pub total_queries: libc::c_int,
     pub blocked_queries: libc::c_int,
@@ -39,6 +39,60 @@ pub struct FtlCounters {
     pub reply_count_domain: libc::c_int
 }
 
+/// The FTL counters stored in version 3 of shared memory, which does not
+/// have the reply counters. This is the `countersStruct` struct in FTL's
+/// `datastructure.h` from the releases which set `SHARED_MEMORY_VERSION` to 3.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct FtlCountersV3 {
+    pub total_queries: libc::c_int,
+    pub blocked_queries: libc::c_int,
+    pub cached_queries: libc::c_int,
+    pub unknown_queries: libc::c_int,
+    pub total_upstreams: libc::c_int,
+    pub total_clients: libc::c_int,
+    pub total_domains: libc::c_int,
+    pub query_capacity: libc::c_int,
+    pub upstream_capacity: libc::c_int,
+    pub client_capacity: libc::c_int,
+    pub domain_capacity: libc::c_int,
+    pub string_capacity: libc::c_int,
+    pub gravity_size: libc::c_int,
+    pub gravity_conf: libc::c_int,
+    pub query_type_counters: [libc::c_int; 7],
+    pub forwarded_queries: libc::c_int
+}
+
+impl From<FtlCountersV3> for FtlCounters {
+    /// Convert the counters to the current layout. The reply counters are set
+    /// to zero.
+    fn from(counters: FtlCountersV3) -> Self {
+        FtlCounters {
+            total_queries: counters.total_queries,
+            blocked_queries: counters.blocked_queries,
+            cached_queries: counters.cached_queries,
+            unknown_queries: counters.unknown_queries,
+            total_upstreams: counters.total_upstreams,
+            total_clients: counters.total_clients,
+            total_domains: counters.total_domains,
+            query_capacity: counters.query_capacity,
+            upstream_capacity: counters.upstream_capacity,
+            client_capacity: counters.client_capacity,
+            domain_capacity: counters.domain_capacity,
+            string_capacity: counters.string_capacity,
+            gravity_size: counters.gravity_size,
+            gravity_conf: counters.gravity_conf,
+            query_type_counters: counters.query_type_counters,
+            forwarded_queries: counters.forwarded_queries,
+            reply_count_nodata: 0,
+            reply_count_nxdomain: 0,
+            reply_count_cname: 0,
+            reply_count_ip: 0,
+            reply_count_domain: 0
+        }
+    }
+}
+
 impl FtlCounters {
     pub fn query_type(&self, query_type: FtlQueryType) -> usize {
         self.query_type_counters[query_type as usize - 1] as usize
@@ -49,7 +103,7 @@ impl FtlCounters {
 ///
 /// [`FtlCounters::query_type`]: struct.FtlCounters.html#method.query_type
 #[repr(u8)]
-#[derive(Copy, Clone, PartialEq, Debug, Eq, Hash)]
+#[derive(Copy, Clone, PartialEq, Debug, Eq, Hash, Serialize, Deserialize)]
 pub enum FtlQueryType {
     A = 1,
     AAAA,
@@ -103,3 +157,16 @@ impl FtlQueryType {
         format!("{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{FtlCounters, FtlCountersV3};
+    use std::mem;
+
+    /// The counters structs have the same size as in FTL
+    #[test]
+    fn layout_size() {
+        assert_eq!(mem::size_of::<FtlCounters>(), 108);
+        assert_eq!(mem::size_of::<FtlCountersV3>(), 88);
+    }
+}
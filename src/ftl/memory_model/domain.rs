@@ -8,16 +8,16 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::ftl::FtlStrings;
+use crate::ftl::{
+    memory_model::{raw_byte, MAGIC_BYTE},
+    FtlRecord, FtlStrings
+};
 use libc;
 
-#[cfg(test)]
-use crate::ftl::memory_model::MAGIC_BYTE;
-
 /// The domain struct stored in shared memory
 #[repr(C)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct FtlDomain {
     magic: libc::c_uchar,
     pub query_count: libc::c_int,
@@ -27,7 +27,6 @@ pub struct FtlDomain {
 }
 
 impl FtlDomain {
-    #[cfg(test)]
     pub fn new(
         total_count: usize,
         blocked_count: usize,
@@ -43,6 +42,12 @@ impl FtlDomain {
         }
     }
 
+    /// Check that the record has the magic byte, and its name starts inside
+    /// the first `strings_len` bytes of string memory
+    pub fn is_valid(&self, strings_len: usize) -> bool {
+        self.magic == MAGIC_BYTE && (self.domain_str_id as usize) < strings_len
+    }
+
     /// Get the domain name
     pub fn get_domain<'a>(&self, strings: &'a FtlStrings) -> &'a str {
         strings
@@ -51,6 +56,24 @@ impl FtlDomain {
     }
 }
 
+/// A valid domain, used to find the offsets of the fields of a domain in
+/// shared memory
+static DOMAIN_LAYOUT: FtlDomain = FtlDomain {
+    magic: MAGIC_BYTE,
+    query_count: 0,
+    blocked_count: 0,
+    domain_str_id: 0,
+    regex_match: FtlRegexMatch::Unknown
+};
+
+impl FtlRecord for FtlDomain {
+    unsafe fn is_intact(record: *const u8) -> bool {
+        let layout = &DOMAIN_LAYOUT;
+
+        raw_byte(record, layout, &layout.regex_match) <= FtlRegexMatch::NotBlocked as u8
+    }
+}
+
 #[cfg(test)]
 impl Default for FtlDomain {
     fn default() -> Self {
@@ -68,7 +91,7 @@ impl Default for FtlDomain {
 /// is checked when a query of the domain comes in.
 #[repr(u8)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub enum FtlRegexMatch {
     Unknown,
@@ -8,30 +8,52 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-#[cfg(test)]
 use libc;
 
 /// Used by FTL to check memory integrity in various structs
-#[cfg(test)]
 pub const MAGIC_BYTE: libc::c_uchar = 0x57;
 
+/// Read the raw byte of a one byte field of a record in shared memory.
+/// Records may be corrupt, so enum and bool fields are read this way to check
+/// that they hold a value their type can have before a reference to the
+/// record is formed.
+///
+/// The field's offset is found using `layout`, a valid record of the same
+/// type, and `field`, the same field of `layout`. `record` must point to a
+/// whole record.
+unsafe fn raw_byte<T, F>(record: *const u8, layout: &T, field: &F) -> u8 {
+    let offset = field as *const F as usize - layout as *const T as usize;
+    *record.add(offset)
+}
+
+/// Check if the raw byte of a bool field is a valid bool. See [`raw_byte`].
+///
+/// [`raw_byte`]: fn.raw_byte.html
+unsafe fn is_bool<T>(record: *const u8, layout: &T, field: &bool) -> bool {
+    raw_byte(record, layout, field) <= 1
+}
+
 mod client;
 mod counters;
 mod domain;
 mod lock;
 mod over_time;
 mod query;
+mod records;
 mod settings;
 mod strings;
 mod upstream;
 
 pub use self::{
     client::*,
-    counters::{FtlCounters, FtlQueryType},
+    counters::{FtlCounters, FtlCountersV3, FtlQueryType},
     domain::{FtlDomain, FtlRegexMatch},
     lock::FtlLock,
     over_time::*,
-    query::{FtlDnssecType, FtlQuery, FtlQueryReplyType, FtlQueryStatus, BLOCKED_STATUSES},
+    query::{
+        FtlDnssecType, FtlQuery, FtlQueryReplyType, FtlQueryStatus, FtlQueryV3, BLOCKED_STATUSES
+    },
+    records::{FtlRecord, FtlRecords},
     settings::FtlSettings,
     strings::FtlStrings,
     upstream::FtlUpstream
@@ -8,10 +8,8 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use libc;
-
-#[cfg(test)]
 use crate::ftl::memory_model::MAGIC_BYTE;
+use libc;
 
 pub const MAX_LOG_AGE: usize = 24;
 pub const OVERTIME_INTERVAL: usize = 600;
@@ -19,7 +17,7 @@ pub const OVERTIME_SLOTS: usize = (MAX_LOG_AGE + 1) * 3600 / OVERTIME_INTERVAL;
 
 #[repr(C)]
 #[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct FtlOverTime {
     magic: libc::c_uchar,
     pub timestamp: libc::time_t,
@@ -31,7 +29,6 @@ pub struct FtlOverTime {
 }
 
 impl FtlOverTime {
-    #[cfg(test)]
     pub fn new(
         timestamp: usize,
         total_queries: usize,
@@ -51,3 +48,38 @@ impl FtlOverTime {
         }
     }
 }
+
+/// Serialization for arrays of overTime slots, which are too long for serde's
+/// built in array support. Use with `#[serde(with = "...")]`.
+pub mod over_time_slots {
+    use super::OVERTIME_SLOTS;
+    use libc;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    /// Serialize the slots as a sequence
+    pub fn serialize<S: Serializer>(
+        slots: &[libc::c_int; OVERTIME_SLOTS],
+        serializer: S
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(slots.iter())
+    }
+
+    /// Deserialize the slots from a sequence, which must have exactly
+    /// `OVERTIME_SLOTS` items
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D
+    ) -> Result<[libc::c_int; OVERTIME_SLOTS], D::Error> {
+        let values = Vec::<libc::c_int>::deserialize(deserializer)?;
+
+        if values.len() != OVERTIME_SLOTS {
+            return Err(D::Error::invalid_length(
+                values.len(),
+                &"the number of overTime slots"
+            ));
+        }
+
+        let mut slots = [0; OVERTIME_SLOTS];
+        slots.copy_from_slice(&values);
+        Ok(slots)
+    }
+}
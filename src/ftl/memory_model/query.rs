@@ -8,7 +8,10 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::ftl::FtlQueryType;
+use crate::ftl::{
+    memory_model::{is_bool, raw_byte},
+    FtlQueryType, FtlRecord, MAGIC_BYTE
+};
 use libc;
 use rocket::{http::RawStr, request::FromFormValue};
 
@@ -23,7 +26,7 @@ pub const BLOCKED_STATUSES: [i32; 4] = [
 /// The query struct stored in shared memory
 #[repr(C)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct FtlQuery {
     pub magic: libc::c_uchar,
     pub timestamp: libc::time_t,
@@ -50,12 +53,130 @@ impl FtlQuery {
     pub fn is_blocked(&self) -> bool {
         BLOCKED_STATUSES.contains(&(self.status as i32))
     }
+
+    /// Check that the record has the magic byte
+    pub fn is_valid(&self) -> bool {
+        self.magic == MAGIC_BYTE
+    }
+}
+
+/// A valid query, used to find the offsets of the fields of a query in
+/// shared memory
+static QUERY_LAYOUT: FtlQuery = FtlQuery {
+    magic: MAGIC_BYTE,
+    timestamp: 0,
+    time_index: 0,
+    query_type: FtlQueryType::A,
+    status: FtlQueryStatus::Unknown,
+    domain_id: 0,
+    client_id: 0,
+    upstream_id: 0,
+    database_id: 0,
+    id: 0,
+    is_complete: false,
+    is_private: false,
+    response_time: 0,
+    reply_type: FtlQueryReplyType::Unknown,
+    dnssec_type: FtlDnssecType::Unspecified,
+    ad_bit: false
+};
+
+impl FtlRecord for FtlQuery {
+    unsafe fn is_intact(record: *const u8) -> bool {
+        let layout = &QUERY_LAYOUT;
+
+        FtlQueryType::from_number(raw_byte(record, layout, &layout.query_type) as isize).is_some()
+            && FtlQueryStatus::from_number(raw_byte(record, layout, &layout.status) as isize)
+                .is_some()
+            && FtlQueryReplyType::from_number(raw_byte(record, layout, &layout.reply_type) as isize)
+                .is_some()
+            && FtlDnssecType::from_number(raw_byte(record, layout, &layout.dnssec_type) as isize)
+                .is_some()
+            && is_bool(record, layout, &layout.is_complete)
+            && is_bool(record, layout, &layout.is_private)
+            && is_bool(record, layout, &layout.ad_bit)
+    }
+}
+
+/// The query struct stored in version 3 of shared memory, which does not
+/// have the database ID, reply type, DNSSEC type, or AD bit. This is the
+/// `queriesData` struct in FTL's `datastructure.h` from the releases which set
+/// `SHARED_MEMORY_VERSION` to 3.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct FtlQueryV3 {
+    pub magic: libc::c_uchar,
+    pub timestamp: libc::time_t,
+    pub time_index: libc::c_uint,
+    pub query_type: FtlQueryType,
+    pub status: FtlQueryStatus,
+    pub domain_id: libc::c_int,
+    pub client_id: libc::c_int,
+    pub upstream_id: libc::c_int,
+    pub id: libc::c_int,
+    pub is_complete: bool,
+    pub is_private: bool,
+    pub response_time: libc::c_ulong
+}
+
+/// A valid version 3 query, used to find the offsets of its fields in shared
+/// memory
+static QUERY_V3_LAYOUT: FtlQueryV3 = FtlQueryV3 {
+    magic: MAGIC_BYTE,
+    timestamp: 0,
+    time_index: 0,
+    query_type: FtlQueryType::A,
+    status: FtlQueryStatus::Unknown,
+    domain_id: 0,
+    client_id: 0,
+    upstream_id: 0,
+    id: 0,
+    is_complete: false,
+    is_private: false,
+    response_time: 0
+};
+
+impl FtlRecord for FtlQueryV3 {
+    unsafe fn is_intact(record: *const u8) -> bool {
+        let layout = &QUERY_V3_LAYOUT;
+
+        FtlQueryType::from_number(raw_byte(record, layout, &layout.query_type) as isize).is_some()
+            && FtlQueryStatus::from_number(raw_byte(record, layout, &layout.status) as isize)
+                .is_some()
+            && is_bool(record, layout, &layout.is_complete)
+            && is_bool(record, layout, &layout.is_private)
+    }
+}
+
+impl From<FtlQueryV3> for FtlQuery {
+    /// Convert the query to the current layout. The missing fields are
+    /// treated as unknown.
+    fn from(query: FtlQueryV3) -> Self {
+        FtlQuery {
+            magic: query.magic,
+            timestamp: query.timestamp,
+            time_index: query.time_index,
+            query_type: query.query_type,
+            status: query.status,
+            domain_id: query.domain_id,
+            client_id: query.client_id,
+            upstream_id: query.upstream_id,
+            database_id: 0,
+            id: query.id,
+            is_complete: query.is_complete,
+            is_private: query.is_private,
+            response_time: query.response_time,
+            reply_type: FtlQueryReplyType::Unknown,
+            dnssec_type: FtlDnssecType::Unspecified,
+            ad_bit: false
+        }
+    }
 }
 
 /// The statuses an FTL query can have
 #[repr(u8)]
 #[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FtlQueryStatus {
     Unknown,
     Gravity,
@@ -94,7 +215,7 @@ impl<'v> FromFormValue<'v> for FtlQueryStatus {
 /// The reply types an FTL query can have
 #[repr(u8)]
 #[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FtlQueryReplyType {
     Unknown,
     NODATA,
@@ -141,7 +262,7 @@ impl<'v> FromFormValue<'v> for FtlQueryReplyType {
 /// The DNSSEC reply types an FTL query can have
 #[repr(u8)]
 #[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FtlDnssecType {
     Unspecified,
     Secure,
@@ -174,3 +295,84 @@ impl<'v> FromFormValue<'v> for FtlDnssecType {
         Self::from_number(num as isize).ok_or(form_value)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        FtlDnssecType, FtlQuery, FtlQueryReplyType, FtlQueryStatus, FtlQueryV3, QUERY_LAYOUT
+    };
+    use crate::ftl::{FtlQueryType, FtlRecord, MAGIC_BYTE};
+    use std::{mem, ptr};
+
+    /// Queries are only intact if their enum and bool fields hold values
+    /// their types can have
+    #[test]
+    fn intact() {
+        let layout = &QUERY_LAYOUT;
+        let size = mem::size_of::<FtlQuery>();
+
+        // Copy a query into an aligned buffer, like a record in shared memory
+        let mut buffer = vec![0u64; (size + 7) / 8];
+        let record = buffer.as_mut_ptr() as *mut u8;
+        unsafe { ptr::copy_nonoverlapping(layout as *const FtlQuery as *const u8, record, size) };
+
+        assert!(unsafe { FtlQuery::is_intact(record) });
+
+        // Corrupt the status
+        let offset =
+            &layout.status as *const FtlQueryStatus as usize - layout as *const FtlQuery as usize;
+        unsafe { *record.add(offset) = 100 };
+
+        assert!(!unsafe { FtlQuery::is_intact(record) });
+    }
+
+    /// The query structs have the same size as in FTL, so the records in
+    /// shared memory line up
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn layout_size() {
+        assert_eq!(mem::size_of::<FtlQuery>(), 72);
+        assert_eq!(mem::size_of::<FtlQueryV3>(), 56);
+    }
+
+    /// Version 3 queries keep their data, and the new fields are unknown
+    #[test]
+    fn convert_v3() {
+        let query = FtlQuery::from(FtlQueryV3 {
+            magic: MAGIC_BYTE,
+            timestamp: 1,
+            time_index: 2,
+            query_type: FtlQueryType::AAAA,
+            status: FtlQueryStatus::Gravity,
+            domain_id: 3,
+            client_id: 4,
+            upstream_id: 0,
+            id: 5,
+            is_complete: true,
+            is_private: false,
+            response_time: 6
+        });
+
+        assert_eq!(
+            query,
+            FtlQuery {
+                magic: MAGIC_BYTE,
+                timestamp: 1,
+                time_index: 2,
+                query_type: FtlQueryType::AAAA,
+                status: FtlQueryStatus::Gravity,
+                domain_id: 3,
+                client_id: 4,
+                upstream_id: 0,
+                database_id: 0,
+                id: 5,
+                is_complete: true,
+                is_private: false,
+                response_time: 6,
+                reply_type: FtlQueryReplyType::Unknown,
+                dnssec_type: FtlDnssecType::Unspecified,
+                ad_bit: false
+            }
+        );
+    }
+}
@@ -0,0 +1,158 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Shared Memory Record Arrays
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use shmem::Array;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    ops::{Deref, Index},
+    ptr
+};
+
+/// A record which FTL stores in shared memory
+pub trait FtlRecord {
+    /// Check that the raw bytes of a record hold values which the record's
+    /// enum and bool fields can have. A reference to a record in shared memory
+    /// is only formed after this check passes, since a corrupt record would
+    /// otherwise be undefined behavior to read.
+    ///
+    /// `record` must point to a whole record.
+    unsafe fn is_intact(record: *const u8) -> bool;
+}
+
+/// A safe wrapper around an array of FTL's records, such as the queries. It
+/// is used to access the records the same way no matter which layout version
+/// shared memory uses. Use a [`Validator`] to iterate over the records.
+///
+/// Records in an older layout are converted into the current struct one at a
+/// time as they are accessed, instead of copying the whole array every time
+/// shared memory is read. Converted records are kept until the wrapper is
+/// dropped, so a record is only converted once and references to it stay
+/// valid.
+///
+/// [`Validator`]: ../validate/struct.Validator.html
+pub enum FtlRecords<'a, T> {
+    /// Records in shared memory which use the current layout
+    Production(Array<u8>),
+    /// Records in shared memory which use an older layout
+    Converted(ConvertedRecords<T>),
+    /// Records which are not in shared memory, such as from a snapshot
+    Copied(Box<dyn Deref<Target = [T]> + 'a>)
+}
+
+/// Records in an older layout, along with the ones which have been converted
+/// so far. Records which are not intact are stored as `None`.
+pub struct ConvertedRecords<T> {
+    bytes: Array<u8>,
+    record_size: usize,
+    convert: unsafe fn(*const u8) -> Option<T>,
+    converted: RefCell<HashMap<usize, Option<Box<T>>>>
+}
+
+impl<'a, T: FtlRecord> FtlRecords<'a, T> {
+    /// Wrap records in shared memory which use the older layout `Old`
+    pub fn converted<Old: FtlRecord + Copy>(bytes: Array<u8>) -> FtlRecords<'a, T>
+    where
+        T: From<Old>
+    {
+        FtlRecords::Converted(ConvertedRecords {
+            bytes,
+            record_size: mem::size_of::<Old>(),
+            convert: convert_record::<Old, T>,
+            converted: RefCell::new(HashMap::new())
+        })
+    }
+
+    /// Get the number of records, including the ones which are not in use
+    pub fn len(&self) -> usize {
+        match self {
+            FtlRecords::Production(bytes) => bytes.len() / mem::size_of::<T>(),
+            FtlRecords::Converted(records) => records.bytes.len() / records.record_size,
+            FtlRecords::Copied(records) => records.len()
+        }
+    }
+
+    /// Check if there are no records
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the record at the position. `None` is returned if the position is
+    /// out of bounds, or if the record in shared memory is not intact.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+
+        match self {
+            FtlRecords::Production(bytes) => {
+                // Shared memory is page aligned and the records are stored one
+                // after another, so the record is aligned
+                let record = unsafe { bytes.as_ptr().add(index * mem::size_of::<T>()) };
+
+                if unsafe { T::is_intact(record) } {
+                    Some(unsafe { &*(record as *const T) })
+                } else {
+                    None
+                }
+            }
+            FtlRecords::Converted(records) => records.get(index),
+            FtlRecords::Copied(records) => records.get(index)
+        }
+    }
+}
+
+impl<'a, T> From<&'a [T]> for FtlRecords<'a, T> {
+    fn from(records: &'a [T]) -> Self {
+        FtlRecords::Copied(Box::new(records))
+    }
+}
+
+impl<'a, T: FtlRecord> Index<usize> for FtlRecords<'a, T> {
+    type Output = T;
+
+    /// Get a record which is known to be valid, such as one referenced by a
+    /// query which the [`Validator`] returned
+    ///
+    /// [`Validator`]: ../validate/struct.Validator.html
+    fn index(&self, index: usize) -> &T {
+        self.get(index)
+            .expect("Record is out of bounds or not intact")
+    }
+}
+
+impl<T> ConvertedRecords<T> {
+    /// Get the converted record at the position, converting it if this is
+    /// the first time it is accessed. The position must be in bounds.
+    fn get(&self, index: usize) -> Option<&T> {
+        let mut converted = self.converted.borrow_mut();
+        let record = converted.entry(index).or_insert_with(|| {
+            let bytes = unsafe { self.bytes.as_ptr().add(index * self.record_size) };
+            unsafe { (self.convert)(bytes) }.map(Box::new)
+        });
+
+        // The record is boxed and never removed from the map, so it stays at
+        // the same address for as long as `self` exists
+        record
+            .as_ref()
+            .map(|record| unsafe { &*(&**record as *const T) })
+    }
+}
+
+/// Read a record in the older layout `Old` and convert it into the current
+/// layout, if it is intact. `record` must point to a whole record.
+unsafe fn convert_record<Old: FtlRecord + Copy, New: From<Old>>(record: *const u8) -> Option<New> {
+    if Old::is_intact(record) {
+        Some(New::from(ptr::read_unaligned(record as *const Old)))
+    } else {
+        None
+    }
+}
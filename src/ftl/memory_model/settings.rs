@@ -11,7 +11,7 @@
 use libc;
 
 /// The settings structure used to share version information and other settings
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 #[repr(C)]
 pub struct FtlSettings {
     pub version: libc::c_int,
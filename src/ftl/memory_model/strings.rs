@@ -10,53 +10,99 @@
 
 use libc;
 use shmem::Array;
-use std::{ffi::CStr, marker::PhantomData};
-
-#[cfg(test)]
-use std::collections::HashMap;
+use std::{cmp, collections::HashMap, slice, str};
 
 /// A safe wrapper around FTL's strings. It is used to access the strings
 /// referenced by other shared memory structs.
 ///
-/// Note: When testing, the 0 entry will be ignore in favor of returning the
-/// empty string
-pub enum FtlStrings<'test> {
-    // Use `PhantomData` because when not in testing mode, the 'test lifetime will be unused and
-    // will cause an error. The `PhantomData` is zero-sized, so it will not actually exist.
-    Production(Array<libc::c_char>, PhantomData<&'test bool>),
+/// Note: When using a snapshot or testing, the 0 entry will be ignored in
+/// favor of returning the empty string
+pub enum FtlStrings<'a> {
+    Production(Array<libc::c_char>),
+    Snapshot(&'a HashMap<usize, String>),
     #[cfg(test)]
-    Test(&'test HashMap<usize, String>)
+    Test(&'a HashMap<usize, String>)
 }
 
-impl<'test> FtlStrings<'test> {
+impl<'a> FtlStrings<'a> {
     /// Read a string from FTL's string memory. If the string does not exist,
     /// `None` is returned. The `id` is the position of the string in
     /// shared memory, which can be obtained from the other shared memory
     /// structs.
     pub fn get_str(&self, id: usize) -> Option<&str> {
         match self {
-            FtlStrings::Production(strings, ..) => Self::get_str_prod(strings, id),
+            FtlStrings::Production(strings) => Self::get_str_prod(strings, id),
+            FtlStrings::Snapshot(strings) => Self::get_str_map(strings, id),
             #[cfg(test)]
-            FtlStrings::Test(strings) => {
-                if id == 0 {
-                    Some("")
-                } else {
-                    strings.get(&id).map(|string| string.as_str())
-                }
+            FtlStrings::Test(strings) => Self::get_str_map(strings, id)
+        }
+    }
+
+    /// Get all of the strings, keyed by their ID. Only the first `len` bytes
+    /// of FTL's string memory are in use, so the rest is ignored.
+    pub fn to_map(&self, len: usize) -> HashMap<usize, String> {
+        match self {
+            FtlStrings::Production(strings) => {
+                Self::to_map_prod(&strings[..cmp::min(len, strings.len())])
             }
+            FtlStrings::Snapshot(strings) => (*strings).clone(),
+            #[cfg(test)]
+            FtlStrings::Test(strings) => (*strings).clone()
         }
     }
 
     /// This function is used for `FtlStrings::Production`. It checks to see
-    /// if the string exists, and then creates a `CStr` from a pointer. It
-    /// is assumed that the string has a null terminator. Then the `CStr` is
+    /// if the string exists and has a null terminator inside string memory,
+    /// so a corrupt ID can not cause a read past the end. Then the bytes are
     /// converted into `&str`. If the conversion fails, `None` is returned.
     fn get_str_prod(strings: &[libc::c_char], id: usize) -> Option<&str> {
-        if strings.get(id).is_some() {
-            unsafe { CStr::from_ptr(&strings[id]) }.to_str().ok()
+        let strings = strings.get(id..)?;
+        let len = strings.iter().position(|&c| c == 0)?;
+        let bytes = unsafe { slice::from_raw_parts(strings.as_ptr() as *const u8, len) };
+
+        str::from_utf8(bytes).ok()
+    }
+
+    /// Get the size of the string memory, if it is known
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            FtlStrings::Production(strings) => Some(strings.len()),
+            _ => None
+        }
+    }
+
+    /// This function is used for the variants which store the strings in a
+    /// map. The 0 entry is always the empty string.
+    fn get_str_map(strings: &HashMap<usize, String>, id: usize) -> Option<&str> {
+        if id == 0 {
+            Some("")
         } else {
-            None
+            strings.get(&id).map(|string| string.as_str())
+        }
+    }
+
+    /// This function is used for `FtlStrings::Production`. Each string
+    /// starts after the null terminator of the previous string. Strings which
+    /// are not valid UTF-8 or are not terminated are skipped.
+    fn to_map_prod(strings: &[libc::c_char]) -> HashMap<usize, String> {
+        let mut map = HashMap::new();
+        let mut start = 0;
+
+        for (i, &c) in strings.iter().enumerate() {
+            if c != 0 {
+                continue;
+            }
+
+            let bytes = strings[start..i].iter().map(|&c| c as u8).collect();
+
+            if let Ok(string) = String::from_utf8(bytes) {
+                map.insert(start, string);
+            }
+
+            start = i + 1;
         }
+
+        map
     }
 }
 
@@ -88,4 +134,31 @@ mod tests {
         assert_eq!(FtlStrings::get_str_prod(&strings, 1), Some("test"));
         assert_eq!(FtlStrings::get_str_prod(&strings, 6), None);
     }
+
+    /// Strings which are not terminated inside string memory are not read
+    #[test]
+    fn get_str_prod_unterminated() {
+        let strings: Vec<libc::c_char> = ['\0', 't', 'e', 's', 't']
+            .iter()
+            .map(|&c| c as libc::c_char)
+            .collect();
+
+        assert_eq!(FtlStrings::get_str_prod(&strings, 0), Some(""));
+        assert_eq!(FtlStrings::get_str_prod(&strings, 1), None);
+    }
+
+    #[test]
+    fn to_map_prod() {
+        let strings: Vec<libc::c_char> = ['\0', 'a', 'b', '\0', 'c', '\0', 'd']
+            .iter()
+            .map(|&c| c as libc::c_char)
+            .collect();
+
+        let mut expected = HashMap::new();
+        expected.insert(0, "".to_owned());
+        expected.insert(1, "ab".to_owned());
+        expected.insert(4, "c".to_owned());
+
+        assert_eq!(FtlStrings::to_map_prod(&strings), expected);
+    }
 }
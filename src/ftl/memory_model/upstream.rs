@@ -8,15 +8,15 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::ftl::FtlStrings;
+use crate::ftl::{
+    memory_model::{is_bool, MAGIC_BYTE},
+    FtlRecord, FtlStrings
+};
 use libc;
 
-#[cfg(test)]
-use crate::ftl::memory_model::MAGIC_BYTE;
-
 /// The upstream (forward destination) struct stored in shared memory
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct FtlUpstream {
     magic: libc::c_uchar,
     pub query_count: libc::c_int,
@@ -27,7 +27,6 @@ pub struct FtlUpstream {
 }
 
 impl FtlUpstream {
-    #[cfg(test)]
     pub fn new(
         query_count: usize,
         failed_count: usize,
@@ -44,6 +43,14 @@ impl FtlUpstream {
         }
     }
 
+    /// Check that the record has the magic byte, and its strings start inside
+    /// the first `strings_len` bytes of string memory
+    pub fn is_valid(&self, strings_len: usize) -> bool {
+        self.magic == MAGIC_BYTE
+            && (self.ip_str_id as usize) < strings_len
+            && (self.name_str_id as usize) < strings_len
+    }
+
     /// Get the IP address of the upstream
     pub fn get_ip<'a>(&self, strings: &'a FtlStrings) -> &'a str {
         strings.get_str(self.ip_str_id as usize).unwrap_or_default()
@@ -63,3 +70,22 @@ impl FtlUpstream {
         }
     }
 }
+
+/// A valid upstream, used to find the offsets of the fields of an upstream in
+/// shared memory
+static UPSTREAM_LAYOUT: FtlUpstream = FtlUpstream {
+    magic: MAGIC_BYTE,
+    query_count: 0,
+    failed_count: 0,
+    ip_str_id: 0,
+    name_str_id: 0,
+    is_name_unknown: false
+};
+
+impl FtlRecord for FtlUpstream {
+    unsafe fn is_intact(record: *const u8) -> bool {
+        let layout = &UPSTREAM_LAYOUT;
+
+        is_bool(record, layout, &layout.is_name_unknown)
+    }
+}
@@ -8,15 +8,25 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+#[macro_use]
+mod decode;
 mod lock_thread;
 mod memory_model;
 mod shared_lock;
 mod shared_memory;
+mod simulator;
+mod snapshot;
 mod socket;
+mod validate;
 
 pub use self::{
+    decode::{FtlBinary, FtlDecode, FtlResponse, FtlValue},
+    lock_thread::{LockStats, LockTiming},
     memory_model::*,
     shared_lock::{ShmLock, ShmLockGuard},
-    shared_memory::FtlMemory,
-    socket::{FtlConnection, FtlConnectionType}
+    shared_memory::{FtlMemory, ShmVersion},
+    simulator::{FtlSimulator, SimulatorOptions},
+    snapshot::FtlSnapshot,
+    socket::{FtlConnection, FtlConnectionType, FtlSocket, SocketOptions},
+    validate::{InvalidRecordCounts, InvalidRecords, Validator}
 };
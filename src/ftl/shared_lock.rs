@@ -9,7 +9,10 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    ftl::lock_thread::{LockRequest, LockThread, RequestType},
+    ftl::{
+        lock_thread::{LockRequest, LockStats, LockThread, RequestType},
+        ShmVersion
+    },
     util::{Error, ErrorKind}
 };
 use failure::{Fail, ResultExt};
@@ -17,9 +20,10 @@ use nix::errno::Errno;
 use std::{
     sync::{
         mpsc::{channel, Sender},
-        Mutex
+        Arc, Mutex
     },
-    thread
+    thread,
+    time::Instant
 };
 
 /// A lock for coordinating shared memory access with FTL. It locks a mutex in
@@ -30,7 +34,8 @@ use std::{
 /// The shared memory lock must be locked and unlocked from the same thread, so
 /// the locking happens on a dedicated lock handling thread.
 pub struct ShmLock {
-    sender: Mutex<Sender<LockRequest>>
+    sender: Mutex<Sender<LockRequest>>,
+    stats: Arc<Mutex<LockStats>>
 }
 
 impl ShmLock {
@@ -39,25 +44,39 @@ impl ShmLock {
         // Create a lock thread which handles taking the shared lock, since
         // pthread doesn't like locking and unlocking from different threads.
         let (request_sender, request_receiver) = channel();
+        let stats = Arc::new(Mutex::new(LockStats::default()));
+        let thread_stats = stats.clone();
 
         thread::Builder::new()
             .name("Lock Handler".to_owned())
             .spawn(move || {
-                let mut lock_thread = LockThread::new();
+                let mut lock_thread = LockThread::with_stats(thread_stats);
                 lock_thread.handle_requests(request_receiver);
             })
             .unwrap();
 
         ShmLock {
-            sender: Mutex::new(request_sender)
+            sender: Mutex::new(request_sender),
+            stats
         }
     }
 
     /// Acquire a read lock on the shared memory. It will last as long as the
     /// guard (return value) lives.
     pub fn read(&self) -> Result<ShmLockGuard, Error> {
+        let start = Instant::now();
         self.send_request(RequestType::Lock)?;
-        Ok(ShmLockGuard::Production { lock: self })
+        self.stats.lock().unwrap().wait.record(start.elapsed());
+
+        Ok(ShmLockGuard::Production {
+            lock: self,
+            version: ShmVersion::LATEST
+        })
+    }
+
+    /// Get the statistics about how long the lock was waited for and held
+    pub fn stats(&self) -> LockStats {
+        *self.stats.lock().unwrap()
     }
 
     /// Send a request to the lock thread. This will block until the request
@@ -91,20 +110,50 @@ impl ShmLock {
 }
 
 /// A RAII type lock guard which keeps the lock active until it is dropped.
+/// It also holds the version of the shared memory layout, which is checked
+/// when the lock is taken.
 pub enum ShmLockGuard<'lock> {
     Production {
-        lock: &'lock ShmLock
+        lock: &'lock ShmLock,
+        version: ShmVersion
     },
+    /// Snapshots are not shared, so they do not need a lock
+    Snapshot,
+    /// The simulator locks its data while it is being copied
+    Simulator,
     #[cfg(test)]
     Test
 }
 
+impl<'lock> ShmLockGuard<'lock> {
+    /// Get the version of the shared memory layout
+    pub fn version(&self) -> ShmVersion {
+        match self {
+            ShmLockGuard::Production { version, .. } => *version,
+            ShmLockGuard::Snapshot | ShmLockGuard::Simulator => ShmVersion::LATEST,
+            #[cfg(test)]
+            ShmLockGuard::Test => ShmVersion::LATEST
+        }
+    }
+
+    /// Set the version of the shared memory layout
+    pub fn set_version(&mut self, new_version: ShmVersion) {
+        match self {
+            ShmLockGuard::Production { version, .. } => *version = new_version,
+            ShmLockGuard::Snapshot | ShmLockGuard::Simulator => (),
+            #[cfg(test)]
+            ShmLockGuard::Test => ()
+        }
+    }
+}
+
 impl<'lock> Drop for ShmLockGuard<'lock> {
     fn drop(&mut self) {
         match self {
-            ShmLockGuard::Production { lock } => {
+            ShmLockGuard::Production { lock, .. } => {
                 lock.send_request(RequestType::Unlock).unwrap();
             }
+            ShmLockGuard::Snapshot | ShmLockGuard::Simulator => (),
             #[cfg(test)]
             ShmLockGuard::Test => ()
         }
@@ -120,7 +169,7 @@ mod test {
     use std::{
         sync::{
             mpsc::{channel, Receiver},
-            Mutex
+            Arc, Mutex
         },
         thread
     };
@@ -143,7 +192,8 @@ mod test {
         let (sender, receiver) = channel();
 
         let lock = ShmLock {
-            sender: Mutex::new(sender)
+            sender: Mutex::new(sender),
+            stats: Arc::default()
         };
 
         // Create the mock lock handler thread
@@ -160,6 +210,9 @@ mod test {
 
         // Join with the mock lock handler thread
         handler_thread.join().unwrap();
+
+        // The time waiting for the lock was recorded
+        assert_eq!(lock.stats().wait.count, 1);
     }
 
     /// Check that error returned from the pthread lock call (returned as
@@ -170,7 +223,8 @@ mod test {
         let (sender, receiver) = channel();
 
         let lock = ShmLock {
-            sender: Mutex::new(sender)
+            sender: Mutex::new(sender),
+            stats: Arc::default()
         };
 
         // Create the mock lock handler thread
@@ -198,7 +252,8 @@ mod test {
         let (sender, receiver) = channel();
 
         let lock = ShmLock {
-            sender: Mutex::new(sender)
+            sender: Mutex::new(sender),
+            stats: Arc::default()
         };
 
         // Create the mock lock handler thread
@@ -10,18 +10,20 @@
 
 use crate::{
     ftl::{
-        FtlClient, FtlCounters, FtlDomain, FtlOverTime, FtlQuery, FtlStrings, FtlUpstream, ShmLock,
-        ShmLockGuard
+        FtlClient, FtlClientV3, FtlCounters, FtlCountersV3, FtlDomain, FtlOverTime, FtlQuery,
+        FtlQueryV3, FtlRecord, FtlRecords, FtlSimulator, FtlSnapshot, FtlStrings, FtlUpstream,
+        InvalidRecordCounts, InvalidRecords, LockStats, ShmLock, ShmLockGuard
     },
     util::Error
 };
 use shmem::{Array, Map, Object};
-use std::{marker::PhantomData, ops::Deref};
+use std::{ops::Deref, sync::Arc};
 
 use crate::{ftl::memory_model::FtlSettings, util::ErrorKind};
 #[cfg(test)]
 use std::collections::HashMap;
 
+/// The newest version of the shared memory layout
 const FTL_SHM_VERSION: usize = 4;
 
 const FTL_SHM_CLIENTS: &str = "/FTL-clients";
@@ -33,15 +35,74 @@ const FTL_SHM_STRINGS: &str = "/FTL-strings";
 const FTL_SHM_COUNTERS: &str = "/FTL-counters";
 const FTL_SHM_SETTINGS: &str = "/FTL-settings";
 
+/// The versions of the shared memory layout which can be read. The settings
+/// object has the same layout in every version, so it is used to find the
+/// version FTL is using.
+///
+/// Records in older layouts are converted into the current structs one at a
+/// time as they are accessed (see [`FtlRecords`]), so the rest of the API only
+/// works with the newest layout. Data which the older layout does not have is
+/// treated as unknown or zero.
+///
+/// [`FtlRecords`]: ../memory_model/enum.FtlRecords.html
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum ShmVersion {
+    V3,
+    V4
+}
+
+impl ShmVersion {
+    /// The newest version, which the memory model structs use directly
+    pub const LATEST: ShmVersion = ShmVersion::V4;
+
+    /// Get the layout version from the number FTL reports
+    pub fn from_number(num: usize) -> Option<Self> {
+        match num {
+            3 => Some(ShmVersion::V3),
+            4 => Some(ShmVersion::V4),
+            _ => None
+        }
+    }
+}
+
+/// Open a shared memory array of records
+fn open_records<'a, T>(name: &str) -> Result<FtlRecords<'a, T>, Error> {
+    Ok(FtlRecords::Production(Array::new(Object::open(name)?)?))
+}
+
+/// Open a shared memory array of records which use the older layout `Old`.
+/// The records are converted into the current layout as they are accessed.
+fn open_converted<'a, Old: FtlRecord + Copy, New: FtlRecord + From<Old>>(
+    name: &str
+) -> Result<FtlRecords<'a, New>, Error> {
+    Ok(FtlRecords::converted::<Old>(Array::new(Object::open(
+        name
+    )?)?))
+}
+
 /// A wrapper for accessing FTL's shared memory.
 ///
-/// - Production mode connects to the real FTL shared memory.
+/// - Production mode connects to the real FTL shared memory. The records read
+///   from it are validated, and the invalid ones are counted.
+/// - Snapshot mode uses a copy of shared memory which was loaded from a file.
+/// - Simulator mode uses data generated by the simulator, which is shared with
+///   the simulated FTL socket.
 /// - Test mode uses the associated test data to mock FTL's shared memory.
+///
+/// Clones share the same data, and in production mode the same lock and
+/// invalid record counts. Anything which reads shared memory outside of
+/// Rocket, like the live update hub and the webhook watcher, should use a
+/// clone of the instance Rocket manages, so there is only one lock thread.
+#[derive(Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum FtlMemory {
     Production {
-        lock: ShmLock
+        lock: Arc<ShmLock>,
+        invalid: Arc<InvalidRecords>
     },
+    Snapshot(Arc<FtlSnapshot>),
+    Simulator(Arc<FtlSimulator>),
     #[cfg(test)]
     Test {
         clients: Vec<FtlClient>,
@@ -59,67 +120,97 @@ impl FtlMemory {
     /// Create a production instance of `FtlMemory`
     pub fn production() -> FtlMemory {
         FtlMemory::Production {
-            lock: ShmLock::new()
+            lock: Arc::new(ShmLock::new()),
+            invalid: Arc::new(InvalidRecords::default())
         }
     }
 
     /// Get the FTL shared memory lock. The resulting [`ShmLockGuard`] is used
-    /// to access the rest of shared memory.
+    /// to access the rest of shared memory, using the layout version which FTL
+    /// reports.
     ///
     /// [`ShmLockGuard`]: ../shared_lock/enum.ShmLockGuard.html
     pub fn lock(&self) -> Result<ShmLockGuard, Error> {
         match self {
-            FtlMemory::Production { lock } => {
-                let guard = lock.read()?;
-
-                // Check the version of shared memory, in case it is not the
-                // same version used by this API
-                let settings = self.settings(&guard)?;
-                let version = settings.version as usize;
-
-                if version == FTL_SHM_VERSION {
-                    Ok(guard)
-                } else {
-                    Err(Error::from(ErrorKind::SharedMemoryVersion(
+            FtlMemory::Production { lock, .. } => {
+                let mut guard = lock.read()?;
+
+                // Check the version of shared memory, in case it is a version
+                // this API can not read
+                let version = self.settings(&guard)?.version as usize;
+
+                match ShmVersion::from_number(version) {
+                    Some(shm_version) => {
+                        guard.set_version(shm_version);
+                        Ok(guard)
+                    }
+                    None => Err(Error::from(ErrorKind::SharedMemoryVersion(
                         version,
                         FTL_SHM_VERSION
                     )))
                 }
             }
+            FtlMemory::Snapshot(_) => Ok(ShmLockGuard::Snapshot),
+            FtlMemory::Simulator(simulator) => {
+                // Generate the queries made since the last lock
+                simulator.update();
+                Ok(ShmLockGuard::Simulator)
+            }
             #[cfg(test)]
             FtlMemory::Test { .. } => Ok(ShmLockGuard::Test)
         }
     }
 
-    /// Get the FTL shared memory client data. The resulting trait object can
-    /// dereference into `&[FtlClient]`.
+    /// Get the statistics about how long the shared memory lock was waited
+    /// for and held. Only production mode uses the lock, so the other modes
+    /// return `None`.
+    pub fn lock_stats(&self) -> Option<LockStats> {
+        match self {
+            FtlMemory::Production { lock, .. } => Some(lock.stats()),
+            _ => None
+        }
+    }
+
+    /// Get the records which have been skipped because they were invalid.
+    /// Only production mode validates records, so the other modes return
+    /// `None`.
+    pub fn invalid_records(&self) -> Option<InvalidRecordCounts> {
+        match self {
+            FtlMemory::Production { invalid, .. } => Some(invalid.counts()),
+            _ => None
+        }
+    }
+
+    /// Get the FTL shared memory client data
     pub fn clients<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
-    ) -> Result<Box<dyn Deref<Target = [FtlClient]> + 'lock>, Error> {
+        lock_guard: &ShmLockGuard<'lock>
+    ) -> Result<FtlRecords<'lock, FtlClient>, Error> {
         Ok(match self {
-            FtlMemory::Production { .. } => Box::new(
+            FtlMemory::Production { .. } => match lock_guard.version() {
                 // Load the shared memory
-                Array::new(Object::open(FTL_SHM_CLIENTS)?)?
-            ),
+                ShmVersion::V4 => open_records(FTL_SHM_CLIENTS)?,
+                ShmVersion::V3 => open_converted::<FtlClientV3, _>(FTL_SHM_CLIENTS)?
+            },
+            FtlMemory::Snapshot(snapshot) => FtlRecords::from(snapshot.clients.as_slice()),
+            FtlMemory::Simulator(simulator) => FtlRecords::Copied(Box::new(simulator.clients())),
             #[cfg(test)]
-            FtlMemory::Test { clients, .. } => Box::new(clients.as_slice())
+            FtlMemory::Test { clients, .. } => FtlRecords::from(clients.as_slice())
         })
     }
 
-    /// Get the FTL shared memory domain data. The resulting trait object can
-    /// dereference into `&[FtlDomain]`.
+    /// Get the FTL shared memory domain data
     pub fn domains<'lock>(
         &'lock self,
         _lock_guard: &ShmLockGuard<'lock>
-    ) -> Result<Box<dyn Deref<Target = [FtlDomain]> + 'lock>, Error> {
+    ) -> Result<FtlRecords<'lock, FtlDomain>, Error> {
         Ok(match self {
-            FtlMemory::Production { .. } => Box::new(
-                // Load the shared memory
-                Array::new(Object::open(FTL_SHM_DOMAINS)?)?
-            ),
+            // Load the shared memory
+            FtlMemory::Production { .. } => open_records(FTL_SHM_DOMAINS)?,
+            FtlMemory::Snapshot(snapshot) => FtlRecords::from(snapshot.domains.as_slice()),
+            FtlMemory::Simulator(simulator) => FtlRecords::Copied(Box::new(simulator.domains())),
             #[cfg(test)]
-            FtlMemory::Test { domains, .. } => Box::new(domains.as_slice())
+            FtlMemory::Test { domains, .. } => FtlRecords::from(domains.as_slice())
         })
     }
 
@@ -134,40 +225,43 @@ impl FtlMemory {
                 // Load the shared memory
                 Array::new(Object::open(FTL_SHM_OVERTIME)?)?
             ),
+            FtlMemory::Snapshot(snapshot) => Box::new(snapshot.over_time.as_slice()),
+            FtlMemory::Simulator(simulator) => Box::new(simulator.over_time()),
             #[cfg(test)]
             FtlMemory::Test { over_time, .. } => Box::new(over_time.as_slice())
         })
     }
 
-    /// Get the FTL shared memory upstream data. The resulting trait object can
-    /// dereference into `&[FtlUpstream]`.
+    /// Get the FTL shared memory upstream data
     pub fn upstreams<'lock>(
         &'lock self,
         _lock_guard: &ShmLockGuard<'lock>
-    ) -> Result<Box<dyn Deref<Target = [FtlUpstream]> + 'lock>, Error> {
+    ) -> Result<FtlRecords<'lock, FtlUpstream>, Error> {
         Ok(match self {
-            FtlMemory::Production { .. } => Box::new(
-                // Load the shared memory
-                Array::new(Object::open(FTL_SHM_FORWARDED)?)?
-            ),
+            // Load the shared memory
+            FtlMemory::Production { .. } => open_records(FTL_SHM_FORWARDED)?,
+            FtlMemory::Snapshot(snapshot) => FtlRecords::from(snapshot.upstreams.as_slice()),
+            FtlMemory::Simulator(simulator) => FtlRecords::Copied(Box::new(simulator.upstreams())),
             #[cfg(test)]
-            FtlMemory::Test { upstreams, .. } => Box::new(upstreams.as_slice())
+            FtlMemory::Test { upstreams, .. } => FtlRecords::from(upstreams.as_slice())
         })
     }
 
-    /// Get the FTL shared memory query data. The resulting trait object can
-    /// dereference into `&[FtlQuery]`.
+    /// Get the FTL shared memory query data
     pub fn queries<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
-    ) -> Result<Box<dyn Deref<Target = [FtlQuery]> + 'lock>, Error> {
+        lock_guard: &ShmLockGuard<'lock>
+    ) -> Result<FtlRecords<'lock, FtlQuery>, Error> {
         Ok(match self {
-            FtlMemory::Production { .. } => Box::new(
+            FtlMemory::Production { .. } => match lock_guard.version() {
                 // Load the shared memory
-                Array::new(Object::open(FTL_SHM_QUERIES)?)?
-            ),
+                ShmVersion::V4 => open_records(FTL_SHM_QUERIES)?,
+                ShmVersion::V3 => open_converted::<FtlQueryV3, _>(FTL_SHM_QUERIES)?
+            },
+            FtlMemory::Snapshot(snapshot) => FtlRecords::from(snapshot.queries.as_slice()),
+            FtlMemory::Simulator(simulator) => FtlRecords::Copied(Box::new(simulator.queries())),
             #[cfg(test)]
-            FtlMemory::Test { queries, .. } => Box::new(queries.as_slice())
+            FtlMemory::Test { queries, .. } => FtlRecords::from(queries.as_slice())
         })
     }
 
@@ -178,8 +272,10 @@ impl FtlMemory {
     ) -> Result<FtlStrings<'lock>, Error> {
         Ok(match self {
             FtlMemory::Production { .. } => {
-                FtlStrings::Production(Array::new(Object::open(FTL_SHM_STRINGS)?)?, PhantomData)
+                FtlStrings::Production(Array::new(Object::open(FTL_SHM_STRINGS)?)?)
             }
+            FtlMemory::Snapshot(snapshot) => FtlStrings::Snapshot(&snapshot.strings),
+            FtlMemory::Simulator(simulator) => FtlStrings::Snapshot(simulator.strings()),
             #[cfg(test)]
             FtlMemory::Test { strings, .. } => FtlStrings::Test(&strings)
         })
@@ -189,10 +285,18 @@ impl FtlMemory {
     /// dereference into `&FtlCounters`.
     pub fn counters<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = FtlCounters> + 'lock>, Error> {
         Ok(match self {
-            FtlMemory::Production { .. } => Box::new(Map::new(Object::open(FTL_SHM_COUNTERS)?)?),
+            FtlMemory::Production { .. } => match lock_guard.version() {
+                ShmVersion::V4 => Box::new(Map::new(Object::open(FTL_SHM_COUNTERS)?)?),
+                ShmVersion::V3 => {
+                    let counters: Map<FtlCountersV3> = Map::new(Object::open(FTL_SHM_COUNTERS)?)?;
+                    Box::new(Box::new(FtlCounters::from(*counters)))
+                }
+            },
+            FtlMemory::Snapshot(snapshot) => Box::new(&snapshot.counters),
+            FtlMemory::Simulator(simulator) => Box::new(Box::new(simulator.counters())),
             #[cfg(test)]
             FtlMemory::Test { counters, .. } => Box::new(counters)
         })
@@ -206,8 +310,23 @@ impl FtlMemory {
     ) -> Result<Box<dyn Deref<Target = FtlSettings> + 'lock>, Error> {
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(Map::new(Object::open(FTL_SHM_SETTINGS)?)?),
+            FtlMemory::Snapshot(snapshot) => Box::new(&snapshot.settings),
+            FtlMemory::Simulator(simulator) => Box::new(simulator.settings()),
             #[cfg(test)]
             FtlMemory::Test { settings, .. } => Box::new(settings)
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::ShmVersion;
+
+    /// Only the known layout versions are supported
+    #[test]
+    fn versions() {
+        assert_eq!(ShmVersion::from_number(3), Some(ShmVersion::V3));
+        assert_eq!(ShmVersion::from_number(4), Some(ShmVersion::LATEST));
+        assert_eq!(ShmVersion::from_number(5), None);
+    }
+}
@@ -0,0 +1,725 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Simulator
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::ftl::{
+    FtlClient, FtlCounters, FtlDnssecType, FtlDomain, FtlOverTime, FtlQuery, FtlQueryReplyType,
+    FtlQueryStatus, FtlQueryType, FtlRegexMatch, FtlSettings, FtlUpstream, MAGIC_BYTE, MAX_LOG_AGE,
+    OVERTIME_INTERVAL, OVERTIME_SLOTS
+};
+use libc;
+use rmp::encode;
+use std::{
+    cmp,
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// The shared memory layout version which the simulated data uses
+const SIMULATOR_SHM_VERSION: libc::c_int = 4;
+
+/// The share of allowed queries which are answered from the cache
+const CACHE_RATIO: f64 = 0.3;
+
+/// The share of blocked queries which are blocked by the blacklist instead of
+/// gravity
+const BLACKLIST_RATIO: f64 = 0.1;
+
+/// The options used to generate the simulated data
+#[derive(Clone)]
+pub struct SimulatorOptions {
+    /// The seed for the random numbers. The same seed always generates the
+    /// same clients, domains, upstreams and queries.
+    pub seed: u64,
+    /// How many clients make queries
+    pub clients: usize,
+    /// How many domains are queried. A quarter of them are blocked domains.
+    pub domains: usize,
+    /// How many upstream servers queries are forwarded to
+    pub upstreams: usize,
+    /// How many queries are made each minute
+    pub queries_per_minute: u32,
+    /// The share of queries which are blocked, from 0 to 1
+    pub block_ratio: f64,
+    /// The query types to choose from. A type which is listed more than once
+    /// is chosen more often.
+    pub query_types: Vec<FtlQueryType>
+}
+
+impl Default for SimulatorOptions {
+    fn default() -> Self {
+        SimulatorOptions {
+            seed: 1,
+            clients: 12,
+            domains: 300,
+            upstreams: 2,
+            queries_per_minute: 20,
+            block_ratio: 0.15,
+            query_types: vec![
+                FtlQueryType::A,
+                FtlQueryType::A,
+                FtlQueryType::A,
+                FtlQueryType::AAAA,
+                FtlQueryType::AAAA,
+                FtlQueryType::PTR,
+            ]
+        }
+    }
+}
+
+/// Simulates FTL, so that the API can run without FTL for demos and frontend
+/// development. It generates clients, domains and upstreams, and then a
+/// stream of queries which grows as time passes. The last 24 hours of queries
+/// are generated when the simulator is created.
+///
+/// The simulator is used by both [`FtlMemory::Simulator`] and
+/// [`FtlConnectionType::Simulator`]. New queries are generated when shared
+/// memory is locked.
+///
+/// [`FtlMemory::Simulator`]: enum.FtlMemory.html#variant.Simulator
+/// [`FtlConnectionType::Simulator`]: enum.FtlConnectionType.html#variant.Simulator
+pub struct FtlSimulator {
+    options: SimulatorOptions,
+    /// The time of the first query
+    started: i64,
+    /// The first domains are allowed domains, and the rest are blocked
+    allowed_domains: usize,
+    strings: HashMap<usize, String>,
+    settings: FtlSettings,
+    state: Mutex<SimulatorState>
+}
+
+/// The data which changes as queries are generated
+struct SimulatorState {
+    rng: Rng,
+    clients: Vec<FtlClient>,
+    domains: Vec<FtlDomain>,
+    upstreams: Vec<FtlUpstream>,
+    over_time: Vec<FtlOverTime>,
+    queries: Vec<FtlQuery>,
+    counters: FtlCounters,
+    /// How many queries have been generated since the simulation started
+    generated: u64
+}
+
+impl FtlSimulator {
+    /// Create a simulator with the last 24 hours of queries
+    pub fn new(options: SimulatorOptions) -> FtlSimulator {
+        Self::starting_at(options, current_time())
+    }
+
+    /// Create a simulator with the 24 hours of queries before `now`
+    fn starting_at(options: SimulatorOptions, now: i64) -> FtlSimulator {
+        let mut rng = Rng::new(options.seed);
+        let mut strings = StringsBuilder::new();
+
+        let clients: Vec<FtlClient> = (0..options.clients)
+            .map(|i| {
+                let ip = strings.add(format!("10.0.{}.{}", i / 250, i % 250 + 2));
+
+                // Some clients do not have a hostname
+                let name = if rng.chance(0.75) {
+                    let device = DEVICES[rng.below(DEVICES.len())];
+                    Some(strings.add(format!("{}-{}.lan", device, i + 1)))
+                } else {
+                    None
+                };
+
+                FtlClient::new(0, 0, ip, name)
+            })
+            .collect();
+
+        let blocked_domains = cmp::min(cmp::max(options.domains / 4, 1), options.domains - 1);
+        let allowed_domains = options.domains - blocked_domains;
+        let domains: Vec<FtlDomain> = (0..options.domains)
+            .map(|i| {
+                let prefixes = if i < allowed_domains {
+                    ALLOWED_PREFIXES
+                } else {
+                    BLOCKED_PREFIXES
+                };
+                let prefix = prefixes[rng.below(prefixes.len())];
+                let site = SITES[rng.below(SITES.len())];
+                let tld = TLDS[rng.below(TLDS.len())];
+                let domain = strings.add(format!("{}.{}{}.{}", prefix, site, i + 1, tld));
+
+                FtlDomain::new(0, 0, domain, FtlRegexMatch::Unknown)
+            })
+            .collect();
+
+        let upstreams: Vec<FtlUpstream> = (0..options.upstreams)
+            .map(|i| match UPSTREAMS.get(i) {
+                Some((ip, name)) => {
+                    let ip = strings.add((*ip).to_owned());
+                    FtlUpstream::new(0, 0, ip, Some(strings.add((*name).to_owned())))
+                }
+                None => FtlUpstream::new(0, 0, strings.add(format!("192.0.2.{}", i + 1)), None)
+            })
+            .collect();
+
+        // The history starts 24 hours ago. FTL keeps an extra hour of overTime
+        // slots, which are in the future.
+        let started = now - (MAX_LOG_AGE * 3600) as i64;
+        let first_slot = started - started % OVERTIME_INTERVAL as i64;
+        let over_time = (0..OVERTIME_SLOTS)
+            .map(|i| new_over_time_slot(first_slot + (i * OVERTIME_INTERVAL) as i64))
+            .collect();
+
+        let counters = FtlCounters {
+            total_queries: 0,
+            blocked_queries: 0,
+            cached_queries: 0,
+            unknown_queries: 0,
+            total_upstreams: upstreams.len() as libc::c_int,
+            total_clients: clients.len() as libc::c_int,
+            total_domains: domains.len() as libc::c_int,
+            query_capacity: 0,
+            upstream_capacity: upstreams.len() as libc::c_int,
+            client_capacity: clients.len() as libc::c_int,
+            domain_capacity: domains.len() as libc::c_int,
+            string_capacity: strings.next_pos as libc::c_int,
+            gravity_size: (100_000 + blocked_domains) as libc::c_int,
+            gravity_conf: 0,
+            query_type_counters: [0; 7],
+            forwarded_queries: 0,
+            reply_count_nodata: 0,
+            reply_count_nxdomain: 0,
+            reply_count_cname: 0,
+            reply_count_ip: 0,
+            reply_count_domain: 0
+        };
+
+        let settings = FtlSettings {
+            version: SIMULATOR_SHM_VERSION,
+            global_shm_counter: 0,
+            next_str_pos: strings.next_pos as libc::c_uint
+        };
+
+        let simulator = FtlSimulator {
+            options,
+            started,
+            allowed_domains,
+            strings: strings.map,
+            settings,
+            state: Mutex::new(SimulatorState {
+                rng,
+                clients,
+                domains,
+                upstreams,
+                over_time,
+                queries: Vec::new(),
+                counters,
+                generated: 0
+            })
+        };
+
+        simulator.update_to(now);
+        simulator
+    }
+
+    /// Generate the queries which were made since the last update
+    pub fn update(&self) {
+        self.update_to(current_time());
+    }
+
+    /// Generate the queries which were made up to `now`. Queries are spread
+    /// evenly over time, at the configured rate.
+    fn update_to(&self, now: i64) {
+        let mut state = self.state();
+        let rate = self.options.queries_per_minute as u64;
+
+        // If the simulator was not used for longer than FTL keeps queries,
+        // skip ahead instead of generating queries which would be removed
+        let elapsed = cmp::max(now - self.started, 0) as u64;
+        let oldest_kept = elapsed.saturating_sub((MAX_LOG_AGE * 3600) as u64) * rate / 60;
+        state.generated = cmp::max(state.generated, oldest_kept);
+
+        loop {
+            let timestamp = self.started + (state.generated * 60 / rate) as i64;
+
+            if timestamp > now {
+                break;
+            }
+
+            self.generate_query(&mut state, timestamp);
+            state.generated += 1;
+        }
+    }
+
+    /// Generate a query made at `timestamp` and add it to the data
+    fn generate_query(&self, state: &mut SimulatorState, timestamp: i64) {
+        let time_index = self.over_time_index(state, timestamp);
+        let options = &self.options;
+        let rng = &mut state.rng;
+
+        let client_id = rng.popular(options.clients);
+        let query_type = options.query_types[rng.below(options.query_types.len())];
+
+        let (status, domain_id, upstream_id, response_time) = if rng.chance(options.block_ratio) {
+            let status = if rng.chance(BLACKLIST_RATIO) {
+                FtlQueryStatus::Blacklist
+            } else {
+                FtlQueryStatus::Gravity
+            };
+            let domain_id =
+                self.allowed_domains + rng.popular(options.domains - self.allowed_domains);
+
+            (status, domain_id, 0, 1 + rng.below(10))
+        } else if rng.chance(CACHE_RATIO) {
+            let domain_id = rng.popular(self.allowed_domains);
+
+            (FtlQueryStatus::Cache, domain_id, 0, 1 + rng.below(10))
+        } else {
+            let domain_id = rng.popular(self.allowed_domains);
+            let upstream_id = rng.popular(options.upstreams);
+
+            (
+                FtlQueryStatus::Forward,
+                domain_id,
+                upstream_id,
+                50 + rng.below(1500)
+            )
+        };
+
+        let query = FtlQuery {
+            magic: MAGIC_BYTE,
+            timestamp: timestamp as libc::time_t,
+            time_index: time_index as libc::c_uint,
+            query_type,
+            status,
+            domain_id: domain_id as libc::c_int,
+            client_id: client_id as libc::c_int,
+            upstream_id: upstream_id as libc::c_int,
+            database_id: 0,
+            id: state.generated as libc::c_int,
+            is_complete: true,
+            is_private: false,
+            response_time: response_time as libc::c_ulong,
+            reply_type: FtlQueryReplyType::IP,
+            dnssec_type: FtlDnssecType::Unspecified,
+            ad_bit: false
+        };
+
+        count_query(state, &query, 1);
+
+        let slot = &mut state.over_time[time_index];
+        slot.total_queries += 1;
+        match query.status {
+            FtlQueryStatus::Forward => slot.forwarded_queries += 1,
+            FtlQueryStatus::Cache => slot.cached_queries += 1,
+            _ => slot.blocked_queries += 1
+        }
+        state.clients[client_id].over_time[time_index] += 1;
+
+        state.queries.push(query);
+        state.counters.query_capacity = state.queries.capacity() as libc::c_int;
+    }
+
+    /// Get the overTime slot which `timestamp` is in. If it is past the last
+    /// slot, the oldest slots and their queries are removed to make room, like
+    /// FTL's garbage collection.
+    fn over_time_index(&self, state: &mut SimulatorState, timestamp: i64) -> usize {
+        let first_slot = state.over_time[0].timestamp as i64 - (OVERTIME_INTERVAL / 2) as i64;
+        let index = ((timestamp - first_slot) / OVERTIME_INTERVAL as i64) as usize;
+
+        if index < OVERTIME_SLOTS {
+            return index;
+        }
+
+        let shift = cmp::min(index - OVERTIME_SLOTS + 1, OVERTIME_SLOTS);
+
+        // Remove the queries in the removed slots
+        let removed = state
+            .queries
+            .iter()
+            .take_while(|query| (query.time_index as usize) < shift)
+            .count();
+        let removed: Vec<FtlQuery> = state.queries.drain(..removed).collect();
+
+        for query in &removed {
+            count_query(state, query, -1);
+        }
+
+        for query in &mut state.queries {
+            query.time_index -= shift as libc::c_uint;
+        }
+
+        // Move the slots, and add empty slots at the end
+        let last_slot = first_slot + ((OVERTIME_SLOTS - 1) * OVERTIME_INTERVAL) as i64;
+        state.over_time.drain(..shift);
+        state.over_time.extend(
+            (1..=shift).map(|i| new_over_time_slot(last_slot + (i * OVERTIME_INTERVAL) as i64))
+        );
+
+        for client in &mut state.clients {
+            client.over_time.rotate_left(shift);
+
+            for count in client.over_time[OVERTIME_SLOTS - shift..].iter_mut() {
+                *count = 0;
+            }
+        }
+
+        index - shift
+    }
+
+    /// Lock the simulator's data
+    fn state(&self) -> MutexGuard<SimulatorState> {
+        // Ignore the poison error, because the counts are updated together
+        // with the queries and are still consistent
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get a copy of the clients
+    pub fn clients(&self) -> Vec<FtlClient> {
+        self.state().clients.clone()
+    }
+
+    /// Get a copy of the domains
+    pub fn domains(&self) -> Vec<FtlDomain> {
+        self.state().domains.clone()
+    }
+
+    /// Get a copy of the overTime slots
+    pub fn over_time(&self) -> Vec<FtlOverTime> {
+        self.state().over_time.clone()
+    }
+
+    /// Get a copy of the upstreams
+    pub fn upstreams(&self) -> Vec<FtlUpstream> {
+        self.state().upstreams.clone()
+    }
+
+    /// Get a copy of the queries
+    pub fn queries(&self) -> Vec<FtlQuery> {
+        self.state().queries.clone()
+    }
+
+    /// Get a copy of the counters
+    pub fn counters(&self) -> FtlCounters {
+        self.state().counters
+    }
+
+    /// Get the strings. They do not change after the simulator is created.
+    pub fn strings(&self) -> &HashMap<usize, String> {
+        &self.strings
+    }
+
+    /// Get the settings
+    pub fn settings(&self) -> &FtlSettings {
+        &self.settings
+    }
+
+    /// Get the MessagePack data FTL would send for the socket command. The
+    /// maintenance commands have no output. Like FTL, unknown commands only
+    /// get an EOM.
+    pub fn respond(&self, command: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        // Writing to a Vec can not fail
+        match command {
+            "version" => {
+                for value in &["simulator", "simulator", "simulator", "0000000", ""] {
+                    encode::write_str(&mut data, value).unwrap();
+                }
+            }
+            "dbstats" => {
+                encode::write_sint(&mut data, self.state().generated as i64).unwrap();
+                encode::write_sint(&mut data, 0).unwrap();
+                encode::write_str(&mut data, "simulator").unwrap();
+            }
+            _ => ()
+        }
+
+        // End of message
+        data.push(0xc1);
+        data
+    }
+}
+
+/// Add `change` to the counts affected by the query
+fn count_query(state: &mut SimulatorState, query: &FtlQuery, change: libc::c_int) {
+    let counters = &mut state.counters;
+    counters.total_queries += change;
+    counters.query_type_counters[query.query_type as usize - 1] += change;
+    counters.reply_count_ip += change;
+
+    match query.status {
+        FtlQueryStatus::Forward => {
+            counters.forwarded_queries += change;
+            state.upstreams[query.upstream_id as usize].query_count += change;
+        }
+        FtlQueryStatus::Cache => counters.cached_queries += change,
+        _ => counters.blocked_queries += change
+    }
+
+    let client = &mut state.clients[query.client_id as usize];
+    let domain = &mut state.domains[query.domain_id as usize];
+    client.query_count += change;
+    domain.query_count += change;
+
+    if query.is_blocked() {
+        client.blocked_count += change;
+        domain.blocked_count += change;
+    }
+}
+
+/// Create an empty overTime slot which starts at `start`. FTL uses the
+/// middle of the slot as its timestamp.
+fn new_over_time_slot(start: i64) -> FtlOverTime {
+    FtlOverTime::new(start as usize + OVERTIME_INTERVAL / 2, 0, 0, 0, 0, [0; 7])
+}
+
+/// Get the current Unix timestamp
+fn current_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as i64
+}
+
+/// Builds the string memory, where each string is stored after the previous
+/// string's null terminator
+struct StringsBuilder {
+    map: HashMap<usize, String>,
+    next_pos: usize
+}
+
+impl StringsBuilder {
+    fn new() -> StringsBuilder {
+        StringsBuilder {
+            map: HashMap::new(),
+            // 0 is used as the empty string
+            next_pos: 1
+        }
+    }
+
+    /// Add the string and get its ID
+    fn add(&mut self, string: String) -> usize {
+        let id = self.next_pos;
+        self.next_pos += string.len() + 1;
+        self.map.insert(id, string);
+        id
+    }
+}
+
+/// A small xorshift random number generator. It is not suitable for
+/// cryptography, but it always generates the same numbers for the same seed.
+struct Rng(u64);
+
+impl Rng {
+    /// Create a generator from the seed. The seed is mixed first, so that
+    /// similar seeds (and zero) still give good numbers.
+    fn new(seed: u64) -> Rng {
+        let mut state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        state ^= state >> 31;
+
+        Rng(cmp::max(state, 1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Get a number from 0 (inclusive) to 1 (exclusive)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Get a number from 0 to `n` (exclusive)
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+
+    /// Get a number from 0 to `n` (exclusive), where lower numbers are more
+    /// likely. This makes some clients and domains much more active than
+    /// others, like on a real network.
+    fn popular(&mut self, n: usize) -> usize {
+        let x = self.next_f64();
+        (x * x * n as f64) as usize
+    }
+
+    /// Returns true with the given probability
+    fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+}
+
+/// Device names used for the client hostnames
+const DEVICES: &[&str] = &[
+    "laptop",
+    "desktop",
+    "phone",
+    "tablet",
+    "tv",
+    "printer",
+    "console",
+    "speaker",
+    "camera",
+    "thermostat"
+];
+
+/// Subdomains used for the allowed domains
+const ALLOWED_PREFIXES: &[&str] = &["www", "api", "cdn", "mail", "static", "img", "login"];
+
+/// Subdomains used for the blocked domains
+const BLOCKED_PREFIXES: &[&str] = &[
+    "ads",
+    "tracker",
+    "telemetry",
+    "metrics",
+    "pixel",
+    "analytics"
+];
+
+/// Site names used for the domains
+const SITES: &[&str] = &[
+    "example", "news", "video", "shop", "social", "search", "weather", "music", "maps", "cloud"
+];
+
+/// Top level domains used for the domains
+const TLDS: &[&str] = &["com", "net", "org", "io"];
+
+/// Public DNS servers used for the first upstreams
+const UPSTREAMS: &[(&str, &str)] = &[
+    ("8.8.8.8", "google-public-dns-a.google.com"),
+    ("1.1.1.1", "one.one.one.one"),
+    ("9.9.9.9", "dns9.quad9.net"),
+    ("8.8.4.4", "google-public-dns-b.google.com"),
+    ("1.0.0.1", "one.one.one.one")
+];
+
+#[cfg(test)]
+mod test {
+    use super::{FtlSimulator, SimulatorOptions};
+    use crate::ftl::{FtlConnectionType, FtlQueryStatus, FtlValue, MAX_LOG_AGE, OVERTIME_SLOTS};
+    use std::sync::Arc;
+
+    /// The time the simulations end at
+    const NOW: i64 = 1_550_000_000;
+
+    /// Simulate 60 queries per minute
+    fn options() -> SimulatorOptions {
+        SimulatorOptions {
+            queries_per_minute: 60,
+            ..SimulatorOptions::default()
+        }
+    }
+
+    /// The same seed generates the same data
+    #[test]
+    fn reproducible() {
+        let first = FtlSimulator::starting_at(options(), NOW);
+        let second = FtlSimulator::starting_at(options(), NOW);
+        let other_seed = FtlSimulator::starting_at(
+            SimulatorOptions {
+                seed: 2,
+                ..options()
+            },
+            NOW
+        );
+
+        assert_eq!(first.queries(), second.queries());
+        assert_eq!(first.strings(), second.strings());
+        assert_ne!(first.queries(), other_seed.queries());
+    }
+
+    /// The last 24 hours of queries are generated, with the counters matching
+    /// the queries
+    #[test]
+    fn history() {
+        let simulator = FtlSimulator::starting_at(options(), NOW);
+        let queries = simulator.queries();
+        let counters = simulator.counters();
+
+        assert_eq!(queries.len(), MAX_LOG_AGE * 3600 + 1);
+        assert_eq!(counters.total_queries as usize, queries.len());
+        assert_eq!(
+            counters.blocked_queries as usize,
+            queries.iter().filter(|query| query.is_blocked()).count()
+        );
+        assert_eq!(
+            counters.cached_queries as usize,
+            queries
+                .iter()
+                .filter(|query| query.status == FtlQueryStatus::Cache)
+                .count()
+        );
+        assert_eq!(queries.last().unwrap().timestamp as i64, NOW);
+
+        // The block ratio is respected
+        let ratio = counters.blocked_queries as f64 / counters.total_queries as f64;
+        assert!((ratio - 0.15).abs() < 0.01);
+
+        // The client counts add up to the total
+        let client_total: i32 = simulator
+            .clients()
+            .iter()
+            .map(|client| client.query_count)
+            .sum();
+        assert_eq!(client_total, counters.total_queries);
+    }
+
+    /// New queries are generated as time passes, and queries older than 24
+    /// hours are removed with their overTime slots
+    #[test]
+    fn update() {
+        let simulator = FtlSimulator::starting_at(options(), NOW);
+        let first_slot = simulator.over_time()[0].timestamp;
+
+        simulator.update_to(NOW + 2 * 3600);
+
+        let queries = simulator.queries();
+        let over_time = simulator.over_time();
+        let counters = simulator.counters();
+
+        assert_eq!(queries.last().unwrap().timestamp as i64, NOW + 2 * 3600);
+        assert!(queries[0].timestamp as i64 >= NOW + 3600 - MAX_LOG_AGE as i64 * 3600);
+        assert_eq!(counters.total_queries as usize, queries.len());
+        assert_eq!(over_time.len(), OVERTIME_SLOTS);
+        assert!(over_time[0].timestamp > first_slot);
+
+        // The queries point to the slots they were made in
+        for query in queries {
+            let slot = &over_time[query.time_index as usize];
+            assert!((slot.timestamp - query.timestamp).abs() <= 300);
+        }
+
+        // The slot totals add up to the total
+        let slot_total: i32 = over_time.iter().map(|slot| slot.total_queries).sum();
+        assert_eq!(slot_total, counters.total_queries);
+    }
+
+    /// The simulated socket answers commands with MessagePack data
+    #[test]
+    fn socket() {
+        let simulator = Arc::new(FtlSimulator::starting_at(options(), NOW));
+        let ftl = FtlConnectionType::Simulator(simulator);
+
+        assert_eq!(
+            ftl.connect("dbstats").unwrap().read_to_eom().unwrap(),
+            vec![
+                FtlValue::UInt(MAX_LOG_AGE as u64 * 3600 + 1),
+                FtlValue::UInt(0),
+                FtlValue::Str("simulator".to_owned()),
+            ]
+        );
+        assert!(ftl
+            .connect("recompile-regex")
+            .unwrap()
+            .read_to_eom()
+            .unwrap()
+            .is_empty());
+    }
+}
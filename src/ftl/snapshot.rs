@@ -0,0 +1,135 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Shared Memory Snapshots
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::{
+        FtlClient, FtlCounters, FtlDomain, FtlMemory, FtlOverTime, FtlQuery, FtlRecord, FtlRecords,
+        FtlSettings, FtlUpstream, Validator
+    },
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use libc;
+use std::{cmp, collections::HashMap, fs::File, io::BufReader};
+
+/// A copy of FTL's shared memory, which can be saved and loaded later with
+/// [`FtlMemory::Snapshot`]. Only the used parts of shared memory are copied.
+///
+/// [`FtlMemory::Snapshot`]: enum.FtlMemory.html#variant.Snapshot
+#[derive(Serialize, Deserialize)]
+pub struct FtlSnapshot {
+    pub clients: Vec<FtlClient>,
+    pub domains: Vec<FtlDomain>,
+    pub over_time: Vec<FtlOverTime>,
+    pub upstreams: Vec<FtlUpstream>,
+    pub queries: Vec<FtlQuery>,
+    pub strings: HashMap<usize, String>,
+    pub counters: FtlCounters,
+    pub settings: FtlSettings
+}
+
+impl FtlSnapshot {
+    /// Copy the data in shared memory. Invalid queries are left out, since
+    /// nothing references queries by their position. Other records are
+    /// referenced by position, so they are copied as they are. If one of them
+    /// is not intact, the snapshot can not be taken.
+    pub fn capture(ftl_memory: &FtlMemory) -> Result<FtlSnapshot, Error> {
+        let lock = ftl_memory.lock()?;
+        let mut counters = **ftl_memory.counters(&lock)?;
+        let settings = **ftl_memory.settings(&lock)?;
+
+        let validator = Validator::new(ftl_memory, &lock)?;
+        let queries: Vec<FtlQuery> = validator
+            .queries(&ftl_memory.queries(&lock)?)
+            .cloned()
+            .collect();
+        counters.total_queries = queries.len() as libc::c_int;
+
+        Ok(FtlSnapshot {
+            clients: used(&ftl_memory.clients(&lock)?, counters.total_clients)?,
+            domains: used(&ftl_memory.domains(&lock)?, counters.total_domains)?,
+            over_time: ftl_memory.over_time(&lock)?.to_vec(),
+            upstreams: used(&ftl_memory.upstreams(&lock)?, counters.total_upstreams)?,
+            queries,
+            strings: ftl_memory
+                .strings(&lock)?
+                .to_map(settings.next_str_pos as usize),
+            counters,
+            settings
+        })
+    }
+
+    /// Load a snapshot from a file
+    pub fn load(path: &str) -> Result<FtlSnapshot, Error> {
+        let file = File::open(path).context(ErrorKind::FileRead(path.to_owned()))?;
+        let snapshot = serde_json::from_reader(BufReader::new(file))
+            .context(ErrorKind::FileRead(path.to_owned()))?;
+
+        Ok(snapshot)
+    }
+}
+
+/// Copy the first `count` items, which are the ones in use
+fn used<T: FtlRecord + Copy>(items: &FtlRecords<T>, count: libc::c_int) -> Result<Vec<T>, Error> {
+    (0..cmp::min(count.max(0) as usize, items.len()))
+        .map(|id| {
+            items
+                .get(id)
+                .cloned()
+                .ok_or_else(|| Error::from(ErrorKind::SharedMemoryRead))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::FtlSnapshot;
+    use crate::{
+        ftl::{FtlMemory, FtlRecord, FtlRecords},
+        routes::stats::history::testing::test_memory
+    };
+    use std::sync::Arc;
+
+    /// Copy all of the records so they can be compared
+    fn all<T: FtlRecord + Copy>(records: FtlRecords<T>) -> Vec<T> {
+        (0..records.len()).map(|id| records[id]).collect()
+    }
+
+    /// A snapshot loaded from JSON has the same data as the original memory
+    #[test]
+    fn round_trip() {
+        let original = test_memory();
+        let json = serde_json::to_string(&FtlSnapshot::capture(&original).unwrap()).unwrap();
+        let snapshot = FtlMemory::Snapshot(Arc::new(serde_json::from_str(&json).unwrap()));
+
+        let original_lock = original.lock().unwrap();
+        let snapshot_lock = snapshot.lock().unwrap();
+
+        assert_eq!(
+            all(snapshot.queries(&snapshot_lock).unwrap()),
+            all(original.queries(&original_lock).unwrap())
+        );
+        assert_eq!(
+            all(snapshot.domains(&snapshot_lock).unwrap()),
+            all(original.domains(&original_lock).unwrap())
+        );
+        assert_eq!(
+            all(snapshot.clients(&snapshot_lock).unwrap()),
+            all(original.clients(&original_lock).unwrap())
+        );
+        assert_eq!(
+            snapshot.counters(&snapshot_lock).unwrap().total_queries,
+            original.counters(&original_lock).unwrap().total_queries
+        );
+
+        let strings = snapshot.strings(&snapshot_lock).unwrap();
+        assert_eq!(strings.get_str(1), Some("domain1.com"));
+    }
+}
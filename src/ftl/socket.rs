@@ -8,36 +8,95 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::util::{Error, ErrorKind};
+use crate::{
+    ftl::{
+        decode::{self as ftl_decode, DecodeError, FtlDecode, FtlResponse, FtlValue},
+        FtlSimulator
+    },
+    util::{Error, ErrorKind}
+};
 use failure::{Fail, ResultExt};
 use rmp::{
     decode::{self, DecodeStringError, ValueReadError},
     Marker
 };
 use std::{
-    io::{prelude::*, BufReader},
-    os::unix::net::UnixStream
+    io::{self, prelude::*, BufReader, Cursor},
+    os::unix::net::UnixStream,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration
 };
 
 #[cfg(test)]
 use std::collections::HashMap;
-#[cfg(test)]
-use std::io::Cursor;
 
-/// The location of the FTL socket
-const SOCKET_LOCATION: &str = "/var/run/pihole/FTL.sock";
+/// The default location of the FTL socket
+pub const DEFAULT_SOCKET_LOCATION: &str = "/var/run/pihole/FTL.sock";
+
+/// How long to wait before the first retry. Each retry after that waits twice
+/// as long as the previous one.
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// The options used to connect to FTL's socket
+#[derive(Clone)]
+pub struct SocketOptions {
+    /// The location of the socket
+    pub path: String,
+    /// How long to wait for a connection to be accepted
+    pub connect_timeout: Duration,
+    /// How long to wait for data from FTL before giving up
+    pub read_timeout: Duration,
+    /// How many times to try connecting again if the connection fails
+    pub retries: u32,
+    /// How many idle connections to keep for reuse
+    pub pool_size: usize
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            path: DEFAULT_SOCKET_LOCATION.to_owned(),
+            connect_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(5),
+            retries: 2,
+            pool_size: 4
+        }
+    }
+}
+
+/// Connects to FTL's socket. Connections whose response was read completely
+/// are kept in a pool and reused for later commands.
+pub struct FtlSocket {
+    options: SocketOptions,
+    pool: Mutex<Vec<UnixStream>>
+}
 
-/// A wrapper around the FTL socket to easily read in data. It takes a
-/// Box<Read> so that it can be tested with fake data from a Vec<u8>
-pub struct FtlConnection<'test>(Box<dyn Read + 'test>);
+/// A wrapper around the FTL socket to easily read in data. In tests and with
+/// the simulator, the data is read from a Vec<u8> instead.
+pub struct FtlConnection<'a> {
+    stream: Option<FtlStream>,
+    socket: Option<&'a FtlSocket>,
+    complete: bool
+}
+
+/// The source of the data read by a `FtlConnection`
+enum FtlStream {
+    Socket(BufReader<UnixStream>),
+    Simulator(Cursor<Vec<u8>>),
+    #[cfg(test)]
+    Test(Cursor<Vec<u8>>)
+}
 
 /// A marker for the type of FTL connection to make.
 ///
 /// - Socket refers to the normal Unix socket connection.
+/// - Simulator answers commands with the simulator's data, without FTL.
 /// - Test is for testing, so that a test can pass in arbitrary MessagePack
 /// data to be processed.   The map in Test maps FTL commands to data.
 pub enum FtlConnectionType {
-    Socket,
+    Socket(FtlSocket),
+    Simulator(Arc<FtlSimulator>),
     #[cfg(test)]
     Test(HashMap<String, Vec<u8>>)
 }
@@ -47,60 +106,265 @@ impl FtlConnectionType {
     pub fn connect(&self, command: &str) -> Result<FtlConnection, Error> {
         // Determine the type of connection to create
         match *self {
-            FtlConnectionType::Socket => {
-                // Try to connect to FTL
-                let mut stream = match UnixStream::connect(SOCKET_LOCATION) {
-                    Ok(s) => s,
-                    Err(_) => return Err(Error::from(ErrorKind::FtlConnectionFail))
-                };
-
-                // Send the command
-                stream
-                    .write_all(format!(">{}\n", command).as_bytes())
-                    .context(ErrorKind::FtlConnectionFail)?;
-
-                // Return the connection so the API can read the response
-                Ok(FtlConnection(Box::new(BufReader::new(stream))))
-            }
+            FtlConnectionType::Socket(ref socket) => socket.connect(command),
+            FtlConnectionType::Simulator(ref simulator) => Ok(FtlConnection {
+                stream: Some(FtlStream::Simulator(Cursor::new(
+                    simulator.respond(command)
+                ))),
+                socket: None,
+                complete: false
+            }),
             #[cfg(test)]
             FtlConnectionType::Test(ref map) => {
+                // Try to get the testing data for this command
+                let data = match map.get(command) {
+                    Some(data) => data,
+                    None => return Err(Error::from(ErrorKind::FtlConnectionFail))
+                };
+
                 // Return a connection reading the testing data
-                Ok(FtlConnection(Box::new(Cursor::new(
-                    // Try to get the testing data for this command
-                    match map.get(command) {
-                        Some(data) => data,
-                        None => return Err(Error::from(ErrorKind::FtlConnectionFail))
-                    }
-                ))))
+                Ok(FtlConnection {
+                    stream: Some(FtlStream::Test(Cursor::new(data.clone()))),
+                    socket: None,
+                    complete: false
+                })
             }
         }
     }
 }
 
-impl<'test> FtlConnection<'test> {
-    fn handle_eom_value<T>(result: Result<T, ValueReadError>) -> Result<T, Error> {
-        result.map_err(|e| {
-            if let ValueReadError::TypeMismatch(marker) = e {
-                if marker == Marker::Reserved {
-                    // Received EOM
-                    return Error::from(e.context(ErrorKind::FtlEomError));
-                }
+impl FtlSocket {
+    /// Create a socket connector with the options. No connection is made
+    /// until a command is run.
+    pub fn new(options: SocketOptions) -> FtlSocket {
+        FtlSocket {
+            options,
+            pool: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Send the command to FTL, using a pooled connection if one is available.
+    /// New connections are retried with exponential backoff.
+    fn connect(&self, command: &str) -> Result<FtlConnection, Error> {
+        let message = format!(">{}\n", command);
+
+        // Try the idle connections first. If FTL closed them, they are
+        // discarded.
+        while let Some(stream) = self.take_pooled() {
+            if let Some(connection) = self.send_pooled(stream, &message)? {
+                return Ok(connection);
             }
+        }
 
-            Error::from(e.context(ErrorKind::FtlReadError))
-        })
+        let mut attempt = 0;
+
+        loop {
+            let error = match self.open() {
+                Ok(mut stream) => match stream.write_all(message.as_bytes()) {
+                    Ok(_) => return Ok(self.wrap(BufReader::new(stream))),
+                    Err(e) => io_error(e, ErrorKind::FtlConnectionFail)
+                },
+                Err(e) => e
+            };
+
+            if attempt >= self.options.retries {
+                return Err(error);
+            }
+
+            thread::sleep(BASE_RETRY_DELAY * 2u32.pow(attempt));
+            attempt += 1;
+        }
+    }
+
+    /// Open a new connection to FTL with the configured timeouts
+    fn open(&self) -> Result<UnixStream, Error> {
+        let stream = connect_unix(&self.options.path, self.options.connect_timeout)?;
+
+        stream
+            .set_read_timeout(Some(self.options.read_timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.options.read_timeout)))
+            .context(ErrorKind::FtlConnectionFail)?;
+
+        Ok(stream)
     }
 
-    fn handle_eom_str<T>(result: Result<T, DecodeStringError>) -> Result<T, Error> {
-        result.map_err(|e| {
-            if let DecodeStringError::TypeMismatch(ref marker) = e {
-                if *marker == Marker::Reserved {
-                    // Received EOM
-                    return Error::from(ErrorKind::FtlEomError);
-                }
+    /// Send the message over a pooled connection and wait for the response to
+    /// start. FTL may close the connection after it was checked, in which case
+    /// the write can still succeed and the failure only shows up when reading.
+    /// If the connection fails before any of the response arrives, the
+    /// command was not run, so `None` is returned and another connection can
+    /// be tried. A timeout is still an error, because FTL may be running the
+    /// command.
+    fn send_pooled(
+        &self,
+        mut stream: UnixStream,
+        message: &str
+    ) -> Result<Option<FtlConnection>, Error> {
+        if stream.write_all(message.as_bytes()).is_err() {
+            return Ok(None);
+        }
+
+        let mut reader = BufReader::new(stream);
+        let started = match reader.fill_buf() {
+            Ok(buffer) => !buffer.is_empty(),
+            Err(ref e) if !is_timeout(e) => false,
+            Err(e) => return Err(io_error(e, ErrorKind::FtlReadError))
+        };
+
+        if started {
+            Ok(Some(self.wrap(reader)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Wrap the reader in a connection which returns it to this pool
+    fn wrap(&self, reader: BufReader<UnixStream>) -> FtlConnection {
+        FtlConnection {
+            stream: Some(FtlStream::Socket(reader)),
+            socket: Some(self),
+            complete: false
+        }
+    }
+
+    /// Take an idle connection from the pool. Connections which FTL has closed
+    /// or which have unexpected data waiting are discarded.
+    fn take_pooled(&self) -> Option<UnixStream> {
+        let mut pool = self.pool.lock().unwrap();
+
+        while let Some(mut stream) = pool.pop() {
+            if is_idle(&mut stream) {
+                return Some(stream);
             }
+        }
+
+        None
+    }
+
+    /// Return a connection to the pool, if there is room
+    fn release(&self, stream: UnixStream) {
+        let mut pool = self.pool.lock().unwrap();
+
+        if pool.len() < self.options.pool_size {
+            pool.push(stream);
+        }
+    }
+}
+
+/// Connect to FTL's Unix socket. The standard library can not time out a
+/// Unix socket connection attempt, so it is made on another thread. If the
+/// attempt hangs, the thread finishes when the attempt does.
+fn connect_unix(path: &str, timeout: Duration) -> Result<UnixStream, Error> {
+    let (sender, receiver) = mpsc::channel();
+    let path = path.to_owned();
+
+    thread::spawn(move || {
+        // The receiver is gone if the attempt timed out
+        let _ = sender.send(UnixStream::connect(path));
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(stream)) => Ok(stream),
+        Ok(Err(e)) => Err(io_error(e, ErrorKind::FtlConnectionFail)),
+        Err(_) => Err(Error::from(ErrorKind::FtlTimeout))
+    }
+}
+
+/// Check if the connection is still open and has no data waiting to be read
+fn is_idle(stream: &mut UnixStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    // A closed connection reads 0 bytes, while an open connection with no
+    // data would block
+    let idle = match stream.read(&mut [0u8; 1]) {
+        Err(ref e) => e.kind() == io::ErrorKind::WouldBlock,
+        Ok(_) => false
+    };
+
+    idle && stream.set_nonblocking(false).is_ok()
+}
+
+/// Check if the IO error was caused by a timeout. Timeouts are reported as
+/// `WouldBlock` on Unix.
+fn is_timeout(error: &io::Error) -> bool {
+    match error.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => true,
+        _ => false
+    }
+}
+
+/// Convert an IO error into an error of `kind`, or
+/// [`ErrorKind::FtlTimeout`] if it was a timeout
+///
+/// [`ErrorKind::FtlTimeout`]: ../util/enum.ErrorKind.html#variant.FtlTimeout
+fn io_error(error: io::Error, kind: ErrorKind) -> Error {
+    if is_timeout(&error) {
+        Error::from(error.context(ErrorKind::FtlTimeout))
+    } else {
+        Error::from(error.context(kind))
+    }
+}
 
-            Error::from(ErrorKind::FtlReadError)
+impl Read for FtlStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FtlStream::Socket(reader) => reader.read(buf),
+            FtlStream::Simulator(cursor) => cursor.read(buf),
+            #[cfg(test)]
+            FtlStream::Test(cursor) => cursor.read(buf)
+        }
+    }
+}
+
+impl<'a> Drop for FtlConnection<'a> {
+    /// Return the connection to the pool if the whole response was read.
+    /// Otherwise, the rest of the response would be read by the next command.
+    fn drop(&mut self) {
+        if !self.complete {
+            return;
+        }
+
+        if let (Some(socket), Some(FtlStream::Socket(reader))) = (self.socket, self.stream.take()) {
+            socket.release(reader.into_inner());
+        }
+    }
+}
+
+impl<'a> FtlConnection<'a> {
+    /// Get the stream to read from
+    fn stream(&mut self) -> &mut FtlStream {
+        self.stream
+            .as_mut()
+            .expect("FTL connection used after being closed")
+    }
+
+    fn handle_eom_value<T>(&mut self, result: Result<T, ValueReadError>) -> Result<T, Error> {
+        result.map_err(|e| match e {
+            ValueReadError::TypeMismatch(Marker::Reserved) => {
+                // Received EOM
+                self.complete = true;
+                Error::from(e.context(ErrorKind::FtlEomError))
+            }
+            ValueReadError::InvalidMarkerRead(e) | ValueReadError::InvalidDataRead(e) => {
+                io_error(e, ErrorKind::FtlReadError)
+            }
+            e => Error::from(e.context(ErrorKind::FtlReadError))
+        })
+    }
+
+    fn handle_eom_str<T>(&mut self, result: Result<T, DecodeStringError>) -> Result<T, Error> {
+        result.map_err(|e| match e {
+            DecodeStringError::TypeMismatch(Marker::Reserved) => {
+                // Received EOM
+                self.complete = true;
+                Error::from(ErrorKind::FtlEomError)
+            }
+            DecodeStringError::InvalidMarkerRead(e) | DecodeStringError::InvalidDataRead(e) => {
+                io_error(e, ErrorKind::FtlReadError)
+            }
+            _ => Error::from(ErrorKind::FtlReadError)
         })
     }
 
@@ -110,9 +374,8 @@ impl<'test> FtlConnection<'test> {
         let mut buffer: [u8; 1] = [0];
 
         // Read exactly 1 byte
-        match self.0.read_exact(&mut buffer) {
-            Ok(_) => (),
-            Err(e) => return Err(Error::from(e.context(ErrorKind::FtlReadError)))
+        if let Err(e) = self.stream().read_exact(&mut buffer) {
+            return Err(io_error(e, ErrorKind::FtlReadError));
         }
 
         // Check if it was the EOM byte
@@ -120,21 +383,204 @@ impl<'test> FtlConnection<'test> {
             return Err(Error::from(ErrorKind::FtlReadError));
         }
 
+        self.complete = true;
         Ok(())
     }
 
+    /// Read in the next value, whatever its type
+    pub fn read_value(&mut self) -> Result<FtlValue, Error> {
+        let result = ftl_decode::read_value(self.stream());
+
+        result.map_err(|e| match e {
+            DecodeError::Eom => {
+                // Received EOM
+                self.complete = true;
+                Error::from(ErrorKind::FtlEomError)
+            }
+            DecodeError::Io(e) => io_error(e, ErrorKind::FtlReadError),
+            DecodeError::Invalid => Error::from(ErrorKind::FtlReadError)
+        })
+    }
+
+    /// Read in the next value as a `T`. A value of a different type is a read
+    /// error.
+    pub fn read<T: FtlDecode>(&mut self) -> Result<T, Error> {
+        T::from_value(self.read_value()?).ok_or_else(|| Error::from(ErrorKind::FtlReadError))
+    }
+
+    /// Read in values until the EOM
+    pub fn read_to_eom(&mut self) -> Result<Vec<FtlValue>, Error> {
+        let mut values = Vec::new();
+
+        loop {
+            match self.read_value() {
+                Ok(value) => values.push(value),
+                Err(ref e) if e.kind() == ErrorKind::FtlEomError => return Ok(values),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Read in a response with a declared layout, followed by an EOM
+    pub fn read_response<T: FtlResponse>(&mut self) -> Result<T, Error> {
+        let response = T::read_values(self)?;
+        self.expect_eom()?;
+
+        Ok(response)
+    }
+
     /// Read in an i32 (signed int) value
     pub fn read_i32(&mut self) -> Result<i32, Error> {
-        FtlConnection::handle_eom_value(decode::read_i32(&mut self.0))
+        let result = decode::read_i32(self.stream());
+        self.handle_eom_value(result)
     }
 
     /// Read in an i64 (signed long int) value
     pub fn read_i64(&mut self) -> Result<i64, Error> {
-        FtlConnection::handle_eom_value(decode::read_i64(&mut self.0))
+        let result = decode::read_i64(self.stream());
+        self.handle_eom_value(result)
     }
 
     /// Read in a string using the buffer
-    pub fn read_str<'a>(&mut self, buffer: &'a mut [u8]) -> Result<&'a str, Error> {
-        FtlConnection::handle_eom_str(decode::read_str(&mut self.0, buffer))
+    pub fn read_str<'b>(&mut self, buffer: &'b mut [u8]) -> Result<&'b str, Error> {
+        let result = decode::read_str(self.stream(), buffer);
+        self.handle_eom_str(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FtlSocket, SocketOptions};
+    use crate::{testing::write_eom, util::ErrorKind};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        thread,
+        time::Duration
+    };
+    use tempfile::TempDir;
+
+    /// Create socket options for connecting to the socket in the directory
+    fn options(dir: &TempDir) -> SocketOptions {
+        SocketOptions {
+            path: socket_path(dir),
+            connect_timeout: Duration::from_millis(500),
+            read_timeout: Duration::from_millis(100),
+            retries: 1,
+            pool_size: 1
+        }
+    }
+
+    /// Get the location of a socket in the directory
+    fn socket_path(dir: &TempDir) -> String {
+        dir.path().join("FTL.sock").to_str().unwrap().to_owned()
+    }
+
+    /// Respond to each `>recompile-regex` command with an EOM
+    fn respond_with_eom(stream: UnixStream, mut writer: UnixStream) {
+        for line in BufReader::new(stream).lines() {
+            assert_eq!(line.unwrap(), ">recompile-regex");
+
+            let mut data = Vec::new();
+            write_eom(&mut data);
+            writer.write_all(&data).unwrap();
+        }
+    }
+
+    /// Connections are reused after the response was read
+    #[test]
+    fn reuse_connection() {
+        let dir = TempDir::new().unwrap();
+        let listener = UnixListener::bind(socket_path(&dir)).unwrap();
+
+        // Only accept a single connection
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let writer = stream.try_clone().unwrap();
+            respond_with_eom(stream, writer);
+        });
+
+        let socket = FtlSocket::new(options(&dir));
+
+        for _ in 0..3 {
+            socket
+                .connect("recompile-regex")
+                .unwrap()
+                .expect_eom()
+                .unwrap();
+        }
+    }
+
+    /// If a reused connection is closed before the response starts, the command
+    /// is sent again over a new connection
+    #[test]
+    fn retry_closed_connection() {
+        let dir = TempDir::new().unwrap();
+        let listener = UnixListener::bind(socket_path(&dir)).unwrap();
+
+        thread::spawn(move || {
+            let mut data = Vec::new();
+            write_eom(&mut data);
+
+            // Answer the first command, then close the connection after
+            // receiving the second command
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+
+            assert_eq!(lines.next().unwrap().unwrap(), ">recompile-regex");
+            writer.write_all(&data).unwrap();
+            assert_eq!(lines.next().unwrap().unwrap(), ">recompile-regex");
+            drop(lines);
+            drop(writer);
+
+            // Answer the retried command on a new connection
+            let (stream, _) = listener.accept().unwrap();
+            let writer = stream.try_clone().unwrap();
+            respond_with_eom(stream, writer);
+        });
+
+        let socket = FtlSocket::new(options(&dir));
+
+        for _ in 0..2 {
+            socket
+                .connect("recompile-regex")
+                .unwrap()
+                .expect_eom()
+                .unwrap();
+        }
+    }
+
+    /// A hung FTL results in a timeout error
+    #[test]
+    fn read_timeout() {
+        let dir = TempDir::new().unwrap();
+        let listener = UnixListener::bind(socket_path(&dir)).unwrap();
+
+        // Accept the connection, but never respond
+        thread::spawn(move || {
+            let _stream = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+
+        let socket = FtlSocket::new(options(&dir));
+        let mut connection = socket.connect("stats").unwrap();
+
+        assert_eq!(
+            connection.read_i32().unwrap_err().kind(),
+            ErrorKind::FtlTimeout
+        );
+    }
+
+    /// A missing socket fails after retrying
+    #[test]
+    fn connection_fail() {
+        let dir = TempDir::new().unwrap();
+        let socket = FtlSocket::new(options(&dir));
+
+        assert_eq!(
+            socket.connect("stats").err().unwrap().kind(),
+            ErrorKind::FtlConnectionFail
+        );
     }
 }
@@ -0,0 +1,488 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Shared Memory Validation
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::{
+        FtlClient, FtlDomain, FtlMemory, FtlQuery, FtlQueryStatus, FtlRecord, FtlRecords,
+        FtlUpstream, ShmLockGuard, OVERTIME_SLOTS
+    },
+    util::Error
+};
+use libc;
+use std::{
+    cmp,
+    collections::HashSet,
+    sync::atomic::{AtomicUsize, Ordering}
+};
+
+/// Counts how many times records in shared memory were skipped because they
+/// were invalid, by record type
+#[derive(Default)]
+pub struct InvalidRecords {
+    queries: AtomicUsize,
+    clients: AtomicUsize,
+    domains: AtomicUsize,
+    upstreams: AtomicUsize
+}
+
+impl InvalidRecords {
+    /// Get the current counts
+    pub fn counts(&self) -> InvalidRecordCounts {
+        InvalidRecordCounts {
+            queries: self.queries.load(Ordering::Relaxed),
+            clients: self.clients.load(Ordering::Relaxed),
+            domains: self.domains.load(Ordering::Relaxed),
+            upstreams: self.upstreams.load(Ordering::Relaxed)
+        }
+    }
+}
+
+/// A copy of the [`InvalidRecords`] counts
+///
+/// [`InvalidRecords`]: struct.InvalidRecords.html
+#[derive(Copy, Clone, Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct InvalidRecordCounts {
+    pub queries: usize,
+    pub clients: usize,
+    pub domains: usize,
+    pub upstreams: usize
+}
+
+/// The number of records of each type which are in use, and the IDs of the
+/// records in use which are invalid
+#[derive(Clone, Default)]
+struct Limits {
+    queries: usize,
+    clients: usize,
+    domains: usize,
+    upstreams: usize,
+    strings: usize,
+    invalid_clients: HashSet<usize>,
+    invalid_domains: HashSet<usize>,
+    invalid_upstreams: HashSet<usize>
+}
+
+impl Limits {
+    /// Check that the query has the magic byte and only references valid
+    /// records which are in use. The upstream is only checked if the query was
+    /// forwarded.
+    fn is_valid_query(&self, query: &FtlQuery) -> bool {
+        query.is_valid()
+            && is_valid_id(query.domain_id, self.domains, &self.invalid_domains)
+            && is_valid_id(query.client_id, self.clients, &self.invalid_clients)
+            && (query.status != FtlQueryStatus::Forward
+                || is_valid_id(query.upstream_id, self.upstreams, &self.invalid_upstreams))
+            && (query.time_index as usize) < OVERTIME_SLOTS
+    }
+}
+
+/// Reads records out of shared memory, skipping the ones which are not in use.
+/// All access to the records goes through a validator. When shared memory is
+/// read from FTL, the records are also validated before they are used, and
+/// invalid records are skipped and counted. A corrupt record would otherwise
+/// cause a panic or garbage output. Queries which reference invalid records
+/// are skipped too, so the records a valid query references can be indexed.
+///
+/// Snapshot, simulator, and test data is trusted, since it did not come
+/// directly from FTL.
+pub struct Validator<'a> {
+    limits: Limits,
+    invalid: Option<&'a InvalidRecords>
+}
+
+impl<'a> Validator<'a> {
+    /// Create a validator for the shared memory which is currently locked
+    pub fn new(ftl_memory: &'a FtlMemory, lock: &ShmLockGuard) -> Result<Validator<'a>, Error> {
+        let counters = ftl_memory.counters(lock)?;
+        let settings = ftl_memory.settings(lock)?;
+        let mut limits = Limits {
+            queries: used(counters.total_queries),
+            clients: used(counters.total_clients),
+            domains: used(counters.total_domains),
+            upstreams: used(counters.total_upstreams),
+            strings: settings.next_str_pos as usize,
+            ..Limits::default()
+        };
+
+        match ftl_memory {
+            FtlMemory::Production { invalid, .. } => {
+                // The counters may be corrupt too, so do not let them point
+                // past the end of the arrays
+                limits.strings = cmp::min(
+                    limits.strings,
+                    ftl_memory.strings(lock)?.size().unwrap_or_default()
+                );
+                limits.queries = cmp::min(limits.queries, ftl_memory.queries(lock)?.len());
+
+                let strings = limits.strings;
+                let clients = ftl_memory.clients(lock)?;
+                let domains = ftl_memory.domains(lock)?;
+                let upstreams = ftl_memory.upstreams(lock)?;
+
+                limits.clients = cmp::min(limits.clients, clients.len());
+                limits.domains = cmp::min(limits.domains, domains.len());
+                limits.upstreams = cmp::min(limits.upstreams, upstreams.len());
+
+                // Find the invalid records which queries may reference
+                limits.invalid_clients =
+                    invalid_ids(&clients, limits.clients, |client| client.is_valid(strings));
+                limits.invalid_domains =
+                    invalid_ids(&domains, limits.domains, |domain| domain.is_valid(strings));
+                limits.invalid_upstreams = invalid_ids(&upstreams, limits.upstreams, |upstream| {
+                    upstream.is_valid(strings)
+                });
+
+                Ok(Validator {
+                    limits,
+                    invalid: Some(invalid)
+                })
+            }
+            _ => Ok(Validator {
+                limits,
+                invalid: None
+            })
+        }
+    }
+
+    /// Get the valid queries which are in use, oldest first
+    pub fn queries<'b>(
+        &self,
+        queries: &'b FtlRecords<'b, FtlQuery>
+    ) -> impl DoubleEndedIterator<Item = &'b FtlQuery> + 'b
+    where
+        'a: 'b
+    {
+        let limits = self.limits.clone();
+
+        filter(
+            queries,
+            limits.queries,
+            self.invalid.map(|invalid| &invalid.queries),
+            move |query| limits.is_valid_query(query)
+        )
+        .map(|(_, query)| query)
+    }
+
+    /// Get the valid clients which are in use
+    pub fn clients<'b>(
+        &self,
+        clients: &'b FtlRecords<'b, FtlClient>
+    ) -> impl DoubleEndedIterator<Item = &'b FtlClient> + 'b
+    where
+        'a: 'b
+    {
+        self.clients_with_ids(clients).map(|(_, client)| client)
+    }
+
+    /// Get the valid clients which are in use, along with their IDs. Queries
+    /// reference clients by their ID, which is their position in shared memory.
+    pub fn clients_with_ids<'b>(
+        &self,
+        clients: &'b FtlRecords<'b, FtlClient>
+    ) -> impl DoubleEndedIterator<Item = (usize, &'b FtlClient)> + 'b
+    where
+        'a: 'b
+    {
+        let strings = self.limits.strings;
+
+        filter(
+            clients,
+            self.limits.clients,
+            self.invalid.map(|invalid| &invalid.clients),
+            move |client| client.is_valid(strings)
+        )
+    }
+
+    /// Get the valid domains which are in use
+    pub fn domains<'b>(
+        &self,
+        domains: &'b FtlRecords<'b, FtlDomain>
+    ) -> impl DoubleEndedIterator<Item = &'b FtlDomain> + 'b
+    where
+        'a: 'b
+    {
+        self.domains_with_ids(domains).map(|(_, domain)| domain)
+    }
+
+    /// Get the valid domains which are in use, along with their IDs. Queries
+    /// reference domains by their ID, which is their position in shared memory.
+    pub fn domains_with_ids<'b>(
+        &self,
+        domains: &'b FtlRecords<'b, FtlDomain>
+    ) -> impl DoubleEndedIterator<Item = (usize, &'b FtlDomain)> + 'b
+    where
+        'a: 'b
+    {
+        let strings = self.limits.strings;
+
+        filter(
+            domains,
+            self.limits.domains,
+            self.invalid.map(|invalid| &invalid.domains),
+            move |domain| domain.is_valid(strings)
+        )
+    }
+
+    /// Get the valid upstreams which are in use
+    pub fn upstreams<'b>(
+        &self,
+        upstreams: &'b FtlRecords<'b, FtlUpstream>
+    ) -> impl DoubleEndedIterator<Item = &'b FtlUpstream> + 'b
+    where
+        'a: 'b
+    {
+        self.upstreams_with_ids(upstreams)
+            .map(|(_, upstream)| upstream)
+    }
+
+    /// Get the valid upstreams which are in use, along with their IDs. Queries
+    /// reference upstreams by their ID, which is their position in shared
+    /// memory.
+    pub fn upstreams_with_ids<'b>(
+        &self,
+        upstreams: &'b FtlRecords<'b, FtlUpstream>
+    ) -> impl DoubleEndedIterator<Item = (usize, &'b FtlUpstream)> + 'b
+    where
+        'a: 'b
+    {
+        let strings = self.limits.strings;
+
+        filter(
+            upstreams,
+            self.limits.upstreams,
+            self.invalid.map(|invalid| &invalid.upstreams),
+            move |upstream| upstream.is_valid(strings)
+        )
+    }
+}
+
+/// Iterate over the first `used` items, along with their IDs. If there is an
+/// invalid record count, the items are validated, and items which are not
+/// intact or not valid are skipped and counted.
+fn filter<'b, T: FtlRecord + 'b>(
+    items: &'b FtlRecords<'b, T>,
+    used: usize,
+    invalid: Option<&'b AtomicUsize>,
+    is_valid: impl Fn(&T) -> bool + 'b
+) -> impl DoubleEndedIterator<Item = (usize, &'b T)> + 'b {
+    (0..cmp::min(used, items.len())).filter_map(move |id| {
+        let item = items
+            .get(id)
+            .filter(|item| invalid.is_none() || is_valid(item));
+
+        if let (None, Some(count)) = (item, invalid) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        item.map(|item| (id, item))
+    })
+}
+
+/// Get the IDs of the first `used` items which are not intact or not valid
+fn invalid_ids<T: FtlRecord>(
+    items: &FtlRecords<T>,
+    used: usize,
+    is_valid: impl Fn(&T) -> bool
+) -> HashSet<usize> {
+    (0..used)
+        .filter(|&id| !items.get(id).map_or(false, |item| is_valid(item)))
+        .collect()
+}
+
+/// Convert a record count from FTL into a usize
+fn used(count: libc::c_int) -> usize {
+    count.max(0) as usize
+}
+
+/// Check if the ID references one of the first `limit` records, and the
+/// record is not invalid
+fn is_valid_id(id: libc::c_int, limit: usize, invalid_ids: &HashSet<usize>) -> bool {
+    id >= 0 && (id as usize) < limit && !invalid_ids.contains(&(id as usize))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{InvalidRecordCounts, InvalidRecords, Limits, Validator};
+    use crate::ftl::{
+        FtlClient, FtlDnssecType, FtlDomain, FtlQuery, FtlQueryReplyType, FtlQueryStatus,
+        FtlQueryType, FtlRecords, FtlRegexMatch, FtlUpstream, MAGIC_BYTE
+    };
+
+    /// Create a validator which checks records, as it does for shared memory
+    /// read from FTL
+    fn validator(invalid: &InvalidRecords) -> Validator {
+        Validator {
+            limits: Limits {
+                queries: 3,
+                clients: 2,
+                domains: 2,
+                upstreams: 1,
+                strings: 10,
+                ..Limits::default()
+            },
+            invalid: Some(invalid)
+        }
+    }
+
+    /// Create a valid query
+    fn query(id: i32) -> FtlQuery {
+        FtlQuery {
+            magic: MAGIC_BYTE,
+            id,
+            database_id: 0,
+            timestamp: 1,
+            time_index: 1,
+            response_time: 1,
+            domain_id: 1,
+            client_id: 1,
+            upstream_id: 0,
+            query_type: FtlQueryType::A,
+            status: FtlQueryStatus::Forward,
+            reply_type: FtlQueryReplyType::IP,
+            dnssec_type: FtlDnssecType::Unspecified,
+            is_complete: true,
+            is_private: false,
+            ad_bit: false
+        }
+    }
+
+    /// Valid queries which are in use are returned, and invalid ones are
+    /// skipped and counted
+    #[test]
+    fn queries() {
+        let invalid = InvalidRecords::default();
+        let validator = validator(&invalid);
+
+        let mut bad_magic = query(2);
+        bad_magic.magic = 0;
+        let mut bad_domain = query(3);
+        bad_domain.domain_id = 2;
+        let unused = query(4);
+
+        let queries = vec![query(1), bad_magic, bad_domain, unused];
+        let ids: Vec<i32> = validator
+            .queries(&FtlRecords::from(&queries[..]))
+            .map(|query| query.id)
+            .collect();
+
+        assert_eq!(ids, vec![1]);
+        assert_eq!(invalid.counts().queries, 2);
+    }
+
+    /// The upstream of a query is only checked if the query was forwarded
+    #[test]
+    fn query_upstream() {
+        let invalid = InvalidRecords::default();
+        let validator = validator(&invalid);
+
+        let mut bad_upstream = query(1);
+        bad_upstream.upstream_id = 5;
+        let mut blocked = query(2);
+        blocked.upstream_id = 5;
+        blocked.status = FtlQueryStatus::Gravity;
+
+        let queries = vec![bad_upstream, blocked];
+        let ids: Vec<i32> = validator
+            .queries(&FtlRecords::from(&queries[..]))
+            .map(|query| query.id)
+            .collect();
+
+        assert_eq!(ids, vec![2]);
+        assert_eq!(invalid.counts().queries, 1);
+    }
+
+    /// Queries which reference invalid records are skipped and counted
+    #[test]
+    fn invalid_references() {
+        let invalid = InvalidRecords::default();
+        let mut validator = validator(&invalid);
+        validator.limits.invalid_clients.insert(1);
+
+        let queries = vec![query(1)];
+
+        assert_eq!(
+            validator.queries(&FtlRecords::from(&queries[..])).count(),
+            0
+        );
+        assert_eq!(invalid.counts().queries, 1);
+    }
+
+    /// Records which reference strings outside of the used string memory are
+    /// skipped
+    #[test]
+    fn string_ids() {
+        let invalid = InvalidRecords::default();
+        let validator = validator(&invalid);
+
+        let clients = vec![
+            FtlClient::new(1, 0, 1, None),
+            FtlClient::new(1, 0, 20, None),
+        ];
+        let domains = vec![
+            FtlDomain::new(1, 0, 15, FtlRegexMatch::NotBlocked),
+            FtlDomain::new(1, 0, 2, FtlRegexMatch::NotBlocked),
+        ];
+        let upstreams = vec![FtlUpstream::new(1, 0, 3, Some(30))];
+
+        let clients = FtlRecords::from(&clients[..]);
+        let domains = FtlRecords::from(&domains[..]);
+        let upstreams = FtlRecords::from(&upstreams[..]);
+
+        let client_ids: Vec<usize> = validator
+            .clients_with_ids(&clients)
+            .map(|(id, _)| id)
+            .collect();
+        let domain_ids: Vec<usize> = validator
+            .domains_with_ids(&domains)
+            .map(|(id, _)| id)
+            .collect();
+
+        assert_eq!(client_ids, vec![0]);
+        assert_eq!(domain_ids, vec![1]);
+        assert_eq!(validator.upstreams(&upstreams).count(), 0);
+        assert_eq!(
+            invalid.counts(),
+            InvalidRecordCounts {
+                queries: 0,
+                clients: 1,
+                domains: 1,
+                upstreams: 1
+            }
+        );
+    }
+
+    /// Records are not checked when the data is trusted
+    #[test]
+    fn trusted() {
+        let validator = Validator {
+            limits: Limits {
+                queries: 2,
+                clients: 0,
+                domains: 0,
+                upstreams: 0,
+                strings: 0,
+                ..Limits::default()
+            },
+            invalid: None
+        };
+
+        let mut bad_magic = query(1);
+        bad_magic.magic = 0;
+
+        let queries = vec![bad_magic, query(2), query(3)];
+        let ids: Vec<i32> = validator
+            .queries(&FtlRecords::from(&queries[..]))
+            .map(|query| query.id)
+            .collect();
+
+        assert_eq!(ids, vec![1, 2]);
+    }
+}
@@ -14,6 +14,8 @@
 #[macro_use]
 extern crate diesel;
 #[macro_use]
+extern crate log;
+#[macro_use]
 extern crate rocket;
 #[macro_use]
 extern crate serde_derive;
@@ -24,13 +26,20 @@ extern crate rust_embed;
 
 pub use crate::setup::start;
 
+mod access;
+mod cors;
 mod databases;
 mod env;
+#[macro_use]
 mod ftl;
+mod live;
+mod proxy;
 mod routes;
 mod settings;
 mod setup;
+mod tls;
 mod util;
+mod webhooks;
 
 #[cfg(test)]
 mod testing;
@@ -0,0 +1,441 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Live Update Hub
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlMemory,
+    live::Topic,
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use libc;
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration
+};
+
+/// Receives the messages sent to a connected client
+pub trait Subscriber: Send {
+    /// Send a message to the client
+    fn send(&self, message: String);
+}
+
+/// A message from a client which changes its subscriptions
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { topics: Vec<Topic> },
+    Unsubscribe { topics: Vec<Topic> }
+}
+
+/// An update of a topic's data. Full updates contain all of the data, and
+/// other updates only contain the top level fields which changed. Removed
+/// fields are set to `null`.
+#[derive(Serialize)]
+struct Update<'a> {
+    topic: Topic,
+    full: bool,
+    data: &'a Value
+}
+
+/// A connected client and the topics it is subscribed to
+struct Client {
+    subscriber: Box<dyn Subscriber>,
+    topics: HashSet<Topic>
+}
+
+/// The parts of shared memory which change when FTL's data changes. If they
+/// are the same as in the last update, the topics which are loaded from
+/// shared memory are not loaded again.
+#[derive(Copy, Clone, PartialEq)]
+struct MemoryState {
+    global_shm_counter: libc::c_uint,
+    total_queries: libc::c_int,
+    blocked_queries: libc::c_int,
+    cached_queries: libc::c_int,
+    forwarded_queries: libc::c_int,
+    total_clients: libc::c_int,
+    total_domains: libc::c_int,
+    gravity_size: libc::c_int
+}
+
+#[derive(Default)]
+struct HubState {
+    clients: HashMap<u32, Client>,
+    /// The last data sent for each topic which has subscribers
+    data: HashMap<Topic, Value>,
+    memory_state: Option<MemoryState>
+}
+
+/// Loads the dashboard data and pushes changes to the clients subscribed to
+/// it. Each topic is loaded once per update, no matter how many clients are
+/// subscribed, and only if shared memory changed since the last update.
+/// Settings read from files, such as the privacy level, are picked up the
+/// next time shared memory changes. The blocking status is the exception, and
+/// is checked on every update.
+pub struct LiveHub {
+    ftl_memory: FtlMemory,
+    env: Env,
+    state: Mutex<HubState>
+}
+
+impl LiveHub {
+    /// Create a hub which loads data outside of Rocket
+    pub fn new(ftl_memory: FtlMemory, env: Env) -> LiveHub {
+        LiveHub {
+            ftl_memory,
+            env,
+            state: Mutex::new(HubState::default())
+        }
+    }
+
+    /// Start checking for changes in the background. A failed update is
+    /// tried again next time. Its error is printed when updates start to fail,
+    /// so that an unavailable FTL does not print an error on every update.
+    pub fn run(hub: Arc<LiveHub>, interval: Duration) {
+        thread::spawn(move || {
+            let mut failing = false;
+
+            loop {
+                match hub.update() {
+                    Ok(()) => failing = false,
+                    Err(e) => {
+                        if !failing {
+                            e.print_stacktrace();
+                        }
+
+                        failing = true;
+                    }
+                }
+
+                thread::sleep(interval);
+            }
+        });
+    }
+
+    /// Add a client, which is not subscribed to any topics yet
+    pub fn connect(&self, id: u32, subscriber: Box<dyn Subscriber>) {
+        self.state.lock().unwrap().clients.insert(
+            id,
+            Client {
+                subscriber,
+                topics: HashSet::new()
+            }
+        );
+    }
+
+    /// Remove a client which disconnected
+    pub fn disconnect(&self, id: u32) {
+        self.state.lock().unwrap().clients.remove(&id);
+    }
+
+    /// Handle a message from a client. When a client subscribes to a topic, it
+    /// is sent the topic's full data.
+    pub fn handle_message(&self, id: u32, message: &str) -> Result<(), Error> {
+        let message: ClientMessage =
+            serde_json::from_str(message).context(ErrorKind::BadRequest)?;
+
+        match message {
+            ClientMessage::Subscribe { topics } => self.subscribe(id, topics),
+            ClientMessage::Unsubscribe { topics } => {
+                if let Some(client) = self.state.lock().unwrap().clients.get_mut(&id) {
+                    for topic in topics {
+                        client.topics.remove(&topic);
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Subscribe a client to the topics, and send it their full data. Topics
+    /// which do not have data yet are loaded without holding the state lock,
+    /// so other clients are not blocked while they load.
+    fn subscribe(&self, id: u32, topics: Vec<Topic>) -> Result<(), Error> {
+        let missing: Vec<Topic> = {
+            let state = self.state.lock().unwrap();
+
+            topics
+                .iter()
+                .filter(|topic| !state.data.contains_key(topic))
+                .cloned()
+                .collect()
+        };
+
+        let mut loaded = HashMap::new();
+        for topic in missing {
+            loaded.insert(topic, topic.load(&self.ftl_memory, &self.env)?);
+        }
+
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let client = match state.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return Ok(())
+        };
+
+        for topic in topics {
+            if !state.data.contains_key(&topic) {
+                // The data may have been removed by an update since it was
+                // checked, if nobody else was subscribed to the topic
+                let data = match loaded.remove(&topic) {
+                    Some(data) => data,
+                    None => topic.load(&self.ftl_memory, &self.env)?
+                };
+                state.data.insert(topic, data);
+            }
+
+            client.topics.insert(topic);
+            client
+                .subscriber
+                .send(update_message(topic, true, &state.data[&topic]));
+        }
+
+        Ok(())
+    }
+
+    /// Check for changes to the topics which have subscribers, and send the
+    /// changes to them. The topics are loaded without holding the state lock,
+    /// so clients can still connect and subscribe while they load. If a topic
+    /// fails to load, the other topics are still updated, and the first error
+    /// is returned.
+    pub fn update(&self) -> Result<(), Error> {
+        let (topics, last_memory_state) = {
+            let mut state = self.state.lock().unwrap();
+
+            let topics: HashSet<Topic> = state
+                .clients
+                .values()
+                .flat_map(|client| client.topics.iter().cloned())
+                .collect();
+
+            // Data for topics nobody is subscribed to would go stale
+            state.data.retain(|topic, _| topics.contains(topic));
+
+            (topics, state.memory_state)
+        };
+
+        if topics.is_empty() {
+            return Ok(());
+        }
+
+        let memory_state = self.memory_state()?;
+        let memory_changed = last_memory_state != Some(memory_state);
+
+        let mut error = None;
+        let mut loaded = Vec::new();
+        for topic in topics {
+            if topic.uses_shared_memory() && !memory_changed {
+                continue;
+            }
+
+            match topic.load(&self.ftl_memory, &self.env) {
+                Ok(data) => loaded.push((topic, data)),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        for (topic, data) in loaded {
+            let message = match state.data.get(&topic) {
+                Some(old_data) if *old_data == data => continue,
+                Some(old_data) => update_message(topic, false, &delta(old_data, &data)),
+                None => update_message(topic, true, &data)
+            };
+
+            for client in state
+                .clients
+                .values()
+                .filter(|client| client.topics.contains(&topic))
+            {
+                client.subscriber.send(message.clone());
+            }
+
+            state.data.insert(topic, data);
+        }
+
+        // Only remember the state once every topic has been loaded, so a
+        // failed topic is tried again
+        match error {
+            Some(e) => Err(e),
+            None => {
+                state.memory_state = Some(memory_state);
+                Ok(())
+            }
+        }
+    }
+
+    /// Read the current state of shared memory
+    fn memory_state(&self) -> Result<MemoryState, Error> {
+        let lock = self.ftl_memory.lock()?;
+        let counters = self.ftl_memory.counters(&lock)?;
+        let settings = self.ftl_memory.settings(&lock)?;
+
+        Ok(MemoryState {
+            global_shm_counter: settings.global_shm_counter,
+            total_queries: counters.total_queries,
+            blocked_queries: counters.blocked_queries,
+            cached_queries: counters.cached_queries,
+            forwarded_queries: counters.forwarded_queries,
+            total_clients: counters.total_clients,
+            total_domains: counters.total_domains,
+            gravity_size: counters.gravity_size
+        })
+    }
+}
+
+/// Create the message sent to clients for an update
+fn update_message(topic: Topic, full: bool, data: &Value) -> String {
+    serde_json::to_string(&Update { topic, full, data }).unwrap_or_default()
+}
+
+/// Get the top level fields of the new data which are different from the old
+/// data. If the data is not an object, all of the new data is used.
+fn delta(old_data: &Value, new_data: &Value) -> Value {
+    match (old_data, new_data) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let mut changes: Map<String, Value> = new_fields
+                .iter()
+                .filter(|(key, value)| old_fields.get(*key) != Some(value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+
+            for key in old_fields.keys() {
+                if !new_fields.contains_key(key) {
+                    changes.insert(key.clone(), Value::Null);
+                }
+            }
+
+            Value::Object(changes)
+        }
+        _ => new_data.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{delta, LiveHub, Subscriber};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        ftl::FtlMemory,
+        routes::stats::history::testing::test_memory,
+        testing::TestEnvBuilder
+    };
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the messages sent to a client
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Value>>>);
+
+    impl Subscriber for Recorder {
+        fn send(&self, message: String) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(serde_json::from_str(&message).unwrap());
+        }
+    }
+
+    impl Recorder {
+        /// Take the messages which have been sent so far
+        fn take(&self) -> Vec<Value> {
+            self.0.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    /// Create a hub with a connected client
+    fn hub(ftl_memory: FtlMemory) -> (LiveHub, Recorder) {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, "BLOCKING_ENABLED=true")
+                .build()
+        );
+        let hub = LiveHub::new(ftl_memory, env);
+        let recorder = Recorder::default();
+        hub.connect(1, Box::new(recorder.clone()));
+
+        (hub, recorder)
+    }
+
+    /// Subscribing to a topic sends its full data
+    #[test]
+    fn subscribe() {
+        let (hub, recorder) = hub(test_memory());
+
+        hub.handle_message(1, r#"{"action":"subscribe","topics":["status"]}"#)
+            .unwrap();
+
+        assert_eq!(
+            recorder.take(),
+            vec![json!({ "topic": "status", "full": true, "data": { "status": "enabled" } }).0]
+        );
+    }
+
+    /// Unknown actions and topics are rejected
+    #[test]
+    fn bad_message() {
+        let (hub, _) = hub(test_memory());
+
+        assert!(hub
+            .handle_message(1, r#"{"action":"subscribe","topics":["unknown"]}"#)
+            .is_err());
+        assert!(hub.handle_message(1, r#"{"action":"publish"}"#).is_err());
+    }
+
+    /// Nothing is sent when the data has not changed, and unsubscribed clients
+    /// are not sent updates
+    #[test]
+    fn no_changes() {
+        let (hub, recorder) = hub(test_memory());
+
+        hub.handle_message(
+            1,
+            r#"{"action":"subscribe","topics":["summary","top_clients"]}"#
+        )
+        .unwrap();
+        assert_eq!(recorder.take().len(), 2);
+
+        hub.update().unwrap();
+        hub.update().unwrap();
+        assert_eq!(recorder.take(), Vec::<Value>::new());
+
+        hub.handle_message(
+            1,
+            r#"{"action":"unsubscribe","topics":["summary","top_clients"]}"#
+        )
+        .unwrap();
+        hub.update().unwrap();
+        assert!(hub.state.lock().unwrap().data.is_empty());
+    }
+
+    /// Only the fields which changed are included in a delta, and removed
+    /// fields are set to null
+    #[test]
+    fn delta_fields() {
+        let old_data = json!({ "a": 1, "b": [1, 2], "c": true }).0;
+        let new_data = json!({ "a": 1, "b": [2, 1], "d": "new" }).0;
+
+        assert_eq!(
+            delta(&old_data, &new_data),
+            json!({ "b": [2, 1], "c": null, "d": "new" }).0
+        );
+        assert_eq!(delta(&json!([1]).0, &json!([2]).0), json!([2]).0);
+    }
+}
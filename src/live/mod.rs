@@ -0,0 +1,21 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Live Update Server
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+mod hub;
+mod server;
+mod tickets;
+mod topic;
+
+pub use self::{
+    hub::{LiveHub, Subscriber},
+    server::start,
+    tickets::{TicketStore, TICKET_LIFETIME},
+    topic::Topic
+};
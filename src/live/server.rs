@@ -0,0 +1,134 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Live Update WebSocket Server
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Config,
+    live::{LiveHub, Subscriber, TicketStore},
+    util::{Error, ErrorKind}
+};
+use failure::Fail;
+use std::{sync::Arc, thread};
+use ws::{self, CloseCode, Handler, Handshake, Message, Request, Response, Sender};
+
+/// The query parameter which holds the connection ticket
+const TICKET_PARAM: &str = "ticket";
+
+impl Subscriber for Sender {
+    fn send(&self, message: String) {
+        // If the connection is closing, the message is not needed anymore
+        let _ = Sender::send(self, message);
+    }
+}
+
+/// A client's WebSocket connection
+struct Connection {
+    sender: Sender,
+    hub: Arc<LiveHub>,
+    tickets: TicketStore
+}
+
+impl Handler for Connection {
+    /// Only accept connections which have a valid ticket
+    fn on_request(&mut self, request: &Request) -> ws::Result<Response> {
+        let authorized =
+            ticket_param(request.resource()).map_or(false, |ticket| self.tickets.redeem(ticket));
+
+        if !authorized {
+            return Ok(Response::new(401, "Unauthorized", Vec::new()));
+        }
+
+        Response::from_request(request)
+    }
+
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        self.hub
+            .connect(self.sender.connection_id(), Box::new(self.sender.clone()));
+        Ok(())
+    }
+
+    fn on_message(&mut self, message: Message) -> ws::Result<()> {
+        let result = match message {
+            Message::Text(text) => self.hub.handle_message(self.sender.connection_id(), &text),
+            Message::Binary(_) => Err(Error::from(ErrorKind::BadRequest))
+        };
+
+        // Errors are sent in the same format as the API's error replies
+        if let Err(error) = result {
+            self.sender.send(
+                json!({
+                    "error": {
+                        "key": error.key(),
+                        "message": error.to_string(),
+                        "data": error.data()
+                    }
+                })
+                .to_string()
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn on_close(&mut self, _: CloseCode, _: &str) {
+        self.hub.disconnect(self.sender.connection_id());
+    }
+}
+
+/// Find the ticket in the query string of the requested resource
+fn ticket_param(resource: &str) -> Option<&str> {
+    let query = resource.splitn(2, '?').nth(1)?;
+
+    query.split('&').find_map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+
+        if parts.next()? == TICKET_PARAM {
+            parts.next()
+        } else {
+            None
+        }
+    })
+}
+
+/// Start the live update hub and the WebSocket server, which listens on the
+/// API's address using the live update port. Clients connect using a ticket
+/// from `/live/ticket`.
+pub fn start(config: &Config, hub: Arc<LiveHub>, tickets: TicketStore) {
+    let address = format!("{}:{}", config.address(), config.live_port());
+
+    LiveHub::run(hub.clone(), config.live_update_interval());
+
+    thread::spawn(move || {
+        let result = ws::Builder::new()
+            .build(move |sender| Connection {
+                sender,
+                hub: hub.clone(),
+                tickets: tickets.clone()
+            })
+            .and_then(|socket| socket.listen(address.as_str()));
+
+        if let Err(e) = result {
+            Error::from(e.context(ErrorKind::LiveServer)).print_stacktrace();
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::ticket_param;
+
+    /// The ticket is found among the other query parameters
+    #[test]
+    fn find_ticket() {
+        assert_eq!(ticket_param("/?ticket=abc"), Some("abc"));
+        assert_eq!(ticket_param("/live?a=1&ticket=abc&b=2"), Some("abc"));
+        assert_eq!(ticket_param("/live?tickets=abc"), None);
+        assert_eq!(ticket_param("/live"), None);
+    }
+}
@@ -0,0 +1,83 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Live Update Connection Tickets
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{routes::auth::random_bytes, util::Error};
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+
+/// How long a ticket can be used after it is issued
+pub const TICKET_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Stores the tickets used to open live update connections. The WebSocket
+/// server does not go through Rocket, so clients authenticate with the API to
+/// get a ticket, then connect with it. Tickets can only be used once.
+#[derive(Clone, Default)]
+pub struct TicketStore {
+    tickets: Arc<Mutex<HashMap<String, Instant>>>
+}
+
+impl TicketStore {
+    /// Issue a new ticket
+    pub fn issue(&self) -> Result<String, Error> {
+        let ticket = encode_config(&random_bytes(32)?, URL_SAFE_NO_PAD);
+        let mut tickets = self.tickets.lock().unwrap();
+
+        // Forget the tickets which expired without being used
+        tickets.retain(|_, issued| issued.elapsed() < TICKET_LIFETIME);
+        tickets.insert(ticket.clone(), Instant::now());
+
+        Ok(ticket)
+    }
+
+    /// Use up a ticket. Returns `false` if the ticket was never issued, was
+    /// already used, or has expired.
+    pub fn redeem(&self, ticket: &str) -> bool {
+        self.tickets
+            .lock()
+            .unwrap()
+            .remove(ticket)
+            .map_or(false, |issued| issued.elapsed() < TICKET_LIFETIME)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TicketStore, TICKET_LIFETIME};
+    use std::time::Instant;
+
+    /// Tickets can only be used once
+    #[test]
+    fn redeem_once() {
+        let store = TicketStore::default();
+        let ticket = store.issue().unwrap();
+
+        assert!(store.redeem(&ticket));
+        assert!(!store.redeem(&ticket));
+        assert!(!store.redeem("unknown"));
+    }
+
+    /// Expired tickets can not be used
+    #[test]
+    fn expired() {
+        let store = TicketStore::default();
+        let ticket = store.issue().unwrap();
+        store
+            .tickets
+            .lock()
+            .unwrap()
+            .insert(ticket.clone(), Instant::now() - TICKET_LIFETIME);
+
+        assert!(!store.redeem(&ticket));
+    }
+}
@@ -0,0 +1,84 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Live Update Topics
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlMemory,
+    routes::{
+        dns::blocking_status,
+        stats::{
+            get_top_clients, get_top_domains, load_over_time_history, load_summary,
+            TopClientParams, TopDomainParams
+        }
+    },
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use serde::Serialize;
+use serde_json::Value;
+
+/// The data which clients can subscribe to. Each topic has the same data as
+/// its endpoint, using the endpoint's default parameters.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// `/stats/summary`
+    Summary,
+    /// `/stats/overTime/history`
+    OverTime,
+    /// `/stats/top_domains`
+    TopDomains,
+    /// `/stats/top_domains?blocked=true`
+    TopAds,
+    /// `/stats/top_clients`
+    TopClients,
+    /// `/dns/status`
+    Status
+}
+
+impl Topic {
+    /// If the topic's data comes from shared memory. It only needs to be
+    /// loaded again when shared memory changes.
+    pub fn uses_shared_memory(self) -> bool {
+        self != Topic::Status
+    }
+
+    /// Load the topic's data
+    pub fn load(self, ftl_memory: &FtlMemory, env: &Env) -> Result<Value, Error> {
+        match self {
+            Topic::Summary => to_value(load_summary(ftl_memory, env)?),
+            Topic::OverTime => to_value(load_over_time_history(ftl_memory)?),
+            Topic::TopDomains => to_value(get_top_domains(
+                ftl_memory,
+                env,
+                TopDomainParams::default()
+            )?),
+            Topic::TopAds => to_value(get_top_domains(
+                ftl_memory,
+                env,
+                TopDomainParams {
+                    blocked: Some(true),
+                    ..TopDomainParams::default()
+                }
+            )?),
+            Topic::TopClients => to_value(get_top_clients(
+                ftl_memory,
+                env,
+                TopClientParams::default()
+            )?),
+            Topic::Status => Ok(json!({ "status": blocking_status(env)? }).0)
+        }
+    }
+}
+
+/// Convert the data into JSON
+fn to_value<T: Serialize>(data: T) -> Result<Value, Error> {
+    Ok(serde_json::to_value(data).context(ErrorKind::Unknown)?)
+}
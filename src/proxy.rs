@@ -0,0 +1,208 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Reverse Proxy Client Address Resolution
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::access::Cidr;
+use rocket::{Request, State};
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr
+};
+
+/// The standard forwarding header (RFC 7239)
+const FORWARDED_HEADER: &str = "Forwarded";
+/// The de-facto standard forwarding header
+const X_FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// The networks of the reverse proxies which are trusted to report the
+/// client's address
+#[derive(Default)]
+pub struct TrustedProxies(pub Vec<Cidr>);
+
+impl TrustedProxies {
+    /// Check if the address belongs to a trusted proxy
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// Resolve the client's address from the peer address and the forwarded
+    /// addresses, which are listed in the order the proxies added them. The
+    /// list is followed backwards from the peer while the addresses belong to
+    /// trusted proxies, so a client can not spoof its address by sending its
+    /// own forwarding header.
+    fn resolve(&self, peer: IpAddr, forwarded: &[Option<IpAddr>]) -> IpAddr {
+        let mut client = peer;
+
+        for address in forwarded.iter().rev() {
+            if !self.contains(client) {
+                break;
+            }
+
+            match *address {
+                Some(address) => client = address,
+                // The proxy did not give a usable address, so the last
+                // trusted hop is as close to the client as we can get
+                None => break
+            }
+        }
+
+        client
+    }
+}
+
+/// Get the client's address. If the peer is a trusted proxy, the address is
+/// taken from the `Forwarded` or `X-Forwarded-For` headers. Otherwise, the
+/// peer's address is used.
+///
+/// Rocket's `Request::client_ip` is not used because it trusts the
+/// `X-Real-IP` header from any client.
+pub fn client_ip(request: &Request) -> Option<IpAddr> {
+    let peer = request.remote()?.ip();
+    let trusted_proxies: State<TrustedProxies> = match request.guard().succeeded() {
+        Some(trusted_proxies) => trusted_proxies,
+        None => return Some(peer)
+    };
+
+    if !trusted_proxies.contains(peer) {
+        return Some(peer);
+    }
+
+    let forwarded: Vec<&str> = request.headers().get(FORWARDED_HEADER).collect();
+    let forwarded = if forwarded.is_empty() {
+        parse_x_forwarded_for(request.headers().get(X_FORWARDED_FOR_HEADER))
+    } else {
+        parse_forwarded(forwarded.into_iter())
+    };
+
+    Some(trusted_proxies.resolve(peer, &forwarded))
+}
+
+/// Parse the addresses in `X-Forwarded-For` headers (ex. `10.0.0.1, ::1`)
+fn parse_x_forwarded_for<'a>(headers: impl Iterator<Item = &'a str>) -> Vec<Option<IpAddr>> {
+    headers
+        .flat_map(|header| header.split(','))
+        .map(|address| parse_address(address.trim()))
+        .collect()
+}
+
+/// Parse the `for` addresses in `Forwarded` headers (ex.
+/// `for=10.0.0.1;proto=https, for="[::1]:8080"`)
+fn parse_forwarded<'a>(headers: impl Iterator<Item = &'a str>) -> Vec<Option<IpAddr>> {
+    headers
+        .flat_map(|header| header.split(','))
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| {
+                    let mut parts = pair.trim().splitn(2, '=');
+                    let name = parts.next()?;
+                    let value = parts.next()?;
+
+                    if name.eq_ignore_ascii_case("for") {
+                        Some(value.trim_matches('"'))
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .and_then(parse_address)
+        })
+        .collect()
+}
+
+/// Parse an address which may have a port, and IPv6 addresses may be in
+/// brackets (ex. `10.0.0.1`, `10.0.0.1:8080`, `[::1]`, `[::1]:8080`)
+fn parse_address(address: &str) -> Option<IpAddr> {
+    IpAddr::from_str(address)
+        .or_else(|_| SocketAddr::from_str(address).map(|address| address.ip()))
+        .or_else(|_| IpAddr::from_str(address.trim_start_matches('[').trim_end_matches(']')))
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_forwarded, parse_x_forwarded_for, TrustedProxies};
+    use crate::access::Cidr;
+    use std::{net::IpAddr, str::FromStr};
+
+    /// Parse an IP address
+    fn ip(ip: &str) -> IpAddr {
+        IpAddr::from_str(ip).unwrap()
+    }
+
+    /// Trust proxies on localhost and 10.0.0.0/24
+    fn trusted_proxies() -> TrustedProxies {
+        TrustedProxies(vec![
+            Cidr::from_str("127.0.0.1").unwrap(),
+            Cidr::from_str("10.0.0.0/24").unwrap(),
+        ])
+    }
+
+    /// `X-Forwarded-For` headers are split into addresses
+    #[test]
+    fn x_forwarded_for() {
+        assert_eq!(
+            parse_x_forwarded_for(vec!["192.168.1.5, 10.0.0.2", "::1"].into_iter()),
+            vec![
+                Some(ip("192.168.1.5")),
+                Some(ip("10.0.0.2")),
+                Some(ip("::1"))
+            ]
+        );
+    }
+
+    /// `Forwarded` headers are parsed for their `for` addresses
+    #[test]
+    fn forwarded() {
+        assert_eq!(
+            parse_forwarded(
+                vec![
+                    "for=192.168.1.5;proto=https, For=\"[2001:db8::17]:4711\"",
+                    "by=10.0.0.1;for=unknown"
+                ]
+                .into_iter()
+            ),
+            vec![Some(ip("192.168.1.5")), Some(ip("2001:db8::17")), None]
+        );
+    }
+
+    /// Untrusted peers are used as the client, even if they send a header
+    #[test]
+    fn untrusted_peer() {
+        assert_eq!(
+            trusted_proxies().resolve(ip("192.168.1.5"), &[Some(ip("10.1.1.1"))]),
+            ip("192.168.1.5")
+        );
+    }
+
+    /// Trusted proxies are skipped until an untrusted address is found
+    #[test]
+    fn trusted_chain() {
+        assert_eq!(
+            trusted_proxies().resolve(
+                ip("127.0.0.1"),
+                &[
+                    Some(ip("8.8.8.8")),
+                    Some(ip("192.168.1.5")),
+                    Some(ip("10.0.0.2"))
+                ]
+            ),
+            ip("192.168.1.5")
+        );
+    }
+
+    /// An unknown address stops the search at the last trusted proxy
+    #[test]
+    fn unknown_address() {
+        assert_eq!(
+            trusted_proxies().resolve(ip("127.0.0.1"), &[None, Some(ip("10.0.0.2"))]),
+            ip("10.0.0.2")
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Maintenance Actions
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::{FtlConnectionType, FtlValue},
+    routes::{
+        audit::AuditEntry,
+        auth::{scopes::FtlActions, ClientIp, Scoped},
+        dns::reload_dns
+    },
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use serde_json::Value;
+
+/// How a maintenance action is run
+enum Action {
+    /// Send this command over FTL's socket
+    Command(&'static str),
+    /// Send FTL a SIGHUP, like `pihole restartdns reload`. FTL has no socket
+    /// command for reloading its lists.
+    Reload
+}
+
+/// The maintenance actions which FTL can run
+const ACTIONS: &[(&str, Action)] = &[
+    ("recompile-regex", Action::Command("recompile-regex")),
+    ("reload-lists", Action::Reload),
+    ("reresolve-names", Action::Command("reresolve"))
+];
+
+/// Run a maintenance action in FTL. The values FTL sends back are returned
+/// as the action's output.
+#[post("/ftl/actions/<name>")]
+pub fn run_action(
+    auth: Scoped<FtlActions>,
+    ip: ClientIp,
+    env: State<Env>,
+    ftl: State<FtlConnectionType>,
+    name: String
+) -> Reply {
+    let action = ACTIONS
+        .iter()
+        .find(|(action, _)| *action == name)
+        .map(|(_, action)| action)
+        .ok_or(ErrorKind::NotFound)?;
+
+    let result = match action {
+        Action::Command(command) => run_command(&ftl, command),
+        Action::Reload => reload_dns(&env).map(|_| Vec::new())
+    };
+
+    AuditEntry::new(&format!("ftl.{}", name), &auth.principal, &ip).record(&env, &result);
+
+    reply_data(json!({
+        "action": name,
+        "output": result?
+    }))
+}
+
+/// Send the command to FTL and read its output
+fn run_command(ftl: &FtlConnectionType, command: &str) -> Result<Vec<Value>, Error> {
+    let output = ftl.connect(command)?.read_to_eom()?;
+
+    Ok(output.into_iter().map(FtlValue::into_json).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::{write_eom, TestBuilder};
+    use rmp::encode;
+    use rocket::http::{Method, Status};
+
+    /// Actions without output succeed with an empty output
+    #[test]
+    fn recompile_regex() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/ftl/actions/recompile-regex")
+            .method(Method::Post)
+            .ftl("recompile-regex", data)
+            .expect_json(json!({
+                "action": "recompile-regex",
+                "output": []
+            }))
+            .test();
+    }
+
+    /// The values FTL sends are returned as the output
+    #[test]
+    fn output() {
+        let mut data = Vec::new();
+        encode::write_map_len(&mut data, 1).unwrap();
+        encode::write_str(&mut data, "clients").unwrap();
+        encode::write_u32(&mut data, 12).unwrap();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/ftl/actions/reresolve-names")
+            .method(Method::Post)
+            .ftl("reresolve", data)
+            .expect_json(json!({
+                "action": "reresolve-names",
+                "output": [{ "clients": 12 }]
+            }))
+            .test();
+    }
+
+    /// Lists are reloaded with a signal instead of a socket command
+    #[test]
+    fn reload_lists() {
+        TestBuilder::new()
+            .endpoint("/admin/api/ftl/actions/reload-lists")
+            .method(Method::Post)
+            .expect_json(json!({
+                "action": "reload-lists",
+                "output": []
+            }))
+            .test();
+    }
+
+    /// Unknown actions are not found
+    #[test]
+    fn unknown_action() {
+        TestBuilder::new()
+            .endpoint("/admin/api/ftl/actions/shutdown")
+            .method(Method::Post)
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,521 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Audit Log
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    routes::auth::{ClientIp, Principal, User},
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use failure::ResultExt;
+use rocket::{request::Form, State};
+use serde_json::Value;
+use std::{
+    cmp,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// The default number of entries per page
+const DEFAULT_PER_PAGE: usize = 100;
+/// The maximum number of entries per page
+const MAX_PER_PAGE: usize = 1000;
+/// The size (in bytes) the audit log can grow to before it is rotated. Only
+/// one rotated log is kept.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// How much of the log is read at a time when reading it from the end
+const CHUNK_SIZE: usize = 8192;
+
+/// A record of a change made through the API, stored as one JSON object per
+/// line in [`PiholeFile::ApiAuditLog`]. When the log gets too big, it is moved
+/// to [`PiholeFile::ApiAuditLogRotated`], replacing the previous rotated log.
+///
+/// [`PiholeFile::ApiAuditLog`]: ../../env/enum.PiholeFile.html
+/// [`PiholeFile::ApiAuditLogRotated`]: ../../env/enum.PiholeFile.html
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub action: String,
+    pub principal: Principal,
+    pub ip: Option<String>,
+    pub old_value: Value,
+    pub new_value: Value,
+    pub outcome: String
+}
+
+impl AuditEntry {
+    /// Start an audit entry for an action taken by `principal`. The old and
+    /// new values default to `null`.
+    pub fn new(action: &str, principal: &Principal, ip: &ClientIp) -> AuditEntry {
+        AuditEntry {
+            timestamp: 0,
+            action: action.to_owned(),
+            principal: principal.clone(),
+            ip: ip.0.clone(),
+            old_value: Value::Null,
+            new_value: Value::Null,
+            outcome: String::new()
+        }
+    }
+
+    /// Set the value before the change
+    pub fn old_value<V: Into<Value>>(mut self, old_value: V) -> Self {
+        self.old_value = old_value.into();
+        self
+    }
+
+    /// Set the value after the change
+    pub fn new_value<V: Into<Value>>(mut self, new_value: V) -> Self {
+        self.new_value = new_value.into();
+        self
+    }
+
+    /// Append the entry to the audit log, with the outcome taken from
+    /// `result`. A failure to write the audit log does not fail the request,
+    /// because the change has already been made.
+    pub fn record<T>(mut self, env: &Env, result: &Result<T, Error>) {
+        self.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Current time is older than epoch")
+            .as_secs();
+        self.outcome = match result {
+            Ok(_) => "success".to_owned(),
+            Err(e) => e.key().to_owned()
+        };
+
+        if let Err(e) = self.append(env, MAX_LOG_SIZE) {
+            e.print_stacktrace();
+        }
+    }
+
+    /// Append the entry to the audit log. If the entry would make the log
+    /// bigger than `max_size`, the log is rotated first.
+    fn append(&self, env: &Env, max_size: u64) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(self).context(ErrorKind::Unknown)?;
+        line.push(b'\n');
+
+        let location = env.file_location(PiholeFile::ApiAuditLog).to_owned();
+        let mut file = env.write_file(PiholeFile::ApiAuditLog, true)?;
+        let size = file
+            .seek(SeekFrom::End(0))
+            .context(ErrorKind::FileWrite(location.clone()))?;
+
+        if size > 0 && size + line.len() as u64 > max_size {
+            env.rename_file(PiholeFile::ApiAuditLog, PiholeFile::ApiAuditLogRotated)?;
+            file = env.write_file(PiholeFile::ApiAuditLog, true)?;
+        }
+
+        file.seek(SeekFrom::End(0))
+            .and_then(|_| file.write_all(&line))
+            .context(ErrorKind::FileWrite(location))?;
+
+        Ok(())
+    }
+
+    /// Check if the entry matches the filters
+    fn matches(&self, params: &AuditParams) -> bool {
+        params
+            .action
+            .as_ref()
+            .map_or(true, |action| self.action.starts_with(action.as_str()))
+            && params
+                .outcome
+                .as_ref()
+                .map_or(true, |outcome| &self.outcome == outcome)
+            && params
+                .principal
+                .as_ref()
+                .map_or(true, |principal| &self.principal.to_string() == principal)
+            && params.from.map_or(true, |from| self.timestamp >= from)
+            && params.until.map_or(true, |until| self.timestamp <= until)
+    }
+}
+
+/// Find up to `limit` audit entries which match the filters, newest first.
+/// The logs are read from the end, so only the newest part of them is read.
+/// Lines which can not be parsed are skipped.
+fn find_entries(env: &Env, params: &AuditParams, limit: usize) -> Result<Vec<AuditEntry>, Error> {
+    let mut entries = Vec::new();
+
+    for &file in &[PiholeFile::ApiAuditLog, PiholeFile::ApiAuditLogRotated] {
+        if !env.file_exists(file) {
+            continue;
+        }
+
+        let location = env.file_location(file);
+
+        for line in ReverseLines::new(env.read_file(file)?)
+            .context(ErrorKind::FileRead(location.to_owned()))?
+        {
+            let line = line.context(ErrorKind::FileRead(location.to_owned()))?;
+
+            let entry: AuditEntry = match serde_json::from_slice(&line) {
+                Ok(entry) => entry,
+                Err(_) => continue
+            };
+
+            if entry.matches(params) {
+                entries.push(entry);
+
+                if entries.len() >= limit {
+                    return Ok(entries);
+                }
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Reads the lines of a file backwards, starting with the last line
+struct ReverseLines<R> {
+    reader: R,
+    /// Where the data which has not been read yet ends
+    position: u64,
+    /// Data which has been read but not returned yet. It ends with a
+    /// complete line, but may start in the middle of one.
+    buffer: Vec<u8>
+}
+
+impl<R: Read + Seek> ReverseLines<R> {
+    fn new(mut reader: R) -> io::Result<ReverseLines<R>> {
+        let position = reader.seek(SeekFrom::End(0))?;
+
+        Ok(ReverseLines {
+            reader,
+            position,
+            buffer: Vec::new()
+        })
+    }
+}
+
+impl<R: Read + Seek> Iterator for ReverseLines<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // The data after the last newline is a complete line. Empty lines,
+            // like the one after the final newline, are skipped.
+            if let Some(newline) = self.buffer.iter().rposition(|&byte| byte == b'\n') {
+                let line = self.buffer.split_off(newline + 1);
+                self.buffer.truncate(newline);
+
+                if line.is_empty() {
+                    continue;
+                }
+
+                return Some(Ok(line));
+            }
+
+            // The first line has no newline before it
+            if self.position == 0 {
+                if self.buffer.is_empty() {
+                    return None;
+                }
+
+                return Some(Ok(mem::replace(&mut self.buffer, Vec::new())));
+            }
+
+            let size = cmp::min(self.position, CHUNK_SIZE as u64);
+            self.position -= size;
+
+            let mut chunk = vec![0; size as usize];
+            let result = self
+                .reader
+                .seek(SeekFrom::Start(self.position))
+                .and_then(|_| self.reader.read_exact(&mut chunk));
+
+            if let Err(e) = result {
+                return Some(Err(e));
+            }
+
+            chunk.extend_from_slice(&self.buffer);
+            self.buffer = chunk;
+        }
+    }
+}
+
+/// Represents the possible GET parameters on `/audit`
+#[derive(FromForm, Default)]
+pub struct AuditParams {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    pub action: Option<String>,
+    pub outcome: Option<String>,
+    pub principal: Option<String>,
+    pub from: Option<u64>,
+    pub until: Option<u64>
+}
+
+/// Get the audit log, newest first. The log can be filtered by action prefix,
+/// outcome, principal (ex. `session:1` or `token:0a1b2c3d`), and time. `more`
+/// is true if there are older entries after this page.
+#[get("/audit?<params..>")]
+pub fn get_audit(_user: User, env: State<Env>, params: Form<AuditParams>) -> Reply {
+    let params = params.into_inner();
+    let page = params.page.unwrap_or(1);
+    let per_page = params.per_page.unwrap_or(DEFAULT_PER_PAGE);
+
+    if page == 0 || per_page == 0 || per_page > MAX_PER_PAGE {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    // Find one entry past the page, to know if there are more
+    let limit = page
+        .checked_mul(per_page)
+        .and_then(|end| end.checked_add(1))
+        .ok_or(ErrorKind::BadRequest)?;
+    let entries = find_entries(&env, &params, limit)?;
+    let more = entries.len() == limit;
+    let entries: Vec<AuditEntry> = entries
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+
+    reply_data(json!({
+        "entries": entries,
+        "page": page,
+        "per_page": per_page,
+        "more": more
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_entries, AuditEntry, AuditParams, ReverseLines};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        routes::auth::{ClientIp, Principal},
+        testing::{TestBuilder, TestEnvBuilder}
+    };
+    use rocket::http::Status;
+    use serde_json::Value;
+    use std::io::Cursor;
+
+    /// An audit log with three entries
+    const AUDIT_LOG: &str = "\
+{\"timestamp\":100,\"action\":\"dns.whitelist.add\",\"principal\":{\"type\":\"session\",\"id\":1},\"ip\":\"10.1.1.1\",\"old_value\":null,\"new_value\":\"example.com\",\"outcome\":\"success\"}
+{\"timestamp\":200,\"action\":\"dns.status\",\"principal\":{\"type\":\"token\",\"id\":\"0a1b2c3d\"},\"ip\":\"10.1.1.2\",\"old_value\":\"enabled\",\"new_value\":\"disable\",\"outcome\":\"success\"}
+{\"timestamp\":300,\"action\":\"dns.blacklist.add\",\"principal\":{\"type\":\"session\",\"id\":1},\"ip\":\"10.1.1.1\",\"old_value\":null,\"new_value\":\"$$$\",\"outcome\":\"invalid_domain\"}
+";
+
+    /// Recorded entries are appended to the log
+    #[test]
+    fn record() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::ApiAuditLog, "")
+                .build()
+        );
+
+        AuditEntry::new(
+            "dns.whitelist.add",
+            &Principal::Token("0a1b2c3d".to_owned()),
+            &ClientIp(Some("10.1.1.1".to_owned()))
+        )
+        .new_value(json!("example.com"))
+        .record(&env, &Ok(()));
+
+        let entries = find_entries(&env, &AuditParams::default(), 10).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, "dns.whitelist.add");
+        assert_eq!(
+            entries[0].principal,
+            Principal::Token("0a1b2c3d".to_owned())
+        );
+        assert_eq!(entries[0].ip, Some("10.1.1.1".to_owned()));
+        assert_eq!(entries[0].old_value, Value::Null);
+        assert_eq!(entries[0].new_value, Value::from("example.com"));
+        assert_eq!(entries[0].outcome, "success");
+    }
+
+    /// When the log would get too big, it is rotated before the entry is
+    /// appended
+    #[test]
+    fn rotate() {
+        let first_line = format!("{}\n", AUDIT_LOG.lines().next().unwrap());
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(PiholeFile::ApiAuditLog, AUDIT_LOG, &first_line)
+            .file_expect(PiholeFile::ApiAuditLogRotated, "old\n", AUDIT_LOG);
+        let test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        let entry: AuditEntry = serde_json::from_str(&first_line).unwrap();
+        entry.append(&env, AUDIT_LOG.len() as u64).unwrap();
+
+        let mut buffer = String::new();
+        for mut test_file in test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// Lines are read from the end, across chunks
+    #[test]
+    fn reverse_lines() {
+        let lines: Vec<String> = (0..2000).map(|i| format!("line {}", i)).collect();
+        let data = lines.join("\n") + "\n";
+
+        let read: Vec<String> = ReverseLines::new(Cursor::new(data.into_bytes()))
+            .unwrap()
+            .map(|line| String::from_utf8(line.unwrap()).unwrap())
+            .collect();
+
+        assert_eq!(read, lines.into_iter().rev().collect::<Vec<String>>());
+    }
+
+    /// Paging continues into the rotated log
+    #[test]
+    fn rotated_log() {
+        let mut lines = AUDIT_LOG.lines();
+        let (first, second, third) = (
+            lines.next().unwrap(),
+            lines.next().unwrap(),
+            lines.next().unwrap()
+        );
+
+        TestBuilder::new()
+            .endpoint("/admin/api/audit?per_page=1&page=2")
+            .file(PiholeFile::ApiAuditLog, third)
+            .file(
+                PiholeFile::ApiAuditLogRotated,
+                &format!("{}\n{}\n", first, second)
+            )
+            .expect_json(json!({
+                "entries": [
+                    {
+                        "timestamp": 200,
+                        "action": "dns.status",
+                        "principal": { "type": "token", "id": "0a1b2c3d" },
+                        "ip": "10.1.1.2",
+                        "old_value": "enabled",
+                        "new_value": "disable",
+                        "outcome": "success"
+                    }
+                ],
+                "page": 2,
+                "per_page": 1,
+                "more": true
+            }))
+            .test();
+    }
+
+    /// The log is returned newest first
+    #[test]
+    fn get_all() {
+        TestBuilder::new()
+            .endpoint("/admin/api/audit")
+            .file(PiholeFile::ApiAuditLog, AUDIT_LOG)
+            .expect_json(json!({
+                "entries": [
+                    {
+                        "timestamp": 300,
+                        "action": "dns.blacklist.add",
+                        "principal": { "type": "session", "id": 1 },
+                        "ip": "10.1.1.1",
+                        "old_value": null,
+                        "new_value": "$$$",
+                        "outcome": "invalid_domain"
+                    },
+                    {
+                        "timestamp": 200,
+                        "action": "dns.status",
+                        "principal": { "type": "token", "id": "0a1b2c3d" },
+                        "ip": "10.1.1.2",
+                        "old_value": "enabled",
+                        "new_value": "disable",
+                        "outcome": "success"
+                    },
+                    {
+                        "timestamp": 100,
+                        "action": "dns.whitelist.add",
+                        "principal": { "type": "session", "id": 1 },
+                        "ip": "10.1.1.1",
+                        "old_value": null,
+                        "new_value": "example.com",
+                        "outcome": "success"
+                    }
+                ],
+                "page": 1,
+                "per_page": 100,
+                "more": false
+            }))
+            .test();
+    }
+
+    /// The log can be filtered and paginated
+    #[test]
+    fn filter_and_paginate() {
+        TestBuilder::new()
+            .endpoint("/admin/api/audit?principal=session:1&per_page=1&page=2")
+            .file(PiholeFile::ApiAuditLog, AUDIT_LOG)
+            .expect_json(json!({
+                "entries": [
+                    {
+                        "timestamp": 100,
+                        "action": "dns.whitelist.add",
+                        "principal": { "type": "session", "id": 1 },
+                        "ip": "10.1.1.1",
+                        "old_value": null,
+                        "new_value": "example.com",
+                        "outcome": "success"
+                    }
+                ],
+                "page": 2,
+                "per_page": 1,
+                "more": false
+            }))
+            .test();
+    }
+
+    /// The action filter matches prefixes
+    #[test]
+    fn filter_action_prefix() {
+        TestBuilder::new()
+            .endpoint("/admin/api/audit?action=dns.status&from=150")
+            .file(PiholeFile::ApiAuditLog, AUDIT_LOG)
+            .expect_json(json!({
+                "entries": [
+                    {
+                        "timestamp": 200,
+                        "action": "dns.status",
+                        "principal": { "type": "token", "id": "0a1b2c3d" },
+                        "ip": "10.1.1.2",
+                        "old_value": "enabled",
+                        "new_value": "disable",
+                        "outcome": "success"
+                    }
+                ],
+                "page": 1,
+                "per_page": 100,
+                "more": false
+            }))
+            .test();
+    }
+
+    /// Invalid pagination is rejected
+    #[test]
+    fn invalid_page() {
+        TestBuilder::new()
+            .endpoint("/admin/api/audit?page=0")
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,159 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Cryptographic Helpers For Authentication
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::util::{Error, ErrorKind};
+use ring::{
+    constant_time::verify_slices_are_equal,
+    digest::{digest, SHA256},
+    hmac,
+    rand::{SecureRandom, SystemRandom}
+};
+
+/// Encode bytes as a lowercase hexadecimal string
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The RFC 4648 base 32 alphabet
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Encode bytes as an unpadded RFC 4648 base 32 string, as used by
+/// authenticator apps
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    output
+}
+
+/// Decode an unpadded RFC 4648 base 32 string. `None` is returned if the
+/// string contains invalid characters.
+pub fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in input.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&letter| letter == c.to_ascii_uppercase())?;
+
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(output)
+}
+
+/// Get the SHA-256 hash of the data as a lowercase hexadecimal string
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex_encode(digest(&SHA256, data).as_ref())
+}
+
+/// Get the HMAC-SHA256 of the data as a lowercase hexadecimal string
+pub fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
+    hex_encode(hmac::sign(&hmac::SigningKey::new(&SHA256, key), data).as_ref())
+}
+
+/// Hash a password the same way as the web interface, which stores a double
+/// SHA-256 hash of the password in `WEBPASSWORD`
+pub fn hash_password(password: &str) -> String {
+    sha256_hex(sha256_hex(password.as_bytes()).as_bytes())
+}
+
+/// Compare two byte strings in constant time, to avoid leaking how much of a
+/// secret was guessed correctly through response timings
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    verify_slices_are_equal(a, b).is_ok()
+}
+
+/// Generate `len` bytes from the system's secure random number generator
+pub fn random_bytes(len: usize) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![0u8; len];
+
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::from(ErrorKind::Unknown))?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        base32_decode, base32_encode, constant_time_eq, hash_password, hex_encode, hmac_sha256_hex,
+        sha256_hex
+    };
+
+    #[test]
+    fn hex() {
+        assert_eq!(hex_encode(&[0x00, 0x0f, 0xab, 0xff]), "000fabff");
+    }
+
+    #[test]
+    fn base32() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("MZXW6YTBOI"), Some(b"foobar".to_vec()));
+        assert_eq!(base32_decode("mzxw6ytboi======"), Some(b"foobar".to_vec()));
+        assert_eq!(base32_decode("MZXW6!"), None);
+    }
+
+    #[test]
+    fn sha256() {
+        assert_eq!(
+            sha256_hex(b"secret"),
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        );
+    }
+
+    /// RFC 4231 test case 2
+    #[test]
+    fn hmac_sha256() {
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn password_hash() {
+        assert_eq!(
+            hash_password("secret"),
+            sha256_hex(b"2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b")
+        );
+    }
+
+    #[test]
+    fn compare() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}
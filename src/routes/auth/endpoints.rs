@@ -0,0 +1,167 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Authentication Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    routes::auth::{AuthData, User},
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::{http::Cookies, State};
+
+/// Provides an endpoint to authenticate or check if already authenticated
+#[get("/auth")]
+pub fn check(_user: User) -> Reply {
+    reply_success()
+}
+
+/// Clears the user's authentication
+#[delete("/auth")]
+pub fn logout(user: User, cookies: Cookies, auth_data: State<AuthData>) -> Reply {
+    user.logout(cookies, &auth_data);
+    reply_success()
+}
+
+/// Get the active sessions
+#[get("/auth/sessions")]
+pub fn get_sessions(user: User, auth_data: State<AuthData>) -> Reply {
+    let sessions: Vec<_> = auth_data
+        .sessions()
+        .list()
+        .into_iter()
+        .map(|session| {
+            json!({
+                "id": session.id,
+                "created": session.created,
+                "last_seen": session.last_seen,
+                "ip": session.ip,
+                "user_agent": session.user_agent,
+                "current": session.id == user.id
+            })
+        })
+        .collect();
+
+    reply_data(sessions)
+}
+
+/// Revoke every session except for the current one
+#[delete("/auth/sessions")]
+pub fn delete_sessions(user: User, auth_data: State<AuthData>) -> Reply {
+    let revoked = auth_data.sessions().revoke_others(user.id);
+
+    reply_data(json!({ "revoked": revoked }))
+}
+
+/// Get the recent lockouts caused by failed authentication attempts
+#[get("/auth/lockouts")]
+pub fn get_lockouts(_user: User, auth_data: State<AuthData>) -> Reply {
+    reply_data(auth_data.throttle().events())
+}
+
+/// Revoke a session
+#[delete("/auth/sessions/<id>")]
+pub fn delete_session(_user: User, auth_data: State<AuthData>, id: usize) -> Reply {
+    if auth_data.sessions().revoke(id) {
+        reply_success()
+    } else {
+        Err(Error::from(ErrorKind::NotFound))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+    use rocket::http::{Header, Method, Status};
+    use serde_json::Value;
+
+    /// Providing the correct authentication should authorize the request
+    #[test]
+    fn authenticated() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .should_auth(true)
+            .expect_json(json!({
+                "status": "success"
+            }))
+            .test()
+    }
+
+    /// Providing no authorization should not authorize the request
+    #[test]
+    fn unauthenticated() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .should_auth(false)
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .test()
+    }
+
+    /// Providing incorrect authorization should not authorize the request
+    #[test]
+    fn wrong_password() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .should_auth(false)
+            .header(Header::new(
+                "X-Pi-hole-Authenticate",
+                "obviously_not_correct"
+            ))
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// Revoking an unknown session is an error
+    #[test]
+    fn delete_missing_session() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/sessions/100")
+            .method(Method::Delete)
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// Revoking the other sessions does not revoke the current session
+    #[test]
+    fn delete_other_sessions() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/sessions")
+            .method(Method::Delete)
+            .expect_json(json!({ "revoked": 0 }))
+            .test();
+    }
+
+    /// There are no lockouts by default
+    #[test]
+    fn no_lockouts() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/lockouts")
+            .expect_json(json!([]))
+            .test();
+    }
+}
@@ -0,0 +1,135 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Password Change Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::{hash_password, AuthData, ClientIp, User},
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// The shortest password which can be set
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Represents the API input for changing the password
+#[derive(Deserialize)]
+pub struct PasswordChange {
+    current_password: String,
+    new_password: String
+}
+
+impl PasswordChange {
+    /// Check if the new password is acceptable
+    fn is_valid(&self) -> bool {
+        self.new_password.chars().count() >= MIN_PASSWORD_LENGTH
+            && !self.new_password.trim().is_empty()
+            && self.new_password != self.current_password
+    }
+}
+
+/// Change the password. The current password must be given again, and all
+/// other sessions are ended.
+#[put("/auth/password", data = "<data>")]
+pub fn change_password(
+    user: User,
+    ip: ClientIp,
+    env: State<Env>,
+    auth_data: State<AuthData>,
+    data: Json<PasswordChange>
+) -> Reply {
+    let data = data.into_inner();
+
+    auth_data.check_password(&ip, &data.current_password)?;
+
+    if !data.is_valid() {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    let hash = hash_password(&data.new_password);
+    // The password hash is not a valid setting value, so that it can only be
+    // changed here
+    SetupVarsEntry::WebPassword.write_unchecked(&hash, &env)?;
+    auth_data.set_password_hash(hash);
+    auth_data.sessions().revoke_others(user.id);
+
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+    use serde_json::Value;
+
+    /// The new password is hashed and saved
+    #[test]
+    fn change_password() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/password")
+            .method(Method::Put)
+            .body(json!({
+                "current_password": "test_key",
+                "new_password": "new_password"
+            }))
+            .file_expect(
+                PiholeFile::SetupVars,
+                "WEBPASSWORD=43c1a7871221fb579dfd36c1dc7f59d29ca8327cb108ad97cd6cd5e51d79ae9b\n",
+                "WEBPASSWORD=8dc7ec63b46d1d3040cf2ad5b9ea4d606f615ec2da20394fa212ba538fd5f909\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// The current password must be correct
+    #[test]
+    fn wrong_current_password() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/password")
+            .method(Method::Put)
+            .body(json!({
+                "current_password": "wrong_password",
+                "new_password": "new_password"
+            }))
+            .file(PiholeFile::SetupVars, "")
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// Short passwords are rejected
+    #[test]
+    fn short_password() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/password")
+            .method(Method::Put)
+            .body(json!({
+                "current_password": "test_key",
+                "new_password": "short"
+            }))
+            .file_expect(PiholeFile::SetupVars, "", "")
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,163 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Scoped Authentication
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::{tokens::ApiToken, AuthData, User},
+    util::{Error, ErrorKind}
+};
+use rocket::{
+    request::{self, FromRequest, Request, State},
+    Outcome
+};
+use std::{
+    fmt::{self, Display},
+    marker::PhantomData
+};
+
+/// The header used to authenticate with an API token
+pub const TOKEN_HEADER: &str = "X-Pi-hole-Token";
+
+/// The permissions which can be granted to an API token. Users authenticated
+/// with the web password always have every scope.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "stats:read")]
+    StatsRead,
+    #[serde(rename = "lists:write")]
+    ListsWrite,
+    #[serde(rename = "settings:read")]
+    SettingsRead,
+    #[serde(rename = "settings:write")]
+    SettingsWrite,
+    #[serde(rename = "dns:status")]
+    DnsStatus,
+    #[serde(rename = "ftl:actions")]
+    FtlActions
+}
+
+/// A type level marker for a [`Scope`], used as the parameter of [`Scoped`]
+///
+/// [`Scope`]: enum.Scope.html
+/// [`Scoped`]: struct.Scoped.html
+pub trait ScopeMarker {
+    const SCOPE: Scope;
+}
+
+/// Marker types for each [`Scope`]
+///
+/// [`Scope`]: ../enum.Scope.html
+pub mod scopes {
+    use super::{Scope, ScopeMarker};
+
+    /// Create a marker type for a scope
+    macro_rules! scope_marker {
+        ($name:ident) => {
+            pub struct $name;
+
+            impl ScopeMarker for $name {
+                const SCOPE: Scope = Scope::$name;
+            }
+        };
+    }
+
+    scope_marker!(StatsRead);
+    scope_marker!(ListsWrite);
+    scope_marker!(SettingsRead);
+    scope_marker!(SettingsWrite);
+    scope_marker!(DnsStatus);
+    scope_marker!(FtlActions);
+}
+
+/// Who made an authenticated request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
+pub enum Principal {
+    /// A user authenticated with the web password (or a session cookie),
+    /// identified by the session ID
+    #[serde(rename = "session")]
+    User(usize),
+    /// An API token, identified by its ID
+    Token(String)
+}
+
+impl Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Principal::User(id) => write!(f, "session:{}", id),
+            Principal::Token(id) => write!(f, "token:{}", id)
+        }
+    }
+}
+
+impl Principal {
+    /// Check if the principal can still make requests which need the scope.
+    /// This is used by long running requests, since sessions can expire or be
+    /// revoked, and tokens can be deleted, after the request starts.
+    pub fn is_authorized(
+        &self,
+        auth_data: &AuthData,
+        env: &Env,
+        scope: Scope
+    ) -> Result<bool, Error> {
+        match self {
+            Principal::User(id) => Ok(auth_data.sessions().is_active(*id)),
+            Principal::Token(id) => Ok(ApiToken::load_all(env)?
+                .iter()
+                .any(|token| &token.id == id && token.has_scope(scope)))
+        }
+    }
+}
+
+/// When used as a request guard, requests must either be authenticated as a
+/// [`User`] or carry an API token which has the scope `S`.
+///
+/// [`User`]: struct.User.html
+pub struct Scoped<S: ScopeMarker> {
+    pub principal: Principal,
+    scope: PhantomData<S>
+}
+
+impl<'a, 'r, S: ScopeMarker> FromRequest<'a, 'r> for Scoped<S> {
+    type Error = Error;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let token = match request.headers().get_one(TOKEN_HEADER) {
+            Some(token) => token,
+            // Without a token, the request must come from a user
+            None => {
+                return User::from_request(request).map(|user| Scoped {
+                    principal: Principal::User(user.id),
+                    scope: PhantomData
+                });
+            }
+        };
+
+        let env: State<Env> = match request.guard().succeeded() {
+            Some(env) => env,
+            None => return Error::from(ErrorKind::Unknown).into_outcome()
+        };
+
+        match ApiToken::verify(&env, token) {
+            Ok(Some(api_token)) => {
+                if api_token.has_scope(S::SCOPE) {
+                    Outcome::Success(Scoped {
+                        principal: Principal::Token(api_token.id),
+                        scope: PhantomData
+                    })
+                } else {
+                    Error::from(ErrorKind::Forbidden).into_outcome()
+                }
+            }
+            Ok(None) => Error::from(ErrorKind::Unauthorized).into_outcome(),
+            Err(e) => e.into_outcome()
+        }
+    }
+}
@@ -0,0 +1,394 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Session Storage
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::routes::auth::crypto::constant_time_eq;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex
+    },
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// A logged in session. Sessions are identified by the ID stored in the
+/// user's encrypted cookie. Each session has its own CSRF token.
+#[derive(Clone, Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Session {
+    pub id: usize,
+    pub created: u64,
+    pub last_seen: u64,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    #[serde(skip)]
+    csrf_token: String
+}
+
+/// The maximum number of sessions which are kept. When a new session would go
+/// over the limit, the least recently used session is removed.
+pub const MAX_SESSIONS: usize = 100;
+
+/// The maximum number of sessions which are kept for each client IP address.
+/// This stops a client which logs in with every request from pushing out the
+/// sessions of other clients.
+pub const MAX_SESSIONS_PER_IP: usize = 10;
+
+/// Keeps track of the active sessions. Sessions expire after not being used
+/// for `idle_timeout` seconds, or `absolute_timeout` seconds after they were
+/// created.
+pub struct SessionStore {
+    sessions: Mutex<HashMap<usize, Session>>,
+    next_id: AtomicUsize,
+    idle_timeout: u64,
+    absolute_timeout: u64
+}
+
+/// Get the current Unix timestamp in seconds
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is older than epoch")
+        .as_secs()
+}
+
+impl Session {
+    /// Check if the session has expired at the time `now`
+    fn is_expired(&self, now: u64, idle_timeout: u64, absolute_timeout: u64) -> bool {
+        now >= self.last_seen + idle_timeout || now >= self.created + absolute_timeout
+    }
+}
+
+impl SessionStore {
+    /// Create an empty session store with the timeouts (in seconds)
+    pub fn new(idle_timeout: u64, absolute_timeout: u64) -> SessionStore {
+        SessionStore {
+            sessions: Mutex::new(HashMap::new()),
+            next_id: AtomicUsize::new(1),
+            idle_timeout,
+            absolute_timeout
+        }
+    }
+
+    /// Start a new session and return its ID
+    pub fn create(
+        &self,
+        csrf_token: String,
+        ip: Option<String>,
+        user_agent: Option<String>
+    ) -> usize {
+        self.create_at(now(), csrf_token, ip, user_agent)
+    }
+
+    /// Start a new session at the time `now`. Expired sessions are removed
+    /// so that they do not pile up. If the client's IP address still has too
+    /// many sessions, its least recently used session is removed, and then if
+    /// there are still too many sessions overall the least recently used one
+    /// is removed.
+    fn create_at(
+        &self,
+        now: u64,
+        csrf_token: String,
+        ip: Option<String>,
+        user_agent: Option<String>
+    ) -> usize {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        self.remove_expired(&mut sessions, now);
+
+        while sessions.values().filter(|session| session.ip == ip).count() >= MAX_SESSIONS_PER_IP {
+            remove_least_recent(&mut sessions, |session| session.ip == ip);
+        }
+
+        while sessions.len() >= MAX_SESSIONS {
+            remove_least_recent(&mut sessions, |_| true);
+        }
+
+        sessions.insert(
+            id,
+            Session {
+                id,
+                created: now,
+                last_seen: now,
+                ip,
+                user_agent,
+                csrf_token
+            }
+        );
+
+        id
+    }
+
+    /// Mark the session as used. If the session does not exist or has
+    /// expired, `false` is returned and the session is removed.
+    pub fn touch(&self, id: usize, ip: Option<String>, user_agent: Option<String>) -> bool {
+        self.touch_at(id, now(), ip, user_agent)
+    }
+
+    /// Mark the session as used at the time `now`. Expired sessions are
+    /// removed first, so the store is also cleaned up between logins.
+    fn touch_at(
+        &self,
+        id: usize,
+        now: u64,
+        ip: Option<String>,
+        user_agent: Option<String>
+    ) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.remove_expired(&mut sessions, now);
+
+        match sessions.get_mut(&id) {
+            Some(session) => {
+                session.last_seen = now;
+                session.ip = ip;
+                session.user_agent = user_agent;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Check if the session exists and has not expired, without marking it
+    /// as used
+    pub fn is_active(&self, id: usize) -> bool {
+        self.is_active_at(id, now())
+    }
+
+    /// Check if the session is active at the time `now`
+    fn is_active_at(&self, id: usize, now: u64) -> bool {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map_or(false, |session| {
+                !session.is_expired(now, self.idle_timeout, self.absolute_timeout)
+            })
+    }
+
+    /// Remove the sessions which have expired at the time `now`
+    fn remove_expired(&self, sessions: &mut HashMap<usize, Session>, now: u64) {
+        let (idle_timeout, absolute_timeout) = (self.idle_timeout, self.absolute_timeout);
+        sessions.retain(|_, session| !session.is_expired(now, idle_timeout, absolute_timeout));
+    }
+
+    /// Check if the CSRF token belongs to the session. The comparison is done
+    /// in constant time.
+    pub fn csrf_token_matches(&self, id: usize, csrf_token: &str) -> bool {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|session| constant_time_eq(session.csrf_token.as_bytes(), csrf_token.as_bytes()))
+            .unwrap_or(false)
+    }
+
+    /// Get the active sessions, sorted by ID
+    pub fn list(&self) -> Vec<Session> {
+        self.list_at(now())
+    }
+
+    /// Get the sessions which are active at the time `now`
+    fn list_at(&self, now: u64) -> Vec<Session> {
+        let mut sessions: Vec<Session> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| !session.is_expired(now, self.idle_timeout, self.absolute_timeout))
+            .cloned()
+            .collect();
+
+        sessions.sort_by_key(|session| session.id);
+        sessions
+    }
+
+    /// Revoke a session. If the session did not exist, `false` is returned.
+    pub fn revoke(&self, id: usize) -> bool {
+        self.sessions.lock().unwrap().remove(&id).is_some()
+    }
+
+    /// Revoke every session except for `keep`. The number of revoked sessions
+    /// is returned.
+    pub fn revoke_others(&self, keep: usize) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let count = sessions.len();
+
+        sessions.retain(|&id, _| id == keep);
+        count - sessions.len()
+    }
+}
+
+/// Remove the least recently used session which matches `filter`
+fn remove_least_recent<F: Fn(&Session) -> bool>(sessions: &mut HashMap<usize, Session>, filter: F) {
+    let oldest = sessions
+        .values()
+        .filter(|session| filter(session))
+        .min_by_key(|session| (session.last_seen, session.id))
+        .map(|session| session.id);
+
+    if let Some(oldest) = oldest {
+        sessions.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SessionStore, MAX_SESSIONS, MAX_SESSIONS_PER_IP};
+
+    /// Sessions are created with increasing IDs
+    #[test]
+    fn create() {
+        let store = SessionStore::new(60, 600);
+
+        assert_eq!(store.create_at(1000, String::new(), None, None), 1);
+        assert_eq!(
+            store.create_at(1000, String::new(), Some("10.1.1.1".to_owned()), None),
+            2
+        );
+        assert_eq!(store.list_at(1000).len(), 2);
+    }
+
+    /// Using a session updates the last seen time and client information
+    #[test]
+    fn touch_updates_session() {
+        let store = SessionStore::new(60, 600);
+        let id = store.create_at(1000, String::new(), None, None);
+
+        assert!(store.touch_at(
+            id,
+            1030,
+            Some("10.1.1.1".to_owned()),
+            Some("Firefox".to_owned())
+        ));
+
+        let session = store.list_at(1030).pop().unwrap();
+        assert_eq!(session.last_seen, 1030);
+        assert_eq!(session.ip, Some("10.1.1.1".to_owned()));
+        assert_eq!(session.user_agent, Some("Firefox".to_owned()));
+    }
+
+    /// Sessions expire if they are not used within the idle timeout
+    #[test]
+    fn idle_timeout() {
+        let store = SessionStore::new(60, 600);
+        let id = store.create_at(1000, String::new(), None, None);
+
+        assert!(!store.touch_at(id, 1060, None, None));
+        assert!(store.list_at(1060).is_empty());
+    }
+
+    /// Checking if a session is active does not keep it from expiring
+    #[test]
+    fn is_active() {
+        let store = SessionStore::new(60, 600);
+        let id = store.create_at(1000, String::new(), None, None);
+
+        assert!(store.is_active_at(id, 1030));
+        assert!(!store.is_active_at(id, 1060));
+        assert!(!store.is_active_at(id + 1, 1030));
+    }
+
+    /// Sessions expire after the absolute timeout, even if they are in use
+    #[test]
+    fn absolute_timeout() {
+        let store = SessionStore::new(60, 100);
+        let id = store.create_at(1000, String::new(), None, None);
+
+        assert!(store.touch_at(id, 1050, None, None));
+        assert!(store.touch_at(id, 1099, None, None));
+        assert!(!store.touch_at(id, 1100, None, None));
+    }
+
+    /// The CSRF token must match the session's token
+    #[test]
+    fn csrf_token() {
+        let store = SessionStore::new(60, 600);
+        let first = store.create_at(1000, "first_token".to_owned(), None, None);
+        let second = store.create_at(1000, "second_token".to_owned(), None, None);
+
+        assert!(store.csrf_token_matches(first, "first_token"));
+        assert!(!store.csrf_token_matches(first, "second_token"));
+        assert!(!store.csrf_token_matches(second, ""));
+        assert!(!store.csrf_token_matches(100, "first_token"));
+    }
+
+    /// Revoked sessions can not be used
+    #[test]
+    fn revoke() {
+        let store = SessionStore::new(60, 600);
+        let id = store.create_at(1000, String::new(), None, None);
+
+        assert!(store.revoke(id));
+        assert!(!store.revoke(id));
+        assert!(!store.touch_at(id, 1000, None, None));
+    }
+
+    /// Revoking other sessions keeps the current session
+    #[test]
+    fn revoke_others() {
+        let store = SessionStore::new(60, 600);
+        let first = store.create_at(1000, String::new(), None, None);
+        store.create_at(1000, String::new(), None, None);
+        store.create_at(1000, String::new(), None, None);
+
+        assert_eq!(store.revoke_others(first), 2);
+        assert_eq!(store.list_at(1000).len(), 1);
+        assert!(store.touch_at(first, 1000, None, None));
+    }
+
+    /// Using a session removes the other expired sessions
+    #[test]
+    fn touch_removes_expired() {
+        let store = SessionStore::new(60, 600);
+        store.create_at(1000, String::new(), None, None);
+        let id = store.create_at(1050, String::new(), None, None);
+
+        assert!(store.touch_at(id, 1070, None, None));
+        assert_eq!(store.sessions.lock().unwrap().len(), 1);
+    }
+
+    /// When there are too many sessions, the least recently used session is
+    /// removed
+    #[test]
+    fn max_sessions() {
+        let ip = |i: usize| Some(format!("10.0.{}.{}", i / 256, i % 256));
+        let store = SessionStore::new(600, 6000);
+        let first = store.create_at(1000, String::new(), ip(0), None);
+        let second = store.create_at(1001, String::new(), ip(1), None);
+        assert!(store.touch_at(first, 1002, ip(0), None));
+
+        for i in 0..MAX_SESSIONS - 1 {
+            store.create_at(1003 + i as u64, String::new(), ip(i + 2), None);
+        }
+
+        assert_eq!(store.list_at(1200).len(), MAX_SESSIONS);
+        assert!(store.touch_at(first, 1200, ip(0), None));
+        assert!(!store.touch_at(second, 1200, ip(1), None));
+    }
+
+    /// When an IP address has too many sessions, its least recently used
+    /// session is removed, and the sessions of other IP addresses are kept
+    #[test]
+    fn max_sessions_per_ip() {
+        let ip = || Some("10.0.0.1".to_owned());
+        let store = SessionStore::new(600, 6000);
+        let other = store.create_at(1000, String::new(), Some("10.0.0.2".to_owned()), None);
+        let first = store.create_at(1001, String::new(), ip(), None);
+
+        for i in 0..MAX_SESSIONS_PER_IP {
+            store.create_at(1002 + i as u64, String::new(), ip(), None);
+        }
+
+        assert_eq!(store.list_at(1100).len(), MAX_SESSIONS_PER_IP + 1);
+        assert!(store.touch_at(other, 1100, None, None));
+        assert!(!store.touch_at(first, 1100, None, None));
+    }
+}
@@ -0,0 +1,208 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Login Throttling
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use std::{
+    cmp::min,
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// How many failed attempts a client can make before being locked out
+const FREE_ATTEMPTS: u32 = 5;
+/// The length of the first lockout, in seconds. Each failed attempt after
+/// that doubles the lockout.
+const BASE_LOCKOUT: u64 = 5;
+/// The longest possible lockout, in seconds
+const MAX_LOCKOUT: u64 = 15 * 60;
+/// How long failed attempts are remembered for, in seconds
+const FORGET_AFTER: u64 = 60 * 60;
+/// How many lockout events are kept for the lockout endpoint
+const MAX_EVENTS: usize = 100;
+
+/// The failed attempts from a single client
+struct Attempts {
+    failures: u32,
+    last_failure: u64,
+    locked_until: u64
+}
+
+/// Records a client being locked out
+#[derive(Clone, Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct LockoutEvent {
+    pub ip: String,
+    pub failures: u32,
+    pub locked_at: u64,
+    pub locked_until: u64
+}
+
+/// The number of seconds a locked out client has to wait. This is stored in
+/// the request's local cache so that the 429 catcher can include it in the
+/// error.
+#[derive(Copy, Clone)]
+pub struct RetryAfter(pub u64);
+
+/// Tracks failed authentication attempts per client IP, and locks out
+/// clients which fail too often. Lockouts grow exponentially with each
+/// failure.
+#[derive(Default)]
+pub struct LoginThrottle {
+    attempts: Mutex<HashMap<String, Attempts>>,
+    events: Mutex<VecDeque<LockoutEvent>>
+}
+
+/// Get the current Unix timestamp in seconds
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is older than epoch")
+        .as_secs()
+}
+
+/// Get the lockout duration (in seconds) for the number of failures
+fn lockout_duration(failures: u32) -> u64 {
+    if failures < FREE_ATTEMPTS {
+        return 0;
+    }
+
+    // Limit the shift to avoid overflowing
+    let exponent = min(failures - FREE_ATTEMPTS, 16);
+    min(BASE_LOCKOUT << exponent, MAX_LOCKOUT)
+}
+
+impl LoginThrottle {
+    /// Get how many seconds the client must wait before trying again. If the
+    /// client is not locked out, `None` is returned.
+    pub fn retry_after(&self, ip: &str) -> Option<u64> {
+        self.retry_after_at(ip, now())
+    }
+
+    /// Get how many seconds the client must wait at the time `now`
+    fn retry_after_at(&self, ip: &str, now: u64) -> Option<u64> {
+        self.attempts
+            .lock()
+            .unwrap()
+            .get(ip)
+            .filter(|attempts| attempts.locked_until > now)
+            .map(|attempts| attempts.locked_until - now)
+    }
+
+    /// Record a failed attempt from the client
+    pub fn failure(&self, ip: &str) {
+        self.failure_at(ip, now())
+    }
+
+    /// Record a failed attempt from the client at the time `now`
+    fn failure_at(&self, ip: &str, now: u64) {
+        let mut attempts = self.attempts.lock().unwrap();
+
+        // Forget old failures so that clients are not punished forever
+        attempts.retain(|_, client| {
+            client.last_failure + FORGET_AFTER > now || client.locked_until > now
+        });
+
+        let client = attempts.entry(ip.to_owned()).or_insert(Attempts {
+            failures: 0,
+            last_failure: now,
+            locked_until: 0
+        });
+        client.failures += 1;
+        client.last_failure = now;
+
+        let lockout = lockout_duration(client.failures);
+
+        if lockout > 0 {
+            client.locked_until = now + lockout;
+
+            let mut events = self.events.lock().unwrap();
+            if events.len() >= MAX_EVENTS {
+                events.pop_front();
+            }
+            events.push_back(LockoutEvent {
+                ip: ip.to_owned(),
+                failures: client.failures,
+                locked_at: now,
+                locked_until: client.locked_until
+            });
+        }
+    }
+
+    /// Clear the failed attempts of the client after it authenticated
+    /// successfully
+    pub fn success(&self, ip: &str) {
+        self.attempts.lock().unwrap().remove(ip);
+    }
+
+    /// Get the recent lockout events, oldest first
+    pub fn events(&self) -> Vec<LockoutEvent> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lockout_duration, LoginThrottle, MAX_LOCKOUT};
+
+    /// Lockouts double with each failure, up to the maximum
+    #[test]
+    fn lockout_durations() {
+        assert_eq!(lockout_duration(4), 0);
+        assert_eq!(lockout_duration(5), 5);
+        assert_eq!(lockout_duration(6), 10);
+        assert_eq!(lockout_duration(7), 20);
+        assert_eq!(lockout_duration(100), MAX_LOCKOUT);
+    }
+
+    /// Clients are not locked out for the first few failures
+    #[test]
+    fn free_attempts() {
+        let throttle = LoginThrottle::default();
+
+        for _ in 0..4 {
+            throttle.failure_at("10.1.1.1", 1000);
+        }
+
+        assert_eq!(throttle.retry_after_at("10.1.1.1", 1000), None);
+        assert!(throttle.events().is_empty());
+    }
+
+    /// Clients are locked out after too many failures, until the lockout is
+    /// over
+    #[test]
+    fn lockout() {
+        let throttle = LoginThrottle::default();
+
+        for _ in 0..5 {
+            throttle.failure_at("10.1.1.1", 1000);
+        }
+
+        assert_eq!(throttle.retry_after_at("10.1.1.1", 1000), Some(5));
+        assert_eq!(throttle.retry_after_at("10.1.1.1", 1003), Some(2));
+        assert_eq!(throttle.retry_after_at("10.1.1.1", 1005), None);
+        assert_eq!(throttle.retry_after_at("10.1.1.2", 1000), None);
+        assert_eq!(throttle.events().len(), 1);
+        assert_eq!(throttle.events()[0].ip, "10.1.1.1");
+    }
+
+    /// A successful login clears the failures
+    #[test]
+    fn success_resets() {
+        let throttle = LoginThrottle::default();
+
+        for _ in 0..5 {
+            throttle.failure_at("10.1.1.1", 1000);
+        }
+        throttle.success("10.1.1.1");
+        throttle.failure_at("10.1.1.1", 1000);
+
+        assert_eq!(throttle.retry_after_at("10.1.1.1", 1000), None);
+    }
+}
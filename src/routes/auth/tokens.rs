@@ -0,0 +1,357 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// API Token Storage And Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    routes::auth::{
+        crypto::{constant_time_eq, hex_encode, random_bytes, sha256_hex},
+        Scope, User
+    },
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use failure::ResultExt;
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// A named API token, as stored in [`PiholeFile::ApiTokens`]. Only the hash
+/// of the token's secret is stored.
+///
+/// [`PiholeFile::ApiTokens`]: ../../../env/enum.PiholeFile.html
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    hash: String,
+    pub created: u64
+}
+
+/// The public information about a token, used in API responses
+#[derive(Serialize)]
+pub struct ApiTokenReply<'a> {
+    id: &'a str,
+    name: &'a str,
+    scopes: &'a [Scope],
+    created: u64
+}
+
+/// Represents the API input for creating a token
+#[derive(Deserialize)]
+pub struct NewToken {
+    name: String,
+    scopes: Vec<Scope>
+}
+
+impl ApiToken {
+    /// Load all tokens from [`PiholeFile::ApiTokens`]. A missing or empty file
+    /// means there are no tokens.
+    ///
+    /// [`PiholeFile::ApiTokens`]: ../../../env/enum.PiholeFile.html
+    pub fn load_all(env: &Env) -> Result<Vec<ApiToken>, Error> {
+        if !env.file_exists(PiholeFile::ApiTokens) {
+            return Ok(Vec::new());
+        }
+
+        let mut buffer = String::new();
+        env.read_file(PiholeFile::ApiTokens)?
+            .read_to_string(&mut buffer)
+            .context(ErrorKind::FileRead(
+                env.file_location(PiholeFile::ApiTokens).to_owned()
+            ))?;
+
+        if buffer.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        serde_json::from_str(&buffer)
+            .context(ErrorKind::FileRead(
+                env.file_location(PiholeFile::ApiTokens).to_owned()
+            ))
+            .map_err(Error::from)
+    }
+
+    /// Overwrite [`PiholeFile::ApiTokens`] with the tokens
+    ///
+    /// [`PiholeFile::ApiTokens`]: ../../../env/enum.PiholeFile.html
+    pub fn save_all(tokens: &[ApiToken], env: &Env) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(tokens).context(ErrorKind::Unknown)?;
+
+        env.write_file(PiholeFile::ApiTokens, false)?
+            .write_all(&data)
+            .context(ErrorKind::FileWrite(
+                env.file_location(PiholeFile::ApiTokens).to_owned()
+            ))?;
+
+        Ok(())
+    }
+
+    /// Generate a new token. The token and the full secret (in the form
+    /// `id.secret`) are returned. The secret can not be recovered later.
+    fn generate(name: String, scopes: Vec<Scope>) -> Result<(ApiToken, String), Error> {
+        let id = hex_encode(&random_bytes(4)?);
+        let secret = encode_config(&random_bytes(32)?, URL_SAFE_NO_PAD);
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Current time is older than epoch")
+            .as_secs();
+
+        let token = ApiToken {
+            id: id.clone(),
+            name,
+            scopes,
+            hash: sha256_hex(secret.as_bytes()),
+            created
+        };
+
+        Ok((token, format!("{}.{}", id, secret)))
+    }
+
+    /// Find the token matching `input` (in the form `id.secret`). `None` is
+    /// returned if no token matches.
+    pub fn verify(env: &Env, input: &str) -> Result<Option<ApiToken>, Error> {
+        let mut split = input.splitn(2, '.');
+        let (id, secret) = match (split.next(), split.next()) {
+            (Some(id), Some(secret)) => (id, secret),
+            _ => return Ok(None)
+        };
+        let hash = sha256_hex(secret.as_bytes());
+
+        Ok(ApiToken::load_all(env)?.into_iter().find(|token| {
+            token.id == id && constant_time_eq(token.hash.as_bytes(), hash.as_bytes())
+        }))
+    }
+
+    /// Check if the token was granted the scope
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Convert the token into the reply format, which does not include the
+    /// hash
+    fn as_reply(&self) -> ApiTokenReply {
+        ApiTokenReply {
+            id: &self.id,
+            name: &self.name,
+            scopes: &self.scopes,
+            created: self.created
+        }
+    }
+}
+
+/// Get the list of API tokens
+#[get("/auth/tokens")]
+pub fn get_tokens(_auth: User, env: State<Env>) -> Reply {
+    let tokens = ApiToken::load_all(&env)?;
+    let replies: Vec<ApiTokenReply> = tokens.iter().map(ApiToken::as_reply).collect();
+
+    reply_data(replies)
+}
+
+/// Create a new API token. The secret is only shown in this response.
+#[post("/auth/tokens", data = "<data>")]
+pub fn create_token(_auth: User, env: State<Env>, data: Json<NewToken>) -> Reply {
+    let data = data.into_inner();
+
+    if data.name.trim().is_empty() || data.scopes.is_empty() {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    let mut tokens = ApiToken::load_all(&env)?;
+    let (token, secret) = ApiToken::generate(data.name, data.scopes)?;
+
+    // The ID is short, so make sure it is unique
+    if tokens.iter().any(|existing| existing.id == token.id) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    tokens.push(token.clone());
+    ApiToken::save_all(&tokens, &env)?;
+
+    reply_data(json!({
+        "id": token.id,
+        "name": token.name,
+        "scopes": token.scopes,
+        "created": token.created,
+        "token": secret
+    }))
+}
+
+/// Revoke an API token
+#[delete("/auth/tokens/<id>")]
+pub fn delete_token(_auth: User, env: State<Env>, id: String) -> Reply {
+    let mut tokens = ApiToken::load_all(&env)?;
+    let count = tokens.len();
+
+    tokens.retain(|token| token.id != id);
+
+    if tokens.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    ApiToken::save_all(&tokens, &env)?;
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use super::ApiToken;
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        routes::auth::Scope,
+        testing::{TestBuilder, TestEnvBuilder}
+    };
+    use rocket::http::{Header, Method, Status};
+    use serde_json::Value;
+
+    /// A token file with one read only token. The secret is "secret".
+    const TOKENS: &str = r#"[{"id":"0a1b2c3d","name":"grafana","scopes":["stats:read"],"hash":"2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b","created":1550000000}]"#;
+
+    /// A token with the correct secret is found
+    #[test]
+    fn verify_valid() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::ApiTokens, TOKENS)
+                .build()
+        );
+
+        let token = ApiToken::verify(&env, "0a1b2c3d.secret").unwrap().unwrap();
+
+        assert_eq!(token.name, "grafana");
+        assert!(token.has_scope(Scope::StatsRead));
+        assert!(!token.has_scope(Scope::SettingsWrite));
+    }
+
+    /// A token with an incorrect secret or ID is not found
+    #[test]
+    fn verify_invalid() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::ApiTokens, TOKENS)
+                .build()
+        );
+
+        assert_eq!(ApiToken::verify(&env, "0a1b2c3d.wrong").unwrap(), None);
+        assert_eq!(ApiToken::verify(&env, "ffffffff.secret").unwrap(), None);
+        assert_eq!(ApiToken::verify(&env, "secret").unwrap(), None);
+    }
+
+    /// A token with the required scope can access the endpoint
+    #[test]
+    fn token_with_scope() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/query_types")
+            .should_auth(false)
+            .header(Header::new("X-Pi-hole-Token", "0a1b2c3d.secret"))
+            .file(PiholeFile::ApiTokens, TOKENS)
+            .expect_json(json!([
+                { "name": "A",    "count": 0 },
+                { "name": "AAAA", "count": 0 },
+                { "name": "ANY",  "count": 0 },
+                { "name": "SRV",  "count": 0 },
+                { "name": "SOA",  "count": 0 },
+                { "name": "PTR",  "count": 0 },
+                { "name": "TXT",  "count": 0 }
+            ]))
+            .test();
+    }
+
+    /// A token without the required scope is forbidden
+    #[test]
+    fn token_without_scope() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/status")
+            .method(Method::Post)
+            .should_auth(false)
+            .header(Header::new("X-Pi-hole-Token", "0a1b2c3d.secret"))
+            .file(PiholeFile::ApiTokens, TOKENS)
+            .body(json!({ "action": "disable" }))
+            .expect_status(Status::Forbidden)
+            .expect_json(json!({
+                "error": {
+                    "key": "forbidden",
+                    "message": "Forbidden",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// Tokens can not be used to manage tokens
+    #[test]
+    fn token_can_not_list_tokens() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/tokens")
+            .should_auth(false)
+            .header(Header::new("X-Pi-hole-Token", "0a1b2c3d.secret"))
+            .file(PiholeFile::ApiTokens, TOKENS)
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// The token list does not include the hashes
+    #[test]
+    fn list_tokens() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/tokens")
+            .file(PiholeFile::ApiTokens, TOKENS)
+            .expect_json(json!([{
+                "id": "0a1b2c3d",
+                "name": "grafana",
+                "scopes": ["stats:read"],
+                "created": 1_550_000_000
+            }]))
+            .test();
+    }
+
+    /// Deleting a token removes it from the file
+    #[test]
+    fn delete_token() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/tokens/0a1b2c3d")
+            .method(Method::Delete)
+            .file_expect(PiholeFile::ApiTokens, TOKENS, "[]")
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Deleting an unknown token is an error
+    #[test]
+    fn delete_missing_token() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/tokens/ffffffff")
+            .method(Method::Delete)
+            .file(PiholeFile::ApiTokens, TOKENS)
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+}
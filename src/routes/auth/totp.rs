@@ -0,0 +1,474 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// TOTP Two-Factor Authentication
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    routes::auth::{
+        crypto::{base32_decode, base32_encode, constant_time_eq, random_bytes, sha256_hex},
+        AuthData, User
+    },
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use failure::ResultExt;
+use ring::{digest, hmac};
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// The header used to send the TOTP code (or a recovery code) when logging in
+pub const TOTP_HEADER: &str = "X-Pi-hole-TOTP";
+
+/// The length of a time step, in seconds
+const TIME_STEP: u64 = 30;
+/// The number of digits in a code
+const DIGITS: usize = 6;
+/// How many time steps before or after the current one are accepted, to
+/// allow for clock drift
+const ALLOWED_DRIFT: u64 = 1;
+/// The length of the secret, in bytes
+const SECRET_LENGTH: usize = 20;
+/// How many recovery codes are generated
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// The TOTP settings, as stored in [`PiholeFile::ApiTotp`]. The secret is
+/// stored base 32 encoded, and only the hashes of the recovery codes are
+/// stored. The time step of the last accepted code is stored so that codes
+/// can not be used more than once.
+///
+/// [`PiholeFile::ApiTotp`]: ../../../env/enum.PiholeFile.html
+#[derive(Serialize, Deserialize, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct TotpConfig {
+    secret: String,
+    enabled: bool,
+    recovery_codes: Vec<String>,
+    #[serde(default)]
+    last_step: u64
+}
+
+/// Represents the API input for verifying or disabling TOTP
+#[derive(Deserialize)]
+pub struct TotpCode {
+    code: String
+}
+
+/// Get the current Unix timestamp in seconds
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is older than epoch")
+        .as_secs()
+}
+
+/// Generate the RFC 6238 code for the time step `step`
+fn totp(secret: &[u8], step: u64) -> u32 {
+    let key = hmac::SigningKey::new(&digest::SHA1, secret);
+    let signature = hmac::sign(&key, &step.to_be_bytes());
+    let hash = signature.as_ref();
+
+    // Dynamic truncation, see RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = (hash[offset] as u32 & 0x7f) << 24
+        | (hash[offset + 1] as u32) << 16
+        | (hash[offset + 2] as u32) << 8
+        | hash[offset + 3] as u32;
+
+    binary % 10u32.pow(DIGITS as u32)
+}
+
+/// Check if the code is valid for the secret at the time `now`. Codes from
+/// time steps at or before `last_step` have already been used, so they are
+/// rejected. If the code is valid, its time step is returned.
+fn verify_code(secret: &[u8], code: &str, now: u64, last_step: u64) -> Option<u64> {
+    if code.len() != DIGITS || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let current_step = now / TIME_STEP;
+    let first_step = current_step.saturating_sub(ALLOWED_DRIFT);
+
+    (first_step..=current_step + ALLOWED_DRIFT)
+        .filter(|&step| step > last_step)
+        .find(|&step| {
+            let expected = format!("{:0width$}", totp(secret, step), width = DIGITS);
+            constant_time_eq(expected.as_bytes(), code.as_bytes())
+        })
+}
+
+impl TotpConfig {
+    /// Load the TOTP settings. If the file does not exist or is empty, TOTP
+    /// is disabled.
+    fn load(env: &Env) -> Result<TotpConfig, Error> {
+        if !env.file_exists(PiholeFile::ApiTotp) {
+            return Ok(TotpConfig::default());
+        }
+
+        let mut buffer = String::new();
+        env.read_file(PiholeFile::ApiTotp)?
+            .read_to_string(&mut buffer)
+            .context(ErrorKind::FileRead(
+                env.file_location(PiholeFile::ApiTotp).to_owned()
+            ))?;
+
+        if buffer.trim().is_empty() {
+            return Ok(TotpConfig::default());
+        }
+
+        serde_json::from_str(&buffer)
+            .context(ErrorKind::FileRead(
+                env.file_location(PiholeFile::ApiTotp).to_owned()
+            ))
+            .map_err(Error::from)
+    }
+
+    /// Overwrite the TOTP settings
+    fn save(&self, env: &Env) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(self).context(ErrorKind::Unknown)?;
+
+        env.write_file(PiholeFile::ApiTotp, false)?
+            .write_all(&data)
+            .context(ErrorKind::FileWrite(
+                env.file_location(PiholeFile::ApiTotp).to_owned()
+            ))?;
+
+        Ok(())
+    }
+
+    /// Check a TOTP code against the secret. If it is valid, its time step is
+    /// recorded so the code can not be used again. The settings must be saved
+    /// afterwards for this to last.
+    fn verify(&mut self, code: &str, now: u64) -> bool {
+        let step = base32_decode(&self.secret)
+            .and_then(|secret| verify_code(&secret, code, now, self.last_step));
+
+        match step {
+            Some(step) => {
+                self.last_step = step;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Use up a recovery code. If the code is not a valid recovery code,
+    /// `false` is returned.
+    fn use_recovery_code(&mut self, code: &str) -> bool {
+        let hash = sha256_hex(code.trim().to_lowercase().as_bytes());
+        let count = self.recovery_codes.len();
+
+        self.recovery_codes
+            .retain(|stored| !constant_time_eq(stored.as_bytes(), hash.as_bytes()));
+
+        self.recovery_codes.len() != count
+    }
+}
+
+/// Check the second factor of a client logging in. If TOTP is not enabled,
+/// this always succeeds. Otherwise, `code` must be a valid TOTP code or an
+/// unused recovery code.
+pub fn check_second_factor(
+    auth_data: &AuthData,
+    env: &Env,
+    code: Option<&str>
+) -> Result<(), Error> {
+    let _lock = auth_data.lock_totp();
+    let mut config = TotpConfig::load(env)?;
+
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let code = match code {
+        Some(code) => code,
+        None => return Err(Error::from(ErrorKind::Unauthorized))
+    };
+
+    if config.verify(code, now()) || config.use_recovery_code(code) {
+        config.save(env)?;
+        return Ok(());
+    }
+
+    Err(Error::from(ErrorKind::Unauthorized))
+}
+
+/// Generate a set of recovery codes. The codes and their hashes are returned.
+fn generate_recovery_codes() -> Result<(Vec<String>, Vec<String>), Error> {
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    let mut hashes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = base32_encode(&random_bytes(5)?).to_lowercase();
+        hashes.push(sha256_hex(code.as_bytes()));
+        codes.push(code);
+    }
+
+    Ok((codes, hashes))
+}
+
+/// Get the TOTP status
+#[get("/auth/totp")]
+pub fn get_totp(_user: User, env: State<Env>) -> Reply {
+    let config = TotpConfig::load(&env)?;
+
+    reply_data(json!({
+        "enabled": config.enabled,
+        "recovery_codes_left": config.recovery_codes.len()
+    }))
+}
+
+/// Start enrolling in TOTP by generating a new secret. TOTP is not enabled
+/// until a code is verified with [`verify_totp`].
+///
+/// [`verify_totp`]: fn.verify_totp.html
+#[post("/auth/totp")]
+pub fn enroll_totp(_user: User, env: State<Env>, auth_data: State<AuthData>) -> Reply {
+    let _lock = auth_data.lock_totp();
+
+    if TotpConfig::load(&env)?.enabled {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    let secret = base32_encode(&random_bytes(SECRET_LENGTH)?);
+    let config = TotpConfig {
+        secret: secret.clone(),
+        enabled: false,
+        recovery_codes: Vec::new(),
+        last_step: 0
+    };
+    config.save(&env)?;
+
+    reply_data(json!({
+        "secret": secret,
+        "uri": format!(
+            "otpauth://totp/Pi-hole:admin?secret={}&issuer=Pi-hole&digits={}&period={}",
+            secret, DIGITS, TIME_STEP
+        )
+    }))
+}
+
+/// Finish enrolling in TOTP by verifying the first code. The recovery codes
+/// are only shown in this response.
+#[post("/auth/totp/verify", data = "<data>")]
+pub fn verify_totp(
+    _user: User,
+    env: State<Env>,
+    auth_data: State<AuthData>,
+    data: Json<TotpCode>
+) -> Reply {
+    let _lock = auth_data.lock_totp();
+    let mut config = TotpConfig::load(&env)?;
+
+    if config.enabled {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    if config.secret.is_empty() {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    if !config.verify(&data.code, now()) {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    let (codes, hashes) = generate_recovery_codes()?;
+    config.enabled = true;
+    config.recovery_codes = hashes;
+    config.save(&env)?;
+
+    reply_data(json!({ "recovery_codes": codes }))
+}
+
+/// Disable TOTP. A valid code is required.
+#[delete("/auth/totp", data = "<data>")]
+pub fn disable_totp(
+    _user: User,
+    env: State<Env>,
+    auth_data: State<AuthData>,
+    data: Json<TotpCode>
+) -> Reply {
+    let _lock = auth_data.lock_totp();
+    let mut config = TotpConfig::load(&env)?;
+
+    if !config.enabled {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    if !config.verify(&data.code, now()) {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    TotpConfig::default().save(&env)?;
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{totp, verify_code, TotpConfig};
+    use crate::{
+        env::PiholeFile,
+        routes::auth::crypto::{base32_encode, sha256_hex},
+        testing::TestBuilder
+    };
+    use rocket::http::{Header, Status};
+    use serde_json::Value;
+
+    /// The secret used in the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    /// The codes match the SHA-1 test vectors from RFC 6238 (truncated to six
+    /// digits)
+    #[test]
+    fn rfc_test_vectors() {
+        assert_eq!(totp(RFC_SECRET, 59 / 30), 287_082);
+        assert_eq!(totp(RFC_SECRET, 1_111_111_109 / 30), 81_804);
+        assert_eq!(totp(RFC_SECRET, 1_234_567_890 / 30), 5_924);
+        assert_eq!(totp(RFC_SECRET, 2_000_000_000 / 30), 279_037);
+    }
+
+    /// Codes from the previous and next time step are accepted, but not older
+    /// ones
+    #[test]
+    fn clock_drift() {
+        let step = 1_111_111_109 / 30;
+
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1_111_111_109, 0),
+            Some(step)
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1_111_111_109 + 30, 0),
+            Some(step)
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1_111_111_109 - 30, 0),
+            Some(step)
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1_111_111_109 + 90, 0),
+            None
+        );
+    }
+
+    /// Malformed codes are rejected
+    #[test]
+    fn malformed_code() {
+        assert_eq!(verify_code(RFC_SECRET, "81804", 1_111_111_109, 0), None);
+        assert_eq!(verify_code(RFC_SECRET, "08180a", 1_111_111_109, 0), None);
+    }
+
+    /// Codes from the last accepted time step or earlier are rejected
+    #[test]
+    fn replayed_code() {
+        let step = 1_111_111_109 / 30;
+
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1_111_111_109, step - 1),
+            Some(step)
+        );
+        assert_eq!(verify_code(RFC_SECRET, "081804", 1_111_111_109, step), None);
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1_111_111_109 + 30, step + 1),
+            None
+        );
+    }
+
+    /// A code can only be used once
+    #[test]
+    fn code_used_once() {
+        let mut config = TotpConfig {
+            secret: base32_encode(RFC_SECRET),
+            enabled: true,
+            recovery_codes: Vec::new(),
+            last_step: 0
+        };
+
+        assert!(config.verify("081804", 1_111_111_109));
+        assert_eq!(config.last_step, 1_111_111_109 / 30);
+        assert!(!config.verify("081804", 1_111_111_109));
+    }
+
+    /// Recovery codes can only be used once
+    #[test]
+    fn recovery_code() {
+        let mut config = TotpConfig {
+            secret: base32_encode(RFC_SECRET),
+            enabled: true,
+            recovery_codes: vec![sha256_hex(b"abcdefgh")],
+            last_step: 0
+        };
+
+        assert!(config.use_recovery_code("ABCDEFGH"));
+        assert!(!config.use_recovery_code("abcdefgh"));
+        assert!(config.recovery_codes.is_empty());
+    }
+
+    /// TOTP is disabled by default
+    #[test]
+    fn status_disabled() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/totp")
+            .expect_json(json!({
+                "enabled": false,
+                "recovery_codes_left": 0
+            }))
+            .test();
+    }
+
+    /// When TOTP is enabled, logging in requires a code
+    #[test]
+    fn login_requires_code() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .file(
+                PiholeFile::ApiTotp,
+                &json!({
+                    "secret": base32_encode(RFC_SECRET),
+                    "enabled": true,
+                    "recovery_codes": []
+                })
+                .to_string()
+            )
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// A recovery code can be used instead of a TOTP code
+    #[test]
+    fn login_with_recovery_code() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .header(Header::new("X-Pi-hole-TOTP", "abcdefgh"))
+            .file_expect(
+                PiholeFile::ApiTotp,
+                &json!({
+                    "secret": base32_encode(RFC_SECRET),
+                    "enabled": true,
+                    "recovery_codes": [sha256_hex(b"abcdefgh")]
+                })
+                .to_string(),
+                &format!(
+                    "{{\n  \"secret\": \"{}\",\n  \"enabled\": true,\n  \"recovery_codes\": [],\n  \"last_step\": 0\n}}",
+                    base32_encode(RFC_SECRET)
+                )
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
@@ -0,0 +1,389 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// User Authentication
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    proxy,
+    routes::auth::{
+        check_second_factor,
+        crypto::{constant_time_eq, hash_password, random_bytes},
+        LoginThrottle, RetryAfter, SessionStore, TOTP_HEADER
+    },
+    util::{Error, ErrorKind}
+};
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use rocket::{
+    http::{Cookie, Cookies, Method, Status},
+    request::{self, FromRequest, Request, State},
+    Outcome
+};
+use std::sync::{Mutex, MutexGuard, RwLock};
+
+const USER_ATTR: &str = "user_id";
+const AUTH_HEADER: &str = "X-Pi-hole-Authenticate";
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// When used as a request guard, requests must be authenticated
+pub struct User {
+    pub id: usize
+}
+
+/// The IP address of the client, if known. When used as a request guard, it
+/// never fails.
+pub struct ClientIp(pub Option<String>);
+
+/// Stores the password hash, the active sessions, and the failed login
+/// attempts in the server state
+pub struct AuthData {
+    key: RwLock<String>,
+    legacy_hash_login: bool,
+    sessions: SessionStore,
+    throttle: LoginThrottle,
+    totp: Mutex<()>
+}
+
+impl User {
+    /// Try to authenticate the user using `input_key` (and the TOTP code, if
+    /// enabled). If it succeeds, a new session and cookie will be created,
+    /// unless the client's cookie already has an active session. Clients which
+    /// fail too often are locked out for a while.
+    fn authenticate(request: &Request, input_key: &str) -> request::Outcome<Self, Error> {
+        let auth_data: State<AuthData> = match request.guard().succeeded() {
+            Some(auth_data) => auth_data,
+            None => return Error::from(ErrorKind::Unknown).into_outcome()
+        };
+        let env: State<Env> = match request.guard().succeeded() {
+            Some(env) => env,
+            None => return Error::from(ErrorKind::Unknown).into_outcome()
+        };
+        let ip = ClientIp(client_ip(request));
+
+        // Clients which send the password with every request keep using their
+        // session, and only the password is checked. The TOTP code is only
+        // needed to start a session, since a code can not be used twice.
+        let session = session_id(request).filter(|&id| {
+            auth_data
+                .sessions
+                .touch(id, client_ip(request), user_agent(request))
+        });
+
+        let result = match session {
+            Some(_) => auth_data.check_password(&ip, input_key),
+            None => {
+                let code = request.headers().get_one(TOTP_HEADER);
+                auth_data.check_login(&env, &ip, input_key, code)
+            }
+        };
+
+        if let Err(e) = result {
+            if let ErrorKind::TooManyRequests(retry_after) = e.kind() {
+                // Save the wait time for the 429 catcher
+                request.local_cache(|| RetryAfter(retry_after));
+            }
+
+            return e.into_outcome();
+        }
+
+        if let Some(id) = session {
+            return Outcome::Success(User { id });
+        }
+
+        let (user, csrf_token) = match auth_data.create_user(request) {
+            Ok(created) => created,
+            Err(e) => return e.into_outcome()
+        };
+
+        // Only send the cookies over HTTPS if it is enabled
+        let secure = env.config().tls_enabled();
+
+        // Set a new encrypted cookie with the user's ID
+        request.cookies().add_private(
+            Cookie::build(USER_ATTR, user.id.to_string())
+                // Allow the web interface to read the cookie
+                .http_only(false)
+                .secure(secure)
+                .finish()
+        );
+
+        // The web interface sends the CSRF token back in a header when making
+        // changes, which other sites can not do
+        request.cookies().add(
+            Cookie::build(CSRF_COOKIE, csrf_token)
+                .path("/")
+                .http_only(false)
+                .secure(secure)
+                .finish()
+        );
+
+        Outcome::Success(user)
+    }
+
+    /// Try to get the user ID from cookies. An error is returned if none are
+    /// found, or if the session has expired or been revoked. Requests which
+    /// make changes must also include the session's CSRF token.
+    fn check_cookies(request: &Request) -> request::Outcome<Self, Error> {
+        let id = match session_id(request) {
+            Some(id) => id,
+            None => return Error::from(ErrorKind::Unauthorized).into_outcome()
+        };
+
+        let auth_data: State<AuthData> = match request.guard().succeeded() {
+            Some(auth_data) => auth_data,
+            None => return Error::from(ErrorKind::Unknown).into_outcome()
+        };
+
+        if !auth_data
+            .sessions
+            .touch(id, client_ip(request), user_agent(request))
+        {
+            return Error::from(ErrorKind::Unauthorized).into_outcome();
+        }
+
+        if is_mutating(request.method()) {
+            let csrf_token = request.headers().get_one(CSRF_HEADER).unwrap_or_default();
+
+            if !auth_data.sessions.csrf_token_matches(id, csrf_token) {
+                return Error::from(ErrorKind::Forbidden).into_outcome();
+            }
+        }
+
+        Outcome::Success(User { id })
+    }
+
+    /// Log the user out by ending the session and removing the cookies
+    pub fn logout(&self, mut cookies: Cookies, auth_data: &AuthData) {
+        auth_data.sessions.revoke(self.id);
+        cookies.remove_private(Cookie::named(USER_ATTR));
+        cookies.remove(Cookie::build(CSRF_COOKIE, "").path("/").finish());
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for User {
+    type Error = Error;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match request.headers().get_one(AUTH_HEADER) {
+            // Try to authenticate, and if that fails check cookies. Locked out
+            // clients can not fall back to cookies.
+            Some(key) => {
+                let auth_result = User::authenticate(request, key);
+
+                match auth_result {
+                    Outcome::Failure((status, _)) if status != Status::TooManyRequests => {
+                        User::check_cookies(request)
+                    }
+                    _ => auth_result
+                }
+            }
+            // No attempt to authenticate, so check cookies
+            None => User::check_cookies(request)
+        }
+    }
+}
+
+impl AuthData {
+    /// Create the authentication data from the password hash (as stored in
+    /// `WEBPASSWORD`) with an empty session store. If `legacy_hash_login` is
+    /// true, clients may also log in by sending the hash itself.
+    pub fn new(key: String, legacy_hash_login: bool, sessions: SessionStore) -> AuthData {
+        AuthData {
+            key: RwLock::new(key),
+            legacy_hash_login,
+            sessions,
+            throttle: LoginThrottle::default(),
+            totp: Mutex::new(())
+        }
+    }
+
+    /// Get the session store
+    pub fn sessions(&self) -> &SessionStore {
+        &self.sessions
+    }
+
+    /// Get the login throttle
+    pub fn throttle(&self) -> &LoginThrottle {
+        &self.throttle
+    }
+
+    /// Lock the TOTP configuration. The lock must be held while the
+    /// configuration is loaded, changed, and saved, so a code can not be
+    /// accepted twice by concurrent requests.
+    pub fn lock_totp(&self) -> MutexGuard<()> {
+        self.totp.lock().unwrap()
+    }
+
+    /// Check the password of the client. Failed attempts count towards the
+    /// client's lockout, and locked out clients are rejected even if the
+    /// password is correct.
+    pub fn check_password(&self, ip: &ClientIp, password: &str) -> Result<(), Error> {
+        let throttle_key = ip.throttle_key();
+
+        if let Some(retry_after) = self.throttle.retry_after(throttle_key) {
+            return Err(Error::from(ErrorKind::TooManyRequests(retry_after)));
+        }
+
+        if self.key_matches(password) {
+            Ok(())
+        } else {
+            self.throttle.failure(throttle_key);
+            Err(Error::from(ErrorKind::Unauthorized))
+        }
+    }
+
+    /// Check the password and second factor of a client which is logging in.
+    /// The client's failed attempts are cleared if both are correct.
+    fn check_login(
+        &self,
+        env: &Env,
+        ip: &ClientIp,
+        password: &str,
+        code: Option<&str>
+    ) -> Result<(), Error> {
+        self.check_password(ip, password)?;
+
+        if let Err(e) = check_second_factor(self, env, code) {
+            if e.kind() == ErrorKind::Unauthorized {
+                self.throttle.failure(ip.throttle_key());
+            }
+
+            return Err(e);
+        }
+
+        self.throttle.success(ip.throttle_key());
+        Ok(())
+    }
+
+    /// Replace the password hash. This takes effect immediately.
+    pub fn set_password_hash(&self, hash: String) {
+        *self.key.write().unwrap() = hash;
+    }
+
+    /// Check if the password matches the server's password hash. The
+    /// comparison is done in constant time. If no password is set (an empty
+    /// `WEBPASSWORD`), only an empty password matches.
+    fn key_matches(&self, password: &str) -> bool {
+        let key = self.key.read().unwrap();
+
+        if key.is_empty() {
+            return password.is_empty();
+        }
+
+        let hash = hash_password(password);
+
+        constant_time_eq(hash.as_bytes(), key.as_bytes())
+            || (self.legacy_hash_login && constant_time_eq(password.as_bytes(), key.as_bytes()))
+    }
+
+    /// Create a new user with a new session. The user and the session's CSRF
+    /// token are returned.
+    fn create_user(&self, request: &Request) -> Result<(User, String), Error> {
+        let csrf_token = encode_config(&random_bytes(32)?, URL_SAFE_NO_PAD);
+        let id = self
+            .sessions
+            .create(csrf_token.clone(), client_ip(request), user_agent(request));
+
+        Ok((User { id }, csrf_token))
+    }
+}
+
+/// Check if requests with this method can make changes, and so need CSRF
+/// protection
+pub fn is_mutating(method: Method) -> bool {
+    match method {
+        Method::Post | Method::Put | Method::Patch | Method::Delete => true,
+        _ => false
+    }
+}
+
+impl ClientIp {
+    /// Get the key used to track the client's failed login attempts
+    fn throttle_key(&self) -> &str {
+        self.0.as_ref().map(String::as_str).unwrap_or("unknown")
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientIp {
+    type Error = Error;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(ClientIp(client_ip(request)))
+    }
+}
+
+/// Get the IP address of the client, if known
+fn client_ip(request: &Request) -> Option<String> {
+    proxy::client_ip(request).map(|ip| ip.to_string())
+}
+
+/// Get the session ID from the client's encrypted cookie, if it has one
+fn session_id(request: &Request) -> Option<usize> {
+    request
+        .cookies()
+        .get_private(USER_ATTR)
+        .and_then(|cookie| cookie.value().parse().ok())
+}
+
+/// Get the user agent of the client, if given
+fn user_agent(request: &Request) -> Option<String> {
+    request.headers().get_one("User-Agent").map(str::to_owned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::AuthData;
+    use crate::routes::auth::{hash_password, SessionStore};
+
+    /// The plaintext password matches the stored hash
+    #[test]
+    fn password_matches() {
+        let auth_data = AuthData::new(hash_password("secret"), false, SessionStore::new(60, 600));
+
+        assert!(auth_data.key_matches("secret"));
+        assert!(!auth_data.key_matches("wrong"));
+    }
+
+    /// Changing the password hash takes effect immediately
+    #[test]
+    fn set_password_hash() {
+        let auth_data = AuthData::new(hash_password("secret"), false, SessionStore::new(60, 600));
+
+        auth_data.set_password_hash(hash_password("new_secret"));
+
+        assert!(!auth_data.key_matches("secret"));
+        assert!(auth_data.key_matches("new_secret"));
+    }
+
+    /// The hash itself is only accepted in legacy mode
+    #[test]
+    fn legacy_hash_login() {
+        let hash = hash_password("secret");
+        let auth_data = AuthData::new(hash.clone(), false, SessionStore::new(60, 600));
+        let legacy_auth_data = AuthData::new(hash.clone(), true, SessionStore::new(60, 600));
+
+        assert!(!auth_data.key_matches(&hash));
+        assert!(legacy_auth_data.key_matches(&hash));
+        assert!(legacy_auth_data.key_matches("secret"));
+    }
+
+    /// If no password is set, only an empty password is accepted, even in
+    /// legacy mode
+    #[test]
+    fn no_password() {
+        let auth_data = AuthData::new(String::new(), false, SessionStore::new(60, 600));
+        let legacy_auth_data = AuthData::new(String::new(), true, SessionStore::new(60, 600));
+
+        assert!(auth_data.key_matches(""));
+        assert!(!auth_data.key_matches("secret"));
+        assert!(!auth_data.key_matches(&hash_password("")));
+        assert!(legacy_auth_data.key_matches(""));
+        assert!(!legacy_auth_data.key_matches("secret"));
+    }
+}
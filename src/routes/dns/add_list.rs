@@ -12,10 +12,12 @@ use crate::{
     env::Env,
     ftl::FtlConnectionType,
     routes::{
-        auth::User,
+        audit::AuditEntry,
+        auth::{scopes::ListsWrite, ClientIp, Scoped},
         dns::{common::reload_gravity, list::List}
     },
-    util::{reply_success, Reply}
+    util::{reply_success, Error, Reply},
+    webhooks::{EventKind, WebhookEvent, Webhooks}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
@@ -28,48 +30,103 @@ pub struct DomainInput {
 
 /// Add a domain to the whitelist
 #[post("/dns/whitelist", data = "<domain_input>")]
-pub fn add_whitelist(_auth: User, env: State<Env>, domain_input: Json<DomainInput>) -> Reply {
+pub fn add_whitelist(
+    auth: Scoped<ListsWrite>,
+    ip: ClientIp,
+    env: State<Env>,
+    webhooks: State<Webhooks>,
+    domain_input: Json<DomainInput>
+) -> Reply {
     let domain = &domain_input.0.domain;
-
-    // We need to add it to the whitelist and remove it from the blacklist
-    List::White.add(domain, &env)?;
-    List::Black.try_remove(domain, &env)?;
-
-    // At this point, since we haven't hit an error yet, reload gravity
-    reload_gravity(List::White, &env)?;
+    let result = add_to_list(List::White, List::Black, domain, &env);
+
+    AuditEntry::new("dns.whitelist.add", &auth.principal, &ip)
+        .new_value(json!(domain))
+        .record(&env, &result);
+
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::ListEntryAdded,
+            json!({ "list": "whitelist", "domain": domain })
+        )
+    );
     reply_success()
 }
 
 /// Add a domain to the blacklist
 #[post("/dns/blacklist", data = "<domain_input>")]
-pub fn add_blacklist(_auth: User, env: State<Env>, domain_input: Json<DomainInput>) -> Reply {
+pub fn add_blacklist(
+    auth: Scoped<ListsWrite>,
+    ip: ClientIp,
+    env: State<Env>,
+    webhooks: State<Webhooks>,
+    domain_input: Json<DomainInput>
+) -> Reply {
     let domain = &domain_input.0.domain;
-
-    // We need to add it to the blacklist and remove it from the whitelist
-    List::Black.add(domain, &env)?;
-    List::White.try_remove(domain, &env)?;
-
-    // At this point, since we haven't hit an error yet, reload gravity
-    reload_gravity(List::Black, &env)?;
+    let result = add_to_list(List::Black, List::White, domain, &env);
+
+    AuditEntry::new("dns.blacklist.add", &auth.principal, &ip)
+        .new_value(json!(domain))
+        .record(&env, &result);
+
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::ListEntryAdded,
+            json!({ "list": "blacklist", "domain": domain })
+        )
+    );
     reply_success()
 }
 
 /// Add a domain to the regex list
 #[post("/dns/regexlist", data = "<domain_input>")]
 pub fn add_regexlist(
-    _auth: User,
+    auth: Scoped<ListsWrite>,
+    ip: ClientIp,
     env: State<Env>,
+    webhooks: State<Webhooks>,
     ftl: State<FtlConnectionType>,
     domain_input: Json<DomainInput>
 ) -> Reply {
     let domain = &domain_input.0.domain;
+    let result = add_to_regexlist(domain, &env, &ftl);
+
+    AuditEntry::new("dns.regexlist.add", &auth.principal, &ip)
+        .new_value(json!(domain))
+        .record(&env, &result);
+
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::ListEntryAdded,
+            json!({ "list": "regexlist", "domain": domain })
+        )
+    );
+    reply_success()
+}
+
+/// Add a domain to `list` and remove it from `opposite_list` (ex. add to the
+/// whitelist and remove from the blacklist), then reload gravity
+fn add_to_list(list: List, opposite_list: List, domain: &str, env: &Env) -> Result<(), Error> {
+    list.add(domain, env)?;
+    opposite_list.try_remove(domain, env)?;
 
+    // At this point, since we haven't hit an error yet, reload gravity
+    reload_gravity(list, env)
+}
+
+/// Add a domain to the regex list and tell FTL to recompile regex
+fn add_to_regexlist(domain: &str, env: &Env, ftl: &FtlConnectionType) -> Result<(), Error> {
     // We only need to add it to the regex list
-    List::Regex.add(domain, &env)?;
+    List::Regex.add(domain, env)?;
 
     // At this point, since we haven't hit an error yet, tell FTL to recompile regex
-    ftl.connect("recompile-regex")?.expect_eom()?;
-    reply_success()
+    ftl.connect("recompile-regex")?.expect_eom()
 }
 
 #[cfg(test)]
@@ -12,42 +12,106 @@ use crate::{
     env::Env,
     ftl::FtlConnectionType,
     routes::{
-        auth::User,
+        audit::AuditEntry,
+        auth::{scopes::ListsWrite, ClientIp, Scoped},
         dns::{common::reload_gravity, list::List}
     },
-    util::{reply_success, Reply}
+    util::{reply_success, Error, Reply},
+    webhooks::{EventKind, WebhookEvent, Webhooks}
 };
 use rocket::State;
 
 /// Delete a domain from the whitelist
 #[delete("/dns/whitelist/<domain>")]
-pub fn delete_whitelist(_auth: User, env: State<Env>, domain: String) -> Reply {
-    List::White.remove(&domain, &env)?;
-    reload_gravity(List::White, &env)?;
+pub fn delete_whitelist(
+    auth: Scoped<ListsWrite>,
+    ip: ClientIp,
+    env: State<Env>,
+    webhooks: State<Webhooks>,
+    domain: String
+) -> Reply {
+    let result = delete_from_list(List::White, &domain, &env);
+
+    AuditEntry::new("dns.whitelist.delete", &auth.principal, &ip)
+        .old_value(json!(domain))
+        .record(&env, &result);
+
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::ListEntryRemoved,
+            json!({ "list": "whitelist", "domain": domain })
+        )
+    );
     reply_success()
 }
 
 /// Delete a domain from the blacklist
 #[delete("/dns/blacklist/<domain>")]
-pub fn delete_blacklist(_auth: User, env: State<Env>, domain: String) -> Reply {
-    List::Black.remove(&domain, &env)?;
-    reload_gravity(List::Black, &env)?;
+pub fn delete_blacklist(
+    auth: Scoped<ListsWrite>,
+    ip: ClientIp,
+    env: State<Env>,
+    webhooks: State<Webhooks>,
+    domain: String
+) -> Reply {
+    let result = delete_from_list(List::Black, &domain, &env);
+
+    AuditEntry::new("dns.blacklist.delete", &auth.principal, &ip)
+        .old_value(json!(domain))
+        .record(&env, &result);
+
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::ListEntryRemoved,
+            json!({ "list": "blacklist", "domain": domain })
+        )
+    );
     reply_success()
 }
 
 /// Delete a domain from the regex list
 #[delete("/dns/regexlist/<domain>")]
 pub fn delete_regexlist(
-    _auth: User,
+    auth: Scoped<ListsWrite>,
+    ip: ClientIp,
     env: State<Env>,
+    webhooks: State<Webhooks>,
     ftl: State<FtlConnectionType>,
     domain: String
 ) -> Reply {
-    List::Regex.remove(&domain, &env)?;
-    ftl.connect("recompile-regex")?.expect_eom()?;
+    let result = delete_from_regexlist(&domain, &env, &ftl);
+
+    AuditEntry::new("dns.regexlist.delete", &auth.principal, &ip)
+        .old_value(json!(domain))
+        .record(&env, &result);
+
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::ListEntryRemoved,
+            json!({ "list": "regexlist", "domain": domain })
+        )
+    );
     reply_success()
 }
 
+/// Remove a domain from the list and reload gravity
+fn delete_from_list(list: List, domain: &str, env: &Env) -> Result<(), Error> {
+    list.remove(domain, env)?;
+    reload_gravity(list, env)
+}
+
+/// Remove a domain from the regex list and tell FTL to recompile regex
+fn delete_from_regexlist(domain: &str, env: &Env, ftl: &FtlConnectionType) -> Result<(), Error> {
+    List::Regex.remove(domain, env)?;
+    ftl.connect("recompile-regex")?.expect_eom()
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
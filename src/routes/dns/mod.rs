@@ -15,4 +15,4 @@ mod get_list;
 mod list;
 mod status;
 
-pub use self::{add_list::*, delete_list::*, get_list::*, status::*};
+pub use self::{add_list::*, common::reload_dns, delete_list::*, get_list::*, status::*};
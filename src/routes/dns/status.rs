@@ -10,9 +10,14 @@
 
 use crate::{
     env::{Env, PiholeFile},
-    routes::dns::common::reload_dns,
+    routes::{
+        audit::AuditEntry,
+        auth::{scopes::DnsStatus, ClientIp, Scoped},
+        dns::common::reload_dns
+    },
     settings::{ConfigEntry, SetupVarsEntry},
-    util::{reply_data, reply_error, reply_success, Error, ErrorKind, Reply}
+    util::{reply_data, reply_success, Error, ErrorKind, Reply},
+    webhooks::{EventKind, WebhookEvent, Webhooks}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
@@ -22,28 +27,43 @@ use task_scheduler::Scheduler;
 /// Get the DNS blocking status
 #[get("/dns/status")]
 pub fn status(env: State<Env>) -> Reply {
-    let status = if SetupVarsEntry::BlockingEnabled.is_true(&env)? {
-        "enabled"
-    } else {
-        "disabled"
-    };
+    reply_data(json!({ "status": blocking_status(&env)? }))
+}
 
-    reply_data(json!({ "status": status }))
+/// Get the blocking status as either "enabled" or "disabled"
+pub fn blocking_status(env: &Env) -> Result<&'static str, Error> {
+    if SetupVarsEntry::BlockingEnabled.is_true(env)? {
+        Ok("enabled")
+    } else {
+        Ok("disabled")
+    }
 }
 
 /// Enable/Disable blocking
 #[post("/dns/status", data = "<data>")]
 pub fn change_status(
+    auth: Scoped<DnsStatus>,
+    ip: ClientIp,
     env: State<Env>,
     scheduler: State<Scheduler>,
+    webhooks: State<Webhooks>,
     data: Json<ChangeStatus>
 ) -> Reply {
-    match (data.action.as_str(), data.time) {
-        ("enable", None) => enable(&env)?,
-        ("disable", time) => disable(&env, time, Some(&scheduler))?,
-        _ => return reply_error(ErrorKind::BadRequest)
-    }
+    let old_status = blocking_status(&env).ok();
+    let result = match (data.action.as_str(), data.time) {
+        ("enable", None) => enable(&env)
+            .map(|_| WebhookEvent::new(EventKind::BlockingEnabled, json!({ "scheduled": false }))),
+        ("disable", time) => disable(&env, time, Some(&scheduler), &webhooks)
+            .map(|_| WebhookEvent::new(EventKind::BlockingDisabled, json!({ "time": time }))),
+        _ => Err(Error::from(ErrorKind::BadRequest))
+    };
+
+    AuditEntry::new("dns.status", &auth.principal, &ip)
+        .old_value(json!(old_status))
+        .new_value(json!({ "action": data.action, "time": data.time }))
+        .record(&env, &result);
 
+    webhooks.fire(&env, result?);
     reply_success()
 }
 
@@ -70,8 +90,14 @@ fn enable(env: &Env) -> Result<(), Error> {
 }
 
 /// Disable blocking. If the time is `None`, then disable permanently.
-/// Otherwise, re-enable after the specified number of seconds.
-fn disable(env: &Env, time: Option<usize>, scheduler: Option<&Scheduler>) -> Result<(), Error> {
+/// Otherwise, re-enable after the specified number of seconds, and let the
+/// webhooks know when blocking is re-enabled.
+fn disable(
+    env: &Env,
+    time: Option<usize>,
+    scheduler: Option<&Scheduler>,
+    webhooks: &Webhooks
+) -> Result<(), Error> {
     // Can't disable blocking when it's already disabled
     if !SetupVarsEntry::BlockingEnabled.is_true(&env)? {
         return Err(Error::from(ErrorKind::BadRequest));
@@ -111,6 +137,7 @@ fn disable(env: &Env, time: Option<usize>, scheduler: Option<&Scheduler>) -> Res
         if let Some(time) = time {
             // Make a copy of the Env to move to the scheduler thread
             let env_copy = env.clone();
+            let webhooks = webhooks.clone();
 
             // Re-enable blocking after the timeout
             scheduler
@@ -118,15 +145,19 @@ fn disable(env: &Env, time: Option<usize>, scheduler: Option<&Scheduler>) -> Res
                 .after_duration(Duration::from_secs(time as u64), move || {
                     // Handle the result of enabling, so that if it's an error
                     // the thread does not panic
-                    if let Err(e) = enable(&env_copy) {
-                        if e.kind() == ErrorKind::BadRequest {
-                            // If it was a bad request, blocking was probably
-                            // already re-enabled. This is a fairly common
-                            // scenario, so no error should be logged.
-                            return;
-                        }
-
-                        e.print_stacktrace();
+                    match enable(&env_copy) {
+                        Ok(()) => webhooks.fire(
+                            &env_copy,
+                            WebhookEvent::new(
+                                EventKind::BlockingEnabled,
+                                json!({ "scheduled": true })
+                            )
+                        ),
+                        // If it was a bad request, blocking was probably
+                        // already re-enabled. This is a fairly common
+                        // scenario, so no error should be logged.
+                        Err(ref e) if e.kind() == ErrorKind::BadRequest => (),
+                        Err(e) => e.print_stacktrace()
                     }
                 });
         }
@@ -152,7 +183,8 @@ mod test {
     use crate::{
         env::{Config, Env, PiholeFile},
         testing::{TestBuilder, TestEnvBuilder},
-        util::ErrorKind
+        util::ErrorKind,
+        webhooks::Webhooks
     };
     use rocket::http::Method;
 
@@ -253,7 +285,7 @@ mod test {
         );
 
         assert_eq!(
-            disable(&env, None, None).map_err(|e| e.kind()),
+            disable(&env, None, None, &Webhooks::default()).map_err(|e| e.kind()),
             Err(ErrorKind::BadRequest)
         );
     }
@@ -0,0 +1,58 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Live Update Ticket Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    live::{TicketStore, TICKET_LIFETIME},
+    routes::auth::{scopes::StatsRead, Scoped},
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use rocket::State;
+
+/// Get a ticket for connecting to the live update WebSocket server, at
+/// `ws://<address>:<port>/?ticket=<ticket>`. The ticket can be used once,
+/// within `expires_in` seconds. After connecting, clients send
+/// `{"action": "subscribe", "topics": [...]}` to get updates.
+#[post("/live/ticket")]
+pub fn get_ticket(_auth: Scoped<StatsRead>, env: State<Env>, tickets: State<TicketStore>) -> Reply {
+    if !env.config().live_enabled() {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    reply_data(json!({
+        "ticket": tickets.issue()?,
+        "port": env.config().live_port(),
+        "expires_in": TICKET_LIFETIME.as_secs()
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+    use rocket::http::{Method, Status};
+    use serde_json::Value;
+
+    /// Tickets are not issued when live updates are disabled
+    #[test]
+    fn disabled() {
+        TestBuilder::new()
+            .endpoint("/admin/api/live/ticket")
+            .method(Method::Post)
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+}
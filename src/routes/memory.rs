@@ -0,0 +1,125 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Shared Memory Usage Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::{FtlMemory, LockTiming},
+    routes::auth::{scopes::StatsRead, Scoped},
+    util::{reply_data, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::JsonValue;
+use std::time::Duration;
+
+/// Get how much of each shared memory segment is used compared to the space
+/// FTL has allocated for it, along with the layout version, how long the
+/// shared memory lock was waited for and held, and how many times invalid
+/// records were skipped. The lock statistics and invalid record counts are
+/// `null` when shared memory is not read from FTL.
+#[get("/ftl/memory")]
+pub fn get_memory(_auth: Scoped<StatsRead>, ftl_memory: State<FtlMemory>) -> Reply {
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let settings = ftl_memory.settings(&lock)?;
+
+    reply_data(json!({
+        "version": settings.version,
+        "global_shm_counter": settings.global_shm_counter,
+        "segments": {
+            "queries": segment(counters.total_queries, counters.query_capacity),
+            "clients": segment(counters.total_clients, counters.client_capacity),
+            "domains": segment(counters.total_domains, counters.domain_capacity),
+            "upstreams": segment(counters.total_upstreams, counters.upstream_capacity),
+            "strings": segment(settings.next_str_pos as i32, counters.string_capacity)
+        },
+        "lock": ftl_memory.lock_stats().map(|stats| json!({
+            "wait": timing(&stats.wait),
+            "hold": timing(&stats.hold)
+        })),
+        "invalid_records": ftl_memory.invalid_records()
+    }))
+}
+
+/// Describe the usage of a shared memory segment
+fn segment(used: i32, capacity: i32) -> JsonValue {
+    json!({
+        "used": used,
+        "capacity": capacity
+    })
+}
+
+/// Describe the lock timing statistics, in milliseconds
+fn timing(timing: &LockTiming) -> JsonValue {
+    json!({
+        "count": timing.count,
+        "last_ms": millis(timing.last),
+        "average_ms": millis(timing.average()),
+        "max_ms": millis(timing.max)
+    })
+}
+
+/// Convert the duration to fractional milliseconds
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ftl::{FtlCounters, FtlMemory, FtlSettings},
+        testing::TestBuilder
+    };
+    use std::collections::HashMap;
+
+    /// The usage and capacity of each segment is reported
+    #[test]
+    fn usage() {
+        TestBuilder::new()
+            .endpoint("/admin/api/ftl/memory")
+            .ftl_memory(FtlMemory::Test {
+                clients: Vec::new(),
+                domains: Vec::new(),
+                over_time: Vec::new(),
+                upstreams: Vec::new(),
+                queries: Vec::new(),
+                strings: HashMap::new(),
+                counters: FtlCounters {
+                    total_queries: 9,
+                    total_clients: 4,
+                    total_domains: 6,
+                    total_upstreams: 2,
+                    query_capacity: 10_000,
+                    client_capacity: 10,
+                    domain_capacity: 1000,
+                    upstream_capacity: 4,
+                    string_capacity: 4096,
+                    ..FtlCounters::default()
+                },
+                settings: FtlSettings {
+                    version: 4,
+                    global_shm_counter: 12,
+                    next_str_pos: 120
+                }
+            })
+            .expect_json(json!({
+                "version": 4,
+                "global_shm_counter": 12,
+                "segments": {
+                    "queries": { "used": 9, "capacity": 10_000 },
+                    "clients": { "used": 4, "capacity": 10 },
+                    "domains": { "used": 6, "capacity": 1000 },
+                    "upstreams": { "used": 2, "capacity": 4 },
+                    "strings": { "used": 120, "capacity": 4096 }
+                },
+                "lock": null,
+                "invalid_records": null
+            }))
+            .test();
+    }
+}
@@ -8,9 +8,15 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+pub mod actions;
+pub mod audit;
 pub mod auth;
 pub mod dns;
+pub mod live;
+pub mod memory;
 pub mod settings;
+pub mod snapshot;
 pub mod stats;
 pub mod version;
 pub mod web;
+pub mod webhooks;
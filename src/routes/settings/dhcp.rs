@@ -10,9 +10,17 @@
 
 use crate::{
     env::Env,
-    routes::{auth::User, settings::common::restart_dns},
+    routes::{
+        audit::AuditEntry,
+        auth::{
+            scopes::{SettingsRead, SettingsWrite},
+            ClientIp, Scoped
+        },
+        settings::common::restart_dns
+    },
     settings::{generate_dnsmasq_config, ConfigEntry, SetupVarsEntry},
-    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+    util::{reply_data, reply_success, Error, ErrorKind, Reply},
+    webhooks::{EventKind, WebhookEvent, Webhooks}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
@@ -46,43 +54,71 @@ impl DhcpSettings {
             && SetupVarsEntry::DhcpRouter.is_valid(&self.router_ip)
             && SetupVarsEntry::PiholeDomain.is_valid(&self.domain)
     }
+
+    /// Read the current DHCP settings
+    fn read(env: &Env) -> Result<DhcpSettings, Error> {
+        Ok(DhcpSettings {
+            active: SetupVarsEntry::DhcpActive.is_true(env)?,
+            ip_start: SetupVarsEntry::DhcpStart.read(env)?,
+            ip_end: SetupVarsEntry::DhcpEnd.read(env)?,
+            router_ip: SetupVarsEntry::DhcpRouter.read(env)?,
+            lease_time: SetupVarsEntry::DhcpLeasetime.read_as(env)?,
+            domain: SetupVarsEntry::PiholeDomain.read(env)?,
+            ipv6_support: SetupVarsEntry::DhcpIpv6.is_true(env)?
+        })
+    }
+
+    /// Validate and save the settings, then restart DNS to apply them
+    fn write(&self, env: &Env) -> Result<(), Error> {
+        if !self.is_valid() {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
+        }
+
+        SetupVarsEntry::DhcpActive.write(&self.active.to_string(), env)?;
+        SetupVarsEntry::DhcpStart.write(&self.ip_start, env)?;
+        SetupVarsEntry::DhcpEnd.write(&self.ip_end, env)?;
+        SetupVarsEntry::DhcpRouter.write(&self.router_ip, env)?;
+        SetupVarsEntry::DhcpLeasetime.write(&self.lease_time.to_string(), env)?;
+        SetupVarsEntry::PiholeDomain.write(&self.domain, env)?;
+        SetupVarsEntry::DhcpIpv6.write(&self.ipv6_support.to_string(), env)?;
+
+        generate_dnsmasq_config(env)?;
+        restart_dns(env)
+    }
 }
 
 /// Get DHCP Configuration
 #[get("/settings/dhcp")]
-pub fn get_dhcp(env: State<Env>, _auth: User) -> Reply {
-    let dhcp_settings = DhcpSettings {
-        active: SetupVarsEntry::DhcpActive.is_true(&env)?,
-        ip_start: SetupVarsEntry::DhcpStart.read(&env)?,
-        ip_end: SetupVarsEntry::DhcpEnd.read(&env)?,
-        router_ip: SetupVarsEntry::DhcpRouter.read(&env)?,
-        lease_time: SetupVarsEntry::DhcpLeasetime.read_as(&env)?,
-        domain: SetupVarsEntry::PiholeDomain.read(&env)?,
-        ipv6_support: SetupVarsEntry::DhcpIpv6.is_true(&env)?
-    };
-
-    reply_data(dhcp_settings)
+pub fn get_dhcp(env: State<Env>, _auth: Scoped<SettingsRead>) -> Reply {
+    reply_data(DhcpSettings::read(&env)?)
 }
 
 /// Update DHCP Configuration
 #[put("/settings/dhcp", data = "<data>")]
-pub fn put_dhcp(env: State<Env>, _auth: User, data: Json<DhcpSettings>) -> Reply {
+pub fn put_dhcp(
+    env: State<Env>,
+    auth: Scoped<SettingsWrite>,
+    ip: ClientIp,
+    webhooks: State<Webhooks>,
+    data: Json<DhcpSettings>
+) -> Reply {
     let settings: DhcpSettings = data.into_inner();
+    let old_settings = DhcpSettings::read(&env).ok();
+    let result = settings.write(&env);
 
-    if !settings.is_valid() {
-        return Err(Error::from(ErrorKind::InvalidSettingValue));
-    }
-
-    SetupVarsEntry::DhcpActive.write(&settings.active.to_string(), &env)?;
-    SetupVarsEntry::DhcpStart.write(&settings.ip_start, &env)?;
-    SetupVarsEntry::DhcpEnd.write(&settings.ip_end, &env)?;
-    SetupVarsEntry::DhcpRouter.write(&settings.router_ip, &env)?;
-    SetupVarsEntry::DhcpLeasetime.write(&settings.lease_time.to_string(), &env)?;
-    SetupVarsEntry::PiholeDomain.write(&settings.domain, &env)?;
-    SetupVarsEntry::DhcpIpv6.write(&settings.ipv6_support.to_string(), &env)?;
+    AuditEntry::new("settings.dhcp", &auth.principal, &ip)
+        .old_value(json!(old_settings))
+        .new_value(json!(settings))
+        .record(&env, &result);
 
-    generate_dnsmasq_config(&env)?;
-    restart_dns(&env)?;
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::SettingsChanged,
+            json!({ "section": "dhcp", "settings": settings })
+        )
+    );
     reply_success()
 }
 
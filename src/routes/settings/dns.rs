@@ -10,9 +10,17 @@
 
 use crate::{
     env::Env,
-    routes::{auth::User, settings::common::restart_dns},
+    routes::{
+        audit::AuditEntry,
+        auth::{
+            scopes::{SettingsRead, SettingsWrite},
+            ClientIp, Scoped
+        },
+        settings::common::restart_dns
+    },
     settings::{generate_dnsmasq_config, ConfigEntry, SetupVarsEntry},
-    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+    util::{reply_data, reply_success, Error, ErrorKind, Reply},
+    webhooks::{EventKind, WebhookEvent, Webhooks}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
@@ -75,11 +83,11 @@ impl DnsConditionalForwarding {
 }
 
 /// Get upstream DNS servers
-fn get_upstream_dns(env: &State<Env>) -> Result<Vec<String>, Error> {
+fn get_upstream_dns(env: &Env) -> Result<Vec<String>, Error> {
     let mut upstream_dns = Vec::new();
 
     for num in 1.. {
-        let ip = SetupVarsEntry::PiholeDns(num).read(&env)?;
+        let ip = SetupVarsEntry::PiholeDns(num).read(env)?;
 
         if !ip.is_empty() {
             upstream_dns.push(ip);
@@ -91,77 +99,107 @@ fn get_upstream_dns(env: &State<Env>) -> Result<Vec<String>, Error> {
     Ok(upstream_dns)
 }
 
-/// Get DNS Configuration
-#[get("/settings/dns")]
-pub fn get_dns(env: State<Env>, _auth: User) -> Reply {
-    let dns_settings = DnsSettings {
-        upstream_dns: get_upstream_dns(&env)?,
-        options: DnsOptions {
-            fqdn_required: SetupVarsEntry::DnsFqdnRequired.is_true(&env)?,
-            bogus_priv: SetupVarsEntry::DnsBogusPriv.is_true(&env)?,
-            dnssec: SetupVarsEntry::Dnssec.is_true(&env)?,
-            listening_type: SetupVarsEntry::DnsmasqListening.read(&env)?
-        },
-        conditional_forwarding: DnsConditionalForwarding {
-            enabled: SetupVarsEntry::ConditionalForwarding.is_true(&env)?,
-            router_ip: SetupVarsEntry::ConditionalForwardingIp.read(&env)?,
-            domain: SetupVarsEntry::ConditionalForwardingDomain.read(&env)?
+impl DnsSettings {
+    /// Read the current DNS settings
+    fn read(env: &Env) -> Result<DnsSettings, Error> {
+        Ok(DnsSettings {
+            upstream_dns: get_upstream_dns(env)?,
+            options: DnsOptions {
+                fqdn_required: SetupVarsEntry::DnsFqdnRequired.is_true(env)?,
+                bogus_priv: SetupVarsEntry::DnsBogusPriv.is_true(env)?,
+                dnssec: SetupVarsEntry::Dnssec.is_true(env)?,
+                listening_type: SetupVarsEntry::DnsmasqListening.read(env)?
+            },
+            conditional_forwarding: DnsConditionalForwarding {
+                enabled: SetupVarsEntry::ConditionalForwarding.is_true(env)?,
+                router_ip: SetupVarsEntry::ConditionalForwardingIp.read(env)?,
+                domain: SetupVarsEntry::ConditionalForwardingDomain.read(env)?
+            }
+        })
+    }
+
+    /// Validate and save the settings, then restart DNS to apply them
+    fn write(&self, env: &Env) -> Result<(), Error> {
+        if !self.is_valid() {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
         }
-    };
 
-    reply_data(dns_settings)
-}
+        // Delete previous upstream DNS entries
+        SetupVarsEntry::delete_upstream_dns(env)?;
 
-/// Update DNS Configuration
-#[put("/settings/dns", data = "<data>")]
-pub fn put_dns(env: State<Env>, _auth: User, data: Json<DnsSettings>) -> Reply {
-    let settings: DnsSettings = data.into_inner();
+        // Add new upstream DNS
+        for (i, dns) in self.upstream_dns.iter().enumerate() {
+            SetupVarsEntry::PiholeDns(i + 1).write(dns, env)?;
+        }
 
-    if !settings.is_valid() {
-        return Err(Error::from(ErrorKind::InvalidSettingValue));
-    }
+        // Write DNS settings to SetupVars
+        SetupVarsEntry::DnsFqdnRequired.write(&self.options.fqdn_required.to_string(), env)?;
+        SetupVarsEntry::DnsBogusPriv.write(&self.options.bogus_priv.to_string(), env)?;
+        SetupVarsEntry::Dnssec.write(&self.options.dnssec.to_string(), env)?;
+        SetupVarsEntry::DnsmasqListening.write(&self.options.listening_type, env)?;
+
+        if self.conditional_forwarding.enabled {
+            let address_segments: Vec<&str> = self
+                .conditional_forwarding
+                .router_ip
+                .split('.')
+                .take(3)
+                .collect();
+            let reverse_address = format!(
+                "{}.{}.{}.in-addr.arpa",
+                address_segments[2], address_segments[1], address_segments[0]
+            );
 
-    // Delete previous upstream DNS entries
-    SetupVarsEntry::delete_upstream_dns(&env)?;
+            SetupVarsEntry::ConditionalForwarding.write("true", env)?;
+            SetupVarsEntry::ConditionalForwardingReverse.write(&reverse_address, env)?;
+            SetupVarsEntry::ConditionalForwardingIp
+                .write(&self.conditional_forwarding.router_ip, env)?;
+            SetupVarsEntry::ConditionalForwardingDomain
+                .write(&self.conditional_forwarding.domain, env)?;
+        } else {
+            SetupVarsEntry::ConditionalForwarding.write("false", env)?;
+            SetupVarsEntry::ConditionalForwardingReverse.delete(env)?;
+            SetupVarsEntry::ConditionalForwardingIp.delete(env)?;
+            SetupVarsEntry::ConditionalForwardingDomain.delete(env)?;
+        }
 
-    // Add new upstream DNS
-    for (i, dns) in settings.upstream_dns.into_iter().enumerate() {
-        SetupVarsEntry::PiholeDns(i + 1).write(&dns, &env)?;
+        generate_dnsmasq_config(env)?;
+        restart_dns(env)
     }
+}
 
-    // Write DNS settings to SetupVars
-    SetupVarsEntry::DnsFqdnRequired.write(&settings.options.fqdn_required.to_string(), &env)?;
-    SetupVarsEntry::DnsBogusPriv.write(&settings.options.bogus_priv.to_string(), &env)?;
-    SetupVarsEntry::Dnssec.write(&settings.options.dnssec.to_string(), &env)?;
-    SetupVarsEntry::DnsmasqListening.write(&settings.options.listening_type, &env)?;
+/// Get DNS Configuration
+#[get("/settings/dns")]
+pub fn get_dns(env: State<Env>, _auth: Scoped<SettingsRead>) -> Reply {
+    reply_data(DnsSettings::read(&env)?)
+}
 
-    if settings.conditional_forwarding.enabled {
-        let address_segments: Vec<&str> = settings
-            .conditional_forwarding
-            .router_ip
-            .split('.')
-            .take(3)
-            .collect();
-        let reverse_address = format!(
-            "{}.{}.{}.in-addr.arpa",
-            address_segments[2], address_segments[1], address_segments[0]
-        );
+/// Update DNS Configuration
+#[put("/settings/dns", data = "<data>")]
+pub fn put_dns(
+    env: State<Env>,
+    auth: Scoped<SettingsWrite>,
+    ip: ClientIp,
+    webhooks: State<Webhooks>,
+    data: Json<DnsSettings>
+) -> Reply {
+    let settings: DnsSettings = data.into_inner();
+    let old_settings = DnsSettings::read(&env).ok();
+    let result = settings.write(&env);
 
-        SetupVarsEntry::ConditionalForwarding.write("true", &env)?;
-        SetupVarsEntry::ConditionalForwardingReverse.write(&reverse_address, &env)?;
-        SetupVarsEntry::ConditionalForwardingIp
-            .write(&settings.conditional_forwarding.router_ip, &env)?;
-        SetupVarsEntry::ConditionalForwardingDomain
-            .write(&settings.conditional_forwarding.domain, &env)?;
-    } else {
-        SetupVarsEntry::ConditionalForwarding.write("false", &env)?;
-        SetupVarsEntry::ConditionalForwardingReverse.delete(&env)?;
-        SetupVarsEntry::ConditionalForwardingIp.delete(&env)?;
-        SetupVarsEntry::ConditionalForwardingDomain.delete(&env)?;
-    }
+    AuditEntry::new("settings.dns", &auth.principal, &ip)
+        .old_value(json!(old_settings))
+        .new_value(json!(settings))
+        .record(&env, &result);
 
-    generate_dnsmasq_config(&env)?;
-    restart_dns(&env)?;
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::SettingsChanged,
+            json!({ "section": "dns", "settings": settings })
+        )
+    );
     reply_success()
 }
 
@@ -10,7 +10,7 @@
 
 use crate::{
     env::Env,
-    routes::auth::User,
+    routes::auth::{scopes::SettingsRead, Scoped},
     settings::{ConfigEntry, FtlConfEntry},
     util::{reply_data, Reply}
 };
@@ -18,7 +18,7 @@ use rocket::State;
 
 /// Read FTL's settings
 #[get("/settings/ftl")]
-pub fn get_ftl(env: State<Env>, _auth: User) -> Reply {
+pub fn get_ftl(env: State<Env>, _auth: Scoped<SettingsRead>) -> Reply {
     // if setting is not present, report default
     let socket_listening = FtlConfEntry::SocketListening.read(&env)?;
     let query_display = FtlConfEntry::QueryDisplay.read(&env)?;
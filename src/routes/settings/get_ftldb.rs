@@ -10,28 +10,27 @@
 
 use crate::{
     ftl::FtlConnectionType,
-    routes::auth::User,
+    routes::auth::{scopes::SettingsRead, Scoped},
     util::{reply_data, Reply}
 };
 use rocket::State;
 
+ftl_response! {
+    /// FTL's database stats, as sent by the `dbstats` command
+    #[derive(Serialize)]
+    struct DatabaseStats {
+        queries: i32,
+        filesize: i64,
+        sqlite_version: String
+    }
+}
+
 /// Read db stats from FTL
 #[get("/settings/ftldb")]
-pub fn get_ftldb(ftl: State<FtlConnectionType>, _auth: User) -> Reply {
-    let mut con = ftl.connect("dbstats")?;
-
-    // Read in FTL's database stats
-    let db_queries = con.read_i32()?;
-    let db_filesize = con.read_i64()?;
-    let mut version_buffer = [0u8; 64];
-    let db_sqlite_version = con.read_str(&mut version_buffer)?;
-    con.expect_eom()?;
+pub fn get_ftldb(ftl: State<FtlConnectionType>, _auth: Scoped<SettingsRead>) -> Reply {
+    let stats: DatabaseStats = ftl.connect("dbstats")?.read_response()?;
 
-    reply_data(json!({
-        "queries": db_queries,
-        "filesize": db_filesize,
-        "sqlite_version": db_sqlite_version
-    }))
+    reply_data(stats)
 }
 
 #[cfg(test)]
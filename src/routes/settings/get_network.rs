@@ -10,7 +10,7 @@
 
 use crate::{
     env::Env,
-    routes::auth::User,
+    routes::auth::{scopes::SettingsRead, Scoped},
     settings::{ConfigEntry, SetupVarsEntry},
     util::{reply_data, Reply}
 };
@@ -19,7 +19,7 @@ use rocket::State;
 
 /// Get Pi-hole local network information
 #[get("/settings/network")]
-pub fn get_network(env: State<Env>, _auth: User) -> Reply {
+pub fn get_network(env: State<Env>, _auth: Scoped<SettingsRead>) -> Reply {
     let ipv4_full = SetupVarsEntry::Ipv4Address.read(&env)?;
     let ipv4_address: Vec<&str> = ipv4_full.split('/').collect();
     let ipv6_full = SetupVarsEntry::Ipv6Address.read(&env)?;
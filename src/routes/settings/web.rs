@@ -10,9 +10,13 @@
 
 use crate::{
     env::Env,
-    routes::auth::User,
+    routes::{
+        audit::AuditEntry,
+        auth::{scopes::SettingsWrite, ClientIp, Scoped}
+    },
     settings::{ConfigEntry, SetupVarsEntry},
-    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+    util::{reply_data, reply_success, Error, ErrorKind, Reply},
+    webhooks::{EventKind, WebhookEvent, Webhooks}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
@@ -20,26 +24,35 @@ use rocket_contrib::json::Json;
 /// Get web interface settings
 #[get("/settings/web")]
 pub fn get_web(env: State<Env>) -> Reply {
-    let settings = WebSettings {
-        layout: SetupVarsEntry::WebLayout.read(&env)?,
-        language: SetupVarsEntry::WebLanguage.read(&env)?
-    };
-
-    reply_data(settings)
+    reply_data(WebSettings::read(&env)?)
 }
 
 /// Update web interface settings
 #[put("/settings/web", data = "<settings>")]
-pub fn put_web(_auth: User, env: State<Env>, settings: Json<WebSettings>) -> Reply {
+pub fn put_web(
+    auth: Scoped<SettingsWrite>,
+    ip: ClientIp,
+    env: State<Env>,
+    webhooks: State<Webhooks>,
+    settings: Json<WebSettings>
+) -> Reply {
     let settings = settings.into_inner();
+    let old_settings = WebSettings::read(&env).ok();
+    let result = settings.write(&env);
 
-    if !settings.is_valid() {
-        return Err(Error::from(ErrorKind::InvalidSettingValue));
-    }
-
-    SetupVarsEntry::WebLayout.write(&settings.layout, &env)?;
-    SetupVarsEntry::WebLanguage.write(&settings.language, &env)?;
+    AuditEntry::new("settings.web", &auth.principal, &ip)
+        .old_value(json!(old_settings))
+        .new_value(json!(settings))
+        .record(&env, &result);
 
+    result?;
+    webhooks.fire(
+        &env,
+        WebhookEvent::new(
+            EventKind::SettingsChanged,
+            json!({ "section": "web", "settings": settings })
+        )
+    );
     reply_success()
 }
 
@@ -55,4 +68,22 @@ impl WebSettings {
         SetupVarsEntry::WebLayout.is_valid(&self.layout)
             && SetupVarsEntry::WebLanguage.is_valid(&self.language)
     }
+
+    /// Read the current web interface settings
+    fn read(env: &Env) -> Result<WebSettings, Error> {
+        Ok(WebSettings {
+            layout: SetupVarsEntry::WebLayout.read(env)?,
+            language: SetupVarsEntry::WebLanguage.read(env)?
+        })
+    }
+
+    /// Validate and save the settings
+    fn write(&self, env: &Env) -> Result<(), Error> {
+        if !self.is_valid() {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
+        }
+
+        SetupVarsEntry::WebLayout.write(&self.layout, env)?;
+        SetupVarsEntry::WebLanguage.write(&self.language, env)
+    }
 }
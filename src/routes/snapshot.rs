@@ -0,0 +1,86 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Shared Memory Snapshot Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::{FtlMemory, FtlSnapshot},
+    routes::auth::User,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+
+/// Get a snapshot of FTL's shared memory. The response can be saved and used
+/// as the `ftl.snapshot` file in the API config to load the data without FTL.
+#[get("/ftl/snapshot")]
+pub fn get_snapshot(_user: User, ftl_memory: State<FtlMemory>) -> Reply {
+    reply_data(FtlSnapshot::capture(&ftl_memory)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ftl::{FtlCounters, FtlMemory, FtlSettings},
+        testing::TestBuilder
+    };
+    use std::collections::HashMap;
+
+    /// The snapshot contains the data in shared memory
+    #[test]
+    fn empty_memory() {
+        TestBuilder::new()
+            .endpoint("/admin/api/ftl/snapshot")
+            .ftl_memory(FtlMemory::Test {
+                clients: Vec::new(),
+                domains: Vec::new(),
+                over_time: Vec::new(),
+                upstreams: Vec::new(),
+                queries: Vec::new(),
+                strings: HashMap::new(),
+                counters: FtlCounters::default(),
+                settings: FtlSettings::default()
+            })
+            .expect_json(json!({
+                "clients": [],
+                "domains": [],
+                "over_time": [],
+                "upstreams": [],
+                "queries": [],
+                "strings": {},
+                "counters": {
+                    "total_queries": 0,
+                    "blocked_queries": 0,
+                    "cached_queries": 0,
+                    "unknown_queries": 0,
+                    "total_upstreams": 0,
+                    "total_clients": 0,
+                    "total_domains": 0,
+                    "query_capacity": 0,
+                    "upstream_capacity": 0,
+                    "client_capacity": 0,
+                    "domain_capacity": 0,
+                    "string_capacity": 0,
+                    "gravity_size": 0,
+                    "gravity_conf": 0,
+                    "query_type_counters": [0, 0, 0, 0, 0, 0, 0],
+                    "forwarded_queries": 0,
+                    "reply_count_nodata": 0,
+                    "reply_count_nxdomain": 0,
+                    "reply_count_cname": 0,
+                    "reply_count_ip": 0,
+                    "reply_count_domain": 0
+                },
+                "settings": {
+                    "version": 0,
+                    "global_shm_counter": 0,
+                    "next_str_pos": 1
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,514 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Details Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::{
+        FtlClient, FtlDomain, FtlMemory, FtlQuery, FtlQueryStatus, FtlQueryType, FtlRecords,
+        FtlStrings, Validator
+    },
+    routes::{
+        auth::{scopes::StatsRead, Scoped},
+        stats::{
+            common::{
+                get_current_over_time_slot, get_excluded_clients, get_excluded_domains,
+                get_hidden_client_ip, get_hidden_domain
+            },
+            query_types::QueryTypeReply,
+            top_domains::TopDomainItemReply,
+            upstreams::UpstreamItemReply
+        }
+    },
+    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel, SetupVarsEntry},
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use rocket::{request::Form, State};
+use std::collections::{BTreeMap, HashSet};
+
+/// Get the details of a single client, found by its IP address or name
+#[get("/stats/clients/<client>?<params..>")]
+pub fn client_details(
+    _auth: Scoped<StatsRead>,
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>,
+    client: String,
+    params: Form<ClientDetailsParams>
+) -> Reply {
+    reply_result(get_client_details(
+        &ftl_memory,
+        &env,
+        &client,
+        params.into_inner()
+    ))
+}
+
+/// Represents the possible GET parameters on `/stats/clients/<client>`
+#[derive(FromForm, Default)]
+pub struct ClientDetailsParams {
+    pub limit: Option<usize>
+}
+
+/// Represents the reply structure for client details
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientDetailsReply {
+    pub name: String,
+    pub ip: String,
+    pub total_queries: usize,
+    pub blocked_queries: usize,
+    pub last_query_time: u64,
+    pub arp_query_count: usize,
+    pub over_time: Vec<ClientOverTimeItem>,
+    pub top_domains: Vec<TopDomainItemReply>,
+    pub top_blocked: Vec<TopDomainItemReply>,
+    pub query_types: Vec<QueryTypeReply>,
+    pub upstreams: Vec<UpstreamItemReply>
+}
+
+/// Represents the number of queries the client made in an overTime interval
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientOverTimeItem {
+    pub timestamp: u64,
+    pub count: usize
+}
+
+/// Get the details of the client with the given IP address or name. The
+/// totals, overTime data, and activity come from the client's counters, and
+/// the rest is computed from the client's queries in shared memory.
+pub fn get_client_details(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    client: &str,
+    params: ClientDetailsParams
+) -> Result<ClientDetailsReply, Error> {
+    let limit = params.limit.unwrap_or(10);
+    let privacy_level = FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(env)?;
+
+    // Check if client details are private
+    if privacy_level >= FtlPrivacyLevel::HideDomainsAndClients {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    let lock = ftl_memory.lock()?;
+    let validator = Validator::new(ftl_memory, &lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+    let clients = ftl_memory.clients(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let upstreams = ftl_memory.upstreams(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let over_time = ftl_memory.over_time(&lock)?;
+
+    // Get the valid clients without hidden and excluded clients. Their IDs
+    // are kept because queries reference clients by their position in shared
+    // memory.
+    let hidden_client_ip = get_hidden_client_ip();
+    let excluded_clients: HashSet<String> = get_excluded_clients(env)?.into_iter().collect();
+    let valid_clients: Vec<(usize, &FtlClient)> = validator
+        .clients_with_ids(&clients)
+        .filter(|(_, ftl_client)| {
+            let ip = ftl_client.get_ip(&strings);
+            let name = ftl_client
+                .get_name(&strings)
+                .unwrap_or_default()
+                .to_lowercase();
+
+            ip != hidden_client_ip
+                && !excluded_clients.contains(ip)
+                && !excluded_clients.contains(&name)
+        })
+        .collect();
+
+    // Find the client by IP address first, then by name
+    let (client_id, ftl_client) = valid_clients
+        .iter()
+        .find(|(_, ftl_client)| ftl_client.get_ip(&strings) == client)
+        .or_else(|| {
+            valid_clients.iter().find(|(_, ftl_client)| {
+                ftl_client
+                    .get_name(&strings)
+                    .map_or(false, |name| name.eq_ignore_ascii_case(client))
+            })
+        })
+        .cloned()
+        .ok_or(ErrorKind::NotFound)?;
+
+    let client_queries: Vec<&FtlQuery> = validator
+        .queries(&queries)
+        .filter(|query| query.client_id as usize == client_id)
+        .collect();
+
+    // Count the queries by domain and upstream
+    let mut permitted_domains: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut blocked_domains: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut forwarded: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut blocked_count = 0;
+    let mut cached_count = 0;
+
+    for query in &client_queries {
+        if query.is_blocked() {
+            blocked_count += 1;
+        } else if query.status == FtlQueryStatus::Cache {
+            cached_count += 1;
+        } else if query.status == FtlQueryStatus::Forward {
+            *forwarded.entry(query.upstream_id as usize).or_insert(0) += 1;
+        }
+
+        // Private queries do not show which domain was queried
+        if !query.is_private {
+            let domain_counts = if query.is_blocked() {
+                &mut blocked_domains
+            } else {
+                &mut permitted_domains
+            };
+
+            *domain_counts.entry(query.domain_id as usize).or_insert(0) += 1;
+        }
+    }
+
+    // Check if the domains can be shown, according to the privacy level and
+    // the `API_QUERY_LOG_SHOW` setting
+    let display_setting = SetupVarsEntry::ApiQueryLogShow.read(env)?;
+    let hide_domains = privacy_level >= FtlPrivacyLevel::HideDomains;
+    let show_permitted =
+        !hide_domains && (display_setting == "all" || display_setting == "permittedonly");
+    let show_blocked =
+        !hide_domains && (display_setting == "all" || display_setting == "blockedonly");
+
+    let excluded_domains = get_excluded_domains(env)?;
+    let excluded_domains: HashSet<&str> = excluded_domains.iter().map(String::as_str).collect();
+
+    let top_domains = if show_permitted {
+        get_client_top_domains(
+            permitted_domains,
+            &domains,
+            &strings,
+            &excluded_domains,
+            limit
+        )
+    } else {
+        Vec::new()
+    };
+    let top_blocked = if show_blocked {
+        get_client_top_domains(
+            blocked_domains,
+            &domains,
+            &strings,
+            &excluded_domains,
+            limit
+        )
+    } else {
+        Vec::new()
+    };
+
+    let query_types: Vec<QueryTypeReply> = FtlQueryType::variants()
+        .iter()
+        .map(|&variant| QueryTypeReply {
+            name: variant.get_name(),
+            count: client_queries
+                .iter()
+                .filter(|query| query.query_type == variant)
+                .count()
+        })
+        .collect();
+
+    // Add blocklist and cache upstreams, then the upstreams the client's
+    // queries were forwarded to (descending by count)
+    let mut forwarded: Vec<(usize, usize)> = forwarded.into_iter().collect();
+    forwarded.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut client_upstreams = vec![
+        UpstreamItemReply {
+            name: "blocklist".to_owned(),
+            ip: "blocklist".to_owned(),
+            count: blocked_count
+        },
+        UpstreamItemReply {
+            name: "cache".to_owned(),
+            ip: "cache".to_owned(),
+            count: cached_count
+        },
+    ];
+    client_upstreams.extend(forwarded.into_iter().map(|(upstream_id, count)| {
+        let upstream = &upstreams[upstream_id];
+
+        UpstreamItemReply {
+            name: upstream.get_name(&strings).unwrap_or_default().to_owned(),
+            ip: upstream.get_ip(&strings).to_owned(),
+            count
+        }
+    }));
+
+    // Get the client's overTime data for each of the overTime slots, skipping
+    // the slots without any data
+    let client_over_time: Vec<ClientOverTimeItem> = over_time
+        .iter()
+        .take(get_current_over_time_slot(&over_time) + 1)
+        .enumerate()
+        .skip_while(|(_, time)| time.total_queries <= 0 && time.blocked_queries <= 0)
+        .map(|(i, time)| ClientOverTimeItem {
+            timestamp: time.timestamp as u64,
+            count: *ftl_client.over_time.get(i).unwrap_or(&0) as usize
+        })
+        .collect();
+
+    Ok(ClientDetailsReply {
+        name: ftl_client.get_name(&strings).unwrap_or_default().to_owned(),
+        ip: ftl_client.get_ip(&strings).to_owned(),
+        total_queries: ftl_client.query_count as usize,
+        blocked_queries: ftl_client.blocked_count as usize,
+        last_query_time: ftl_client.last_query_time as u64,
+        arp_query_count: ftl_client.arp_query_count as usize,
+        over_time: client_over_time,
+        top_domains,
+        top_blocked,
+        query_types,
+        upstreams: client_upstreams
+    })
+}
+
+/// Get the most queried domains (descending by count) from the query counts
+/// of each domain ID, without hidden and excluded domains
+fn get_client_top_domains(
+    domain_counts: BTreeMap<usize, usize>,
+    domains: &FtlRecords<FtlDomain>,
+    strings: &FtlStrings,
+    excluded_domains: &HashSet<&str>,
+    limit: usize
+) -> Vec<TopDomainItemReply> {
+    let hidden_domain = get_hidden_domain();
+
+    let mut top_domains: Vec<(&str, usize)> = domain_counts
+        .into_iter()
+        .map(|(domain_id, count)| (domains[domain_id].get_domain(strings), count))
+        .filter(|(domain, _)| *domain != hidden_domain && !excluded_domains.contains(domain))
+        .collect();
+
+    top_domains.sort_by(|a, b| b.1.cmp(&a.1));
+
+    top_domains
+        .into_iter()
+        .take(limit)
+        .map(|(domain, count)| TopDomainItemReply {
+            domain: domain.to_owned(),
+            count
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        env::PiholeFile,
+        ftl::{FtlMemory, FtlOverTime, FtlSettings},
+        routes::stats::history::testing::{
+            test_clients, test_counters, test_domains, test_queries, test_strings, test_upstreams
+        },
+        testing::TestBuilder
+    };
+    use rocket::http::Status;
+    use rocket_contrib::json::JsonValue;
+
+    /// The history test data, with activity and overTime data for the client
+    /// 192.168.1.11. There are 3 overTime slots, and the first one is empty.
+    fn test_data() -> FtlMemory {
+        let mut clients = test_clients();
+        clients[1] = clients[1].with_over_time(vec![0, 1, 2]);
+        clients[1].last_query_time = 263_585;
+        clients[1].arp_query_count = 4;
+
+        FtlMemory::Test {
+            clients,
+            counters: test_counters(),
+            domains: test_domains(),
+            over_time: vec![
+                FtlOverTime::new(0, 0, 0, 0, 0, [0; 7]),
+                FtlOverTime::new(1, 2, 0, 0, 2, [0; 7]),
+                FtlOverTime::new(2, 5, 3, 1, 1, [0; 7]),
+            ],
+            strings: test_strings(),
+            queries: test_queries(),
+            upstreams: test_upstreams(),
+            settings: FtlSettings::default()
+        }
+    }
+
+    /// The expected details of the client 192.168.1.11
+    fn expected_details() -> JsonValue {
+        json!({
+            "name": "",
+            "ip": "192.168.1.11",
+            "total_queries": 3,
+            "blocked_queries": 2,
+            "last_query_time": 263_585,
+            "arp_query_count": 4,
+            "over_time": [
+                { "timestamp": 1, "count": 1 },
+                { "timestamp": 2, "count": 2 }
+            ],
+            "top_domains": [
+                { "domain": "domain1.com", "count": 1 }
+            ],
+            "top_blocked": [
+                { "domain": "domain2.com", "count": 1 },
+                { "domain": "domain3.com", "count": 1 }
+            ],
+            "query_types": [
+                { "name": "A", "count": 1 },
+                { "name": "AAAA", "count": 2 },
+                { "name": "ANY", "count": 0 },
+                { "name": "SRV", "count": 0 },
+                { "name": "SOA", "count": 0 },
+                { "name": "PTR", "count": 0 },
+                { "name": "TXT", "count": 0 }
+            ],
+            "upstreams": [
+                { "name": "blocklist", "ip": "blocklist", "count": 2 },
+                { "name": "cache", "ip": "cache", "count": 1 }
+            ]
+        })
+    }
+
+    /// The client can be found by its IP address
+    #[test]
+    fn by_ip() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/192.168.1.11")
+            .ftl_memory(test_data())
+            .expect_json(expected_details())
+            .test();
+    }
+
+    /// The client can be found by its name, ignoring case, and the upstreams
+    /// its queries were forwarded to are listed
+    #[test]
+    fn by_name() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/CLIENT1")
+            .ftl_memory(test_data())
+            .expect_json(json!({
+                "name": "client1",
+                "ip": "192.168.1.10",
+                "total_queries": 3,
+                "blocked_queries": 0,
+                "last_query_time": 0,
+                "arp_query_count": 0,
+                "over_time": [
+                    { "timestamp": 1, "count": 0 },
+                    { "timestamp": 2, "count": 0 }
+                ],
+                "top_domains": [
+                    { "domain": "domain1.com", "count": 3 }
+                ],
+                "top_blocked": [],
+                "query_types": [
+                    { "name": "A", "count": 1 },
+                    { "name": "AAAA", "count": 1 },
+                    { "name": "ANY", "count": 0 },
+                    { "name": "SRV", "count": 0 },
+                    { "name": "SOA", "count": 0 },
+                    { "name": "PTR", "count": 1 },
+                    { "name": "TXT", "count": 0 }
+                ],
+                "upstreams": [
+                    { "name": "blocklist", "ip": "blocklist", "count": 0 },
+                    { "name": "cache", "ip": "cache", "count": 0 },
+                    {
+                        "name": "google-public-dns-a.google.com",
+                        "ip": "8.8.8.8",
+                        "count": 3
+                    }
+                ]
+            }))
+            .test();
+    }
+
+    /// Excluded domains are not shown in the top domains
+    #[test]
+    fn excluded_domains() {
+        let mut expected = expected_details();
+        expected.0["top_blocked"] = json!([{ "domain": "domain3.com", "count": 1 }]).0;
+
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/192.168.1.11")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "API_EXCLUDE_DOMAINS=domain2.com")
+            .expect_json(expected)
+            .test();
+    }
+
+    /// Privacy level 1 does not show any domains
+    #[test]
+    fn privacy_hide_domains() {
+        let mut expected = expected_details();
+        expected.0["top_domains"] = json!([]).0;
+        expected.0["top_blocked"] = json!([]).0;
+
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/192.168.1.11")
+            .ftl_memory(test_data())
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=1")
+            .expect_json(expected)
+            .test();
+    }
+
+    /// Excluded clients are not found
+    #[test]
+    fn excluded_client() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/client1")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "API_EXCLUDE_CLIENTS=client1")
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The hidden client is not found
+    #[test]
+    fn hidden_client() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/0.0.0.0")
+            .ftl_memory(test_data())
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Privacy level 2 does not show any clients
+    #[test]
+    fn privacy_hide_clients() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/192.168.1.11")
+            .ftl_memory(test_data())
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=2")
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -10,9 +10,9 @@
 
 use crate::{
     env::Env,
-    ftl::{ClientReply, FtlClient, FtlMemory, ShmLockGuard},
+    ftl::{ClientReply, FtlClient, FtlMemory, FtlRecords, ShmLockGuard, Validator},
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::common::{remove_excluded_clients, remove_hidden_clients}
     },
     settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
@@ -23,7 +23,7 @@ use rocket::{request::Form, State};
 /// Get client information
 #[get("/stats/clients?<params..>")]
 pub fn clients(
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
     params: Form<ClientParams>
@@ -44,11 +44,12 @@ fn get_clients(
     params: ClientParams
 ) -> Result<Vec<ClientReply>, Error> {
     let lock = ftl_memory.lock()?;
+    let validator = Validator::new(ftl_memory, &lock)?;
     let strings = ftl_memory.strings(&lock)?;
     let clients = ftl_memory.clients(&lock)?;
 
     Ok(
-        filter_ftl_clients(ftl_memory, &lock, &clients, env, params)?
+        filter_ftl_clients(ftl_memory, &lock, &validator, &clients, env, params)?
             .iter()
             .map(|client| client.as_reply(&strings))
             .collect::<Vec<ClientReply>>()
@@ -56,11 +57,12 @@ fn get_clients(
 }
 
 /// Get FTL clients which are allowed to be used according to settings and
-/// parameters
+/// parameters. Only valid clients are returned, using the validator.
 pub fn filter_ftl_clients<'a>(
     ftl_memory: &'a FtlMemory,
     lock: &ShmLockGuard<'a>,
-    clients: &'a [FtlClient],
+    validator: &Validator<'a>,
+    clients: &'a FtlRecords<'a, FtlClient>,
     env: &Env,
     params: ClientParams
 ) -> Result<Vec<&'a FtlClient>, Error> {
@@ -72,13 +74,9 @@ pub fn filter_ftl_clients<'a>(
     }
 
     let strings = ftl_memory.strings(&lock)?;
-    let counters = ftl_memory.counters(&lock)?;
 
     // Get an array of valid client references (FTL allocates more than it uses)
-    let mut clients = clients
-        .iter()
-        .take(counters.total_clients as usize)
-        .collect();
+    let mut clients = validator.clients(clients).collect();
 
     // Ignore hidden and excluded clients
     remove_hidden_clients(&mut clients, &strings);
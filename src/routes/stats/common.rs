@@ -200,7 +200,7 @@ mod tests {
 
         let lock_guard = ShmLockGuard::Test;
         let clients = ftl_memory.clients(&lock_guard).unwrap();
-        let mut clients = clients.iter().collect();
+        let mut clients = (0..clients.len()).map(|id| &clients[id]).collect();
 
         remove_excluded_clients(
             &mut clients,
@@ -229,7 +229,7 @@ mod tests {
 
         let lock_guard = ShmLockGuard::Test;
         let domains = ftl_memory.domains(&lock_guard).unwrap();
-        let mut clients = domains.iter().collect();
+        let mut clients = (0..domains.len()).map(|id| &domains[id]).collect();
 
         remove_excluded_domains(
             &mut clients,
@@ -254,7 +254,7 @@ mod tests {
         let lock_guard = ShmLockGuard::Test;
 
         let clients = ftl_memory.clients(&lock_guard).unwrap();
-        let mut clients: Vec<&FtlClient> = clients.iter().collect();
+        let mut clients: Vec<&FtlClient> = (0..clients.len()).map(|id| &clients[id]).collect();
         let mut clients_clone = clients.clone();
         clients_clone.remove(2);
 
@@ -270,7 +270,7 @@ mod tests {
         let lock_guard = ShmLockGuard::Test;
 
         let domains = ftl_memory.domains(&lock_guard).unwrap();
-        let mut domains: Vec<&FtlDomain> = domains.iter().collect();
+        let mut domains: Vec<&FtlDomain> = (0..domains.len()).map(|id| &domains[id]).collect();
         let mut domains_clone = domains.clone();
         domains_clone.remove(2);
 
@@ -13,7 +13,7 @@ use crate::{
     env::Env,
     ftl::ClientReply,
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::{
             common::{get_excluded_clients, get_hidden_client_ip},
             database::over_time_history_db::align_from_until,
@@ -34,7 +34,7 @@ pub fn over_time_clients_db(
     from: u64,
     until: u64,
     interval: Option<usize>,
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     db: FtlDatabase,
     env: State<Env>
 ) -> Reply {
@@ -11,7 +11,10 @@
 use crate::{
     databases::ftl::FtlDatabase,
     ftl::BLOCKED_STATUSES,
-    routes::{auth::User, stats::over_time_history::OverTimeItem},
+    routes::{
+        auth::{scopes::StatsRead, Scoped},
+        stats::over_time_history::OverTimeItem
+    },
     util::{reply_result, Error, ErrorKind, Reply}
 };
 use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
@@ -25,7 +28,7 @@ pub fn over_time_history_db(
     from: u64,
     until: u64,
     interval: Option<usize>,
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     db: FtlDatabase
 ) -> Reply {
     reply_result(over_time_history_db_impl(
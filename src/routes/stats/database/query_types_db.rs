@@ -11,7 +11,10 @@
 use crate::{
     databases::ftl::FtlDatabase,
     ftl::FtlQueryType,
-    routes::{auth::User, stats::query_types::QueryTypeReply},
+    routes::{
+        auth::{scopes::StatsRead, Scoped},
+        stats::query_types::QueryTypeReply
+    },
     util::{reply_result, Error, ErrorKind, Reply}
 };
 use diesel::{dsl::sql, prelude::*, sql_types::BigInt, sqlite::SqliteConnection};
@@ -20,7 +23,7 @@ use std::collections::HashMap;
 
 /// Get query type counts from the database
 #[get("/stats/database/query_types?<from>&<until>")]
-pub fn query_types_db(from: u64, until: u64, _auth: User, db: FtlDatabase) -> Reply {
+pub fn query_types_db(from: u64, until: u64, _auth: Scoped<StatsRead>, db: FtlDatabase) -> Reply {
     reply_result(query_types_db_impl(from, until, &db as &SqliteConnection))
 }
 
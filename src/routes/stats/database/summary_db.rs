@@ -13,7 +13,7 @@ use crate::{
     env::Env,
     ftl::{FtlQueryStatus, FtlQueryType, BLOCKED_STATUSES},
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::{
             database::get_query_type_counts,
             summary::{ReplyTypes, Summary, TotalQueries}
@@ -31,7 +31,7 @@ use rocket::State;
 pub fn get_summary_db(
     from: u64,
     until: u64,
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     db: FtlDatabase,
     env: State<Env>
 ) -> Reply {
@@ -13,7 +13,7 @@ use crate::{
     env::Env,
     ftl::BLOCKED_STATUSES,
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::{
             check_privacy_level_top_clients,
             common::{get_excluded_clients, get_hidden_client_ip},
@@ -31,7 +31,7 @@ use rocket::{request::Form, State};
 /// Get the top clients
 #[get("/stats/database/top_clients?<from>&<until>&<params..>")]
 pub fn top_clients_db(
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     env: State<Env>,
     db: FtlDatabase,
     from: u64,
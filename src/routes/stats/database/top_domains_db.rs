@@ -13,7 +13,7 @@ use crate::{
     env::{Env, PiholeFile},
     ftl::BLOCKED_STATUSES,
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::{
             check_privacy_level_top_domains, check_query_log_show_top_domains,
             common::{get_excluded_domains, get_hidden_domain},
@@ -32,7 +32,7 @@ use rocket::{request::Form, State};
 /// Return the top domains
 #[get("/stats/database/top_domains?<from>&<until>&<params..>")]
 pub fn top_domains_db(
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     env: State<Env>,
     db: FtlDatabase,
     from: u64,
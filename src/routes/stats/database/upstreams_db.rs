@@ -12,7 +12,7 @@ use crate::{
     databases::ftl::FtlDatabase,
     ftl::FtlQueryStatus,
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::{
             database::{get_blocked_query_count, get_query_status_count},
             upstreams::{UpstreamItemReply, UpstreamsReply}
@@ -26,7 +26,7 @@ use std::collections::HashMap;
 
 /// Get upstream data from the database
 #[get("/stats/database/upstreams?<from>&<until>")]
-pub fn upstreams_db(from: u64, until: u64, _auth: User, db: FtlDatabase) -> Reply {
+pub fn upstreams_db(from: u64, until: u64, _auth: Scoped<StatsRead>, db: FtlDatabase) -> Reply {
     reply_result(upstreams_db_impl(from, until, &db as &SqliteConnection))
 }
 
@@ -53,17 +53,7 @@ pub fn load_queries_from_database(
     let db_query = skip_to_cursor_db(db_query, start_id);
 
     // Apply filters
-    let db_query = filter_time_from_db(db_query, params);
-    let db_query = filter_time_until_db(db_query, params);
-    let db_query = filter_domain_db(db_query, params);
-    let db_query = filter_client_db(db_query, params);
-    let db_query = filter_upstream_db(db_query, params);
-    let db_query = filter_query_type_db(db_query, params);
-    let db_query = filter_status_db(db_query, params);
-    let db_query = filter_blocked_db(db_query, params);
-    let db_query = filter_excluded_domains_db(db_query, env)?;
-    let db_query = filter_excluded_clients_db(db_query, env)?;
-    let db_query = filter_setup_vars_setting_db(db_query, env)?;
+    let db_query = filter_queries_db(db_query, params, env)?;
 
     // Execute the query and load the results
     let mut results: Vec<FtlDbQuery> = execute_query(db, db_query)?;
@@ -86,6 +76,57 @@ pub fn load_queries_from_database(
     Ok((results, cursor))
 }
 
+/// Load the queries from the database which were stored after the given
+/// query ID, oldest first.
+///
+/// # Arguments:
+/// - `db`: A connection to the FTL database
+/// - `after_id`: Only queries with a larger ID than this are loaded
+/// - `params`: Parameters given to the history endpoint (filters)
+/// - `limit`: The maximum number of queries to load
+pub fn load_new_queries_from_database(
+    db: &SqliteConnection,
+    after_id: i64,
+    params: &HistoryParams,
+    env: &Env,
+    limit: usize
+) -> Result<Vec<FtlDbQuery>, Error> {
+    // Use the Diesel DSL of this table for easy querying
+    use crate::databases::ftl::queries::dsl::*;
+
+    let db_query = queries
+        .into_boxed()
+        .filter(id.gt(after_id as i32))
+        .limit(limit as i64)
+        // Start with the oldest of the new queries
+        .order(id.asc());
+
+    // Apply filters
+    let db_query = filter_queries_db(db_query, params, env)?;
+
+    execute_query(db, db_query)
+}
+
+/// Apply the history filters and the query log settings to the database query
+fn filter_queries_db<'a>(
+    db_query: queries::BoxedQuery<'a, Sqlite>,
+    params: &HistoryParams,
+    env: &Env
+) -> Result<queries::BoxedQuery<'a, Sqlite>, Error> {
+    let db_query = filter_time_from_db(db_query, params);
+    let db_query = filter_time_until_db(db_query, params);
+    let db_query = filter_domain_db(db_query, params);
+    let db_query = filter_client_db(db_query, params);
+    let db_query = filter_upstream_db(db_query, params);
+    let db_query = filter_query_type_db(db_query, params);
+    let db_query = filter_status_db(db_query, params);
+    let db_query = filter_blocked_db(db_query, params);
+    let db_query = filter_excluded_domains_db(db_query, env)?;
+    let db_query = filter_excluded_clients_db(db_query, env)?;
+
+    filter_setup_vars_setting_db(db_query, env)
+}
+
 /// Execute a database query for DNS queries on an FTL database.
 /// The database could be real, or it could be a test database.
 pub fn execute_query(
@@ -100,7 +141,7 @@ pub fn execute_query(
 
 #[cfg(test)]
 mod test {
-    use super::load_queries_from_database;
+    use super::{load_new_queries_from_database, load_queries_from_database};
     use crate::{
         databases::ftl::connect_to_test_db,
         env::{Config, Env},
@@ -148,4 +189,22 @@ mod test {
         assert_eq!(queries.len(), 2);
         assert_eq!(cursor, expected_cursor);
     }
+
+    /// New queries are loaded after the given ID, oldest first
+    #[test]
+    fn new_queries() {
+        let env = Env::Test(Config::default(), HashMap::new());
+
+        let queries = load_new_queries_from_database(
+            &connect_to_test_db(),
+            1,
+            &HistoryParams::default(),
+            &env,
+            2
+        )
+        .unwrap();
+        let ids: Vec<Option<i32>> = queries.iter().map(|query| query.id).collect();
+
+        assert_eq!(ids, vec![Some(2), Some(3)]);
+    }
 }
@@ -12,21 +12,30 @@ use crate::{
     databases::ftl::FtlDatabase,
     env::Env,
     ftl::{FtlDnssecType, FtlMemory, FtlQueryReplyType, FtlQueryStatus, FtlQueryType},
-    routes::{auth::User, stats::history::get_history::get_history},
+    routes::{
+        auth::{scopes::StatsRead, AuthData, Scoped},
+        stats::history::{
+            get_history::get_history,
+            stream::{QueryStream, StreamLimit}
+        }
+    },
     util::{Error, ErrorKind, Reply}
 };
 use base64::{decode, encode};
 use failure::ResultExt;
 use rocket::{
-    http::RawStr,
+    http::{ContentType, RawStr},
     request::{Form, FromFormValue},
+    response::{content::Content, Stream},
     State
 };
 
-/// Get the query history according to the specified parameters
+/// Get the query history according to the specified parameters. If `since`
+/// is given, only the queries newer than that cursor are returned, oldest
+/// first, so a client can keep a copy of the query log up to date.
 #[get("/stats/history?<params..>")]
 pub fn history(
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
     params: Form<HistoryParams>,
@@ -35,10 +44,40 @@ pub fn history(
     get_history(&ftl_memory, &env, params.into_inner(), &db)
 }
 
+/// Stream the queries FTL receives as Server-Sent Events, so clients can follow
+/// live traffic without polling `/stats/history`. The same filters and
+/// privacy settings are applied as in `/stats/history`, but `cursor`, `since`,
+/// and `limit` are ignored. Only a few streams can be open at once, so more
+/// are rejected with `429 Too Many Requests`.
+#[get("/stats/history/stream?<params..>")]
+pub fn history_stream<'r>(
+    auth: Scoped<StatsRead>,
+    ftl_memory: State<'r, FtlMemory>,
+    env: State<'r, Env>,
+    auth_data: State<'r, AuthData>,
+    limit: State<'r, StreamLimit>,
+    params: Form<HistoryParams>
+) -> Result<Content<Stream<QueryStream<'r>>>, Error> {
+    let stream = QueryStream::new(
+        ftl_memory.inner(),
+        env.inner(),
+        auth_data.inner(),
+        limit.inner(),
+        auth.principal,
+        params.into_inner()
+    )?;
+
+    Ok(Content(
+        ContentType::new("text", "event-stream"),
+        Stream::from(stream)
+    ))
+}
+
 /// Represents the possible GET parameters on `/stats/history`
 #[derive(FromForm)]
 pub struct HistoryParams {
     pub cursor: Option<HistoryCursor>,
+    pub since: Option<HistoryCursor>,
     pub from: Option<u64>,
     pub until: Option<u64>,
     pub domain: Option<String>,
@@ -56,6 +95,7 @@ impl Default for HistoryParams {
     fn default() -> Self {
         HistoryParams {
             cursor: None,
+            since: None,
             from: None,
             until: None,
             domain: None,
@@ -10,7 +10,7 @@
 
 use crate::{
     databases::ftl::queries,
-    ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard, Validator},
     routes::stats::history::endpoints::HistoryParams,
     util::Error
 };
@@ -27,13 +27,11 @@ pub fn filter_client<'a>(
     if let Some(ref client_filter) = params.client {
         // Find the matching clients. If none are found, return an empty
         // iterator because no query can match the client requested
-        let counters = ftl_memory.counters(ftl_lock)?;
+        let validator = Validator::new(ftl_memory, ftl_lock)?;
         let strings = ftl_memory.strings(ftl_lock)?;
         let clients = ftl_memory.clients(ftl_lock)?;
-        let client_ids: HashSet<usize> = clients
-            .iter()
-            .take(counters.total_clients as usize)
-            .enumerate()
+        let client_ids: HashSet<usize> = validator
+            .clients_with_ids(&clients)
             .filter_map(|(i, client)| {
                 let ip = client.get_ip(&strings);
                 let name = client.get_name(&strings).unwrap_or_default();
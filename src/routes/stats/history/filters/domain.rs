@@ -10,7 +10,7 @@
 
 use crate::{
     databases::ftl::queries,
-    ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard, Validator},
     routes::stats::history::endpoints::HistoryParams,
     util::Error
 };
@@ -27,13 +27,11 @@ pub fn filter_domain<'a>(
     if let Some(ref domain_filter) = params.domain {
         // Find the matching domains. If none are found, return an empty
         // iterator because no query can match the domain requested
-        let counters = ftl_memory.counters(ftl_lock)?;
+        let validator = Validator::new(ftl_memory, ftl_lock)?;
         let strings = ftl_memory.strings(ftl_lock)?;
         let domains = ftl_memory.domains(ftl_lock)?;
-        let domain_ids: HashSet<usize> = domains
-            .iter()
-            .take(counters.total_domains as usize)
-            .enumerate()
+        let domain_ids: HashSet<usize> = validator
+            .domains_with_ids(&domains)
             .filter_map(|(i, domain)| {
                 if domain.get_domain(&strings).contains(domain_filter) {
                     Some(i)
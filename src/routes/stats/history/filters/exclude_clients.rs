@@ -11,7 +11,7 @@
 use crate::{
     databases::ftl::queries,
     env::Env,
-    ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard, Validator},
     settings::{ConfigEntry, SetupVarsEntry},
     util::Error
 };
@@ -39,13 +39,11 @@ pub fn filter_excluded_clients<'a>(
     }
 
     // Find the client IDs of the excluded clients
-    let counters = ftl_memory.counters(ftl_lock)?;
+    let validator = Validator::new(ftl_memory, ftl_lock)?;
     let strings = ftl_memory.strings(ftl_lock)?;
     let clients = ftl_memory.clients(ftl_lock)?;
-    let excluded_client_ids: HashSet<usize> = clients
-        .iter()
-        .take(counters.total_clients as usize)
-        .enumerate()
+    let excluded_client_ids: HashSet<usize> = validator
+        .clients_with_ids(&clients)
         .filter_map(|(i, client)| {
             let ip = client.get_ip(&strings);
             let name = client.get_name(&strings).unwrap_or_default();
@@ -11,7 +11,7 @@
 use crate::{
     databases::ftl::queries,
     env::Env,
-    ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard, Validator},
     settings::{ConfigEntry, SetupVarsEntry},
     util::Error
 };
@@ -39,13 +39,11 @@ pub fn filter_excluded_domains<'a>(
     }
 
     // Find the domain IDs of the excluded domains
-    let counters = ftl_memory.counters(ftl_lock)?;
+    let validator = Validator::new(ftl_memory, ftl_lock)?;
     let strings = ftl_memory.strings(ftl_lock)?;
     let domains = ftl_memory.domains(ftl_lock)?;
-    let excluded_domain_ids: HashSet<usize> = domains
-        .iter()
-        .take(counters.total_domains as usize)
-        .enumerate()
+    let excluded_domain_ids: HashSet<usize> = validator
+        .domains_with_ids(&domains)
         .filter_map(|(i, domain)| {
             if excluded_domains.contains(domain.get_domain(&strings)) {
                 Some(i)
@@ -10,7 +10,7 @@
 
 use crate::{
     databases::ftl::queries,
-    ftl::{FtlMemory, FtlQuery, FtlQueryStatus, ShmLockGuard},
+    ftl::{FtlMemory, FtlQuery, FtlQueryStatus, ShmLockGuard, Validator},
     routes::stats::history::endpoints::HistoryParams,
     util::Error
 };
@@ -39,13 +39,11 @@ pub fn filter_upstream<'a>(
         } else {
             // Find the matching upstreams. If none are found, return an empty
             // iterator because no query can match the upstream requested
-            let counters = ftl_memory.counters(ftl_lock)?;
+            let validator = Validator::new(ftl_memory, ftl_lock)?;
             let strings = ftl_memory.strings(ftl_lock)?;
             let upstreams = ftl_memory.upstreams(ftl_lock)?;
-            let upstream_ids: HashSet<usize> = upstreams
-                .iter()
-                .take(counters.total_upstreams as usize)
-                .enumerate()
+            let upstream_ids: HashSet<usize> = validator
+                .upstreams_with_ids(&upstreams)
                 .filter_map(|(i, item)| {
                     let ip = item.get_ip(&strings);
                     let name = item.get_name(&strings).unwrap_or_default();
@@ -12,15 +12,16 @@ use super::{
     endpoints::{HistoryCursor, HistoryParams},
     filters::*,
     map_query_to_json::map_query_to_json,
+    since::get_history_since,
     skip_to_cursor::skip_to_cursor
 };
 use crate::{
     databases::ftl::FtlDatabase,
     env::Env,
-    ftl::{FtlMemory, FtlQuery},
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard, Validator},
     routes::stats::history::database::load_queries_from_database,
     settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
-    util::{reply_data, Reply}
+    util::{reply_data, Error, Reply}
 };
 use diesel::sqlite::SqliteConnection;
 use rocket_contrib::json::JsonValue;
@@ -33,6 +34,11 @@ pub fn get_history(
     params: HistoryParams,
     db: &FtlDatabase
 ) -> Reply {
+    // If there is a `since` cursor, only get the queries newer than it
+    if let Some(since) = params.since {
+        return get_history_since(ftl_memory, env, params, since, db as &SqliteConnection);
+    }
+
     // Check if query details are private
     if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(env)? >= FtlPrivacyLevel::Maximum {
         // `None::<()>` represents `null` in JSON. It needs the type parameter because
@@ -44,7 +50,7 @@ pub fn get_history(
     }
 
     let lock = ftl_memory.lock()?;
-    let counters = ftl_memory.counters(&lock)?;
+    let validator = Validator::new(ftl_memory, &lock)?;
     let queries = ftl_memory.queries(&lock)?;
 
     // The following code uses a boxed iterator,
@@ -63,34 +69,15 @@ pub fn get_history(
     // type.
 
     // Start making an iterator by getting valid query references (FTL allocates
-    // more than it uses).
-    let queries_iter = Box::new(
-        queries
-            .iter()
-            // Get the most recent queries first
-            .rev()
-            // Skip the uninitialized queries
-            .skip(queries.len() - counters.total_queries as usize)
-    );
+    // more than it uses, and corrupt queries are skipped). Get the most recent
+    // queries first.
+    let queries_iter = Box::new(validator.queries(&queries).rev());
 
     // If there is a cursor, skip to the referenced query
     let queries_iter = skip_to_cursor(queries_iter, &params);
 
     // Apply filters
-    let queries_iter = filter_private_queries(queries_iter);
-    let queries_iter = filter_setup_vars_setting(queries_iter, env)?;
-    let queries_iter = filter_time_from(queries_iter, &params);
-    let queries_iter = filter_time_until(queries_iter, &params);
-    let queries_iter = filter_query_type(queries_iter, &params);
-    let queries_iter = filter_upstream(queries_iter, &params, ftl_memory, &lock)?;
-    let queries_iter = filter_domain(queries_iter, &params, ftl_memory, &lock)?;
-    let queries_iter = filter_client(queries_iter, &params, ftl_memory, &lock)?;
-    let queries_iter = filter_status(queries_iter, &params);
-    let queries_iter = filter_blocked(queries_iter, &params);
-    let queries_iter = filter_dnssec(queries_iter, &params);
-    let queries_iter = filter_reply(queries_iter, &params);
-    let queries_iter = filter_excluded_domains(queries_iter, env, ftl_memory, &lock)?;
-    let queries_iter = filter_excluded_clients(queries_iter, env, ftl_memory, &lock)?;
+    let queries_iter = filter_queries(queries_iter, &params, env, ftl_memory, &lock)?;
 
     // Get the limit
     let limit = params.limit.unwrap_or(100);
@@ -171,6 +158,31 @@ pub fn get_history(
     }))
 }
 
+/// Apply the history filters and the query log settings to the queries
+pub fn filter_queries<'a>(
+    queries_iter: Box<dyn Iterator<Item = &'a FtlQuery> + 'a>,
+    params: &HistoryParams,
+    env: &Env,
+    ftl_memory: &FtlMemory,
+    ftl_lock: &ShmLockGuard<'a>
+) -> Result<Box<dyn Iterator<Item = &'a FtlQuery> + 'a>, Error> {
+    let queries_iter = filter_private_queries(queries_iter);
+    let queries_iter = filter_setup_vars_setting(queries_iter, env)?;
+    let queries_iter = filter_time_from(queries_iter, params);
+    let queries_iter = filter_time_until(queries_iter, params);
+    let queries_iter = filter_query_type(queries_iter, params);
+    let queries_iter = filter_upstream(queries_iter, params, ftl_memory, ftl_lock)?;
+    let queries_iter = filter_domain(queries_iter, params, ftl_memory, ftl_lock)?;
+    let queries_iter = filter_client(queries_iter, params, ftl_memory, ftl_lock)?;
+    let queries_iter = filter_status(queries_iter, params);
+    let queries_iter = filter_blocked(queries_iter, params);
+    let queries_iter = filter_dnssec(queries_iter, params);
+    let queries_iter = filter_reply(queries_iter, params);
+    let queries_iter = filter_excluded_domains(queries_iter, env, ftl_memory, ftl_lock)?;
+
+    filter_excluded_clients(queries_iter, env, ftl_memory, ftl_lock)
+}
+
 /// Check if the timespan is completely within the last 24 hours
 fn is_within_24_hours(from: Option<u64>, until: Option<u64>) -> bool {
     let now = SystemTime::now()
@@ -13,9 +13,11 @@ mod endpoints;
 mod filters;
 mod get_history;
 mod map_query_to_json;
+mod since;
 mod skip_to_cursor;
+mod stream;
 
 #[cfg(test)]
-mod testing;
+pub mod testing;
 
-pub use self::endpoints::*;
+pub use self::{endpoints::*, stream::StreamLimit};
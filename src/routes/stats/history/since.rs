@@ -0,0 +1,288 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// History Since Cursor Functionality
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::{
+    database::load_new_queries_from_database,
+    endpoints::{HistoryCursor, HistoryParams},
+    get_history::filter_queries,
+    map_query_to_json::map_query_to_json
+};
+use crate::{
+    env::Env,
+    ftl::{FtlMemory, FtlQuery, Validator},
+    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use diesel::sqlite::SqliteConnection;
+use rocket_contrib::json::JsonValue;
+
+/// Get the queries which are newer than the `since` cursor, oldest first, up
+/// to the limit. A new `since` cursor is returned which points to the newest
+/// query returned, and `more` is true if there are more new queries than the
+/// limit allowed.
+///
+/// When the query referenced by the cursor is no longer in shared memory
+/// (it was removed from the in-memory ring, or FTL restarted), `expired` is
+/// true. In that case the new queries are loaded from the database if the
+/// cursor has a database ID. Otherwise the queries start from the oldest query
+/// in memory, and `gap` is true because some queries may have been missed.
+///
+/// An empty cursor (`{}`) starts from the oldest query in memory.
+pub fn get_history_since(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    params: HistoryParams,
+    since: HistoryCursor,
+    db: &SqliteConnection
+) -> Reply {
+    // Paging backwards and forwards at the same time is not supported
+    if params.cursor.is_some() {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    // Check if query details are private
+    if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(env)? >= FtlPrivacyLevel::Maximum {
+        return reply_data(json!({
+            "history": [],
+            "since": since.as_base64()?,
+            "more": false,
+            "expired": false,
+            "gap": false
+        }));
+    }
+
+    let lock = ftl_memory.lock()?;
+    let validator = Validator::new(ftl_memory, &lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let limit = params.limit.unwrap_or(100);
+
+    // Check if the cursor's query has fallen out of memory. Query IDs start
+    // over when FTL restarts, so a cursor newer than the newest query in memory
+    // is also out of date.
+    let expired = match since.id {
+        Some(id) => {
+            let oldest = validator.queries(&queries).next();
+            let newest = validator.queries(&queries).next_back();
+
+            match (oldest, newest) {
+                (Some(oldest), Some(newest)) => oldest.id > id.saturating_add(1) || newest.id < id,
+                _ => true
+            }
+        }
+        None => false
+    };
+
+    // The queries, along with the cursor which points to them
+    let mut history: Vec<(JsonValue, HistoryCursor)> = Vec::new();
+    let mut gap = false;
+
+    let queries_iter: Box<dyn Iterator<Item = &FtlQuery> + '_> = match since {
+        HistoryCursor { id: Some(id), .. } if !expired => Box::new(
+            validator
+                .queries(&queries)
+                .skip_while(move |query| query.id <= id)
+        ),
+        HistoryCursor {
+            db_id: Some(db_id), ..
+        } => {
+            // Load the queries which were stored in the database after the
+            // cursor, then continue with the queries in memory which were
+            // stored after those, or have not been stored yet
+            let db_queries = load_new_queries_from_database(db, db_id, &params, env, limit + 1)?;
+            let last_db_id = db_queries
+                .last()
+                .and_then(|query| query.id)
+                .map(i64::from)
+                .unwrap_or(db_id);
+
+            history.extend(db_queries.into_iter().map(|query| {
+                let cursor = HistoryCursor {
+                    id: None,
+                    db_id: query.id.map(i64::from)
+                };
+
+                (query.into(), cursor)
+            }));
+
+            Box::new(
+                validator
+                    .queries(&queries)
+                    .filter(move |query| query.database_id == 0 || query.database_id > last_db_id)
+            )
+        }
+        _ => {
+            // The queries between the cursor and the oldest query in memory
+            // can not be found
+            gap = expired;
+
+            Box::new(validator.queries(&queries))
+        }
+    };
+
+    // Apply filters
+    let queries_iter = filter_queries(queries_iter, &params, env, ftl_memory, &lock)?;
+    let map_function = map_query_to_json(ftl_memory, &lock)?;
+
+    // Take one more than the limit to check if there are more new queries
+    history.extend(
+        queries_iter
+            .take(limit + 1)
+            .map(|query| (map_function(query), memory_cursor(query)))
+    );
+
+    let more = history.len() > limit;
+    history.truncate(limit);
+
+    // If there are no new queries, the client keeps using the same cursor
+    let next_since = history.last().map(|(_, cursor)| *cursor).unwrap_or(since);
+
+    reply_data(json!({
+        "history": history.into_iter().map(|(query, _)| query).collect::<Vec<JsonValue>>(),
+        "since": next_since.as_base64()?,
+        "more": more,
+        "expired": expired,
+        "gap": gap
+    }))
+}
+
+/// Get the cursor which points to the query in memory. The database ID is
+/// included if the query has been stored, so the cursor can still be used
+/// after the query is removed from memory.
+fn memory_cursor(query: &FtlQuery) -> HistoryCursor {
+    HistoryCursor {
+        id: Some(query.id),
+        db_id: if query.database_id != 0 {
+            Some(query.database_id)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        env::PiholeFile,
+        ftl::ShmLockGuard,
+        routes::stats::history::{
+            map_query_to_json::map_query_to_json,
+            testing::{test_memory, test_queries}
+        },
+        testing::TestBuilder
+    };
+    use rocket_contrib::json::JsonValue;
+
+    /// Get the JSON of the test queries with the given IDs
+    fn expected_history(ids: &[i32]) -> Vec<JsonValue> {
+        let ftl_memory = test_memory();
+        let map_function = map_query_to_json(&ftl_memory, &ShmLockGuard::Test).unwrap();
+
+        test_queries()
+            .iter()
+            .filter(|query| ids.contains(&query.id))
+            .map(map_function)
+            .collect()
+    }
+
+    /// The queries newer than the cursor are returned oldest first, without
+    /// private queries
+    #[test]
+    fn new_queries() {
+        // { "id": 6, "db_id": 100 }
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?since=eyJpZCI6NiwiZGJfaWQiOjEwMH0=")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_json(json!({
+                "history": expected_history(&[7, 8]),
+                "since": "eyJpZCI6OCwiZGJfaWQiOm51bGx9",
+                "more": false,
+                "expired": false,
+                "gap": false
+            }))
+            .test();
+    }
+
+    /// When there are more new queries than the limit, `more` is true and the
+    /// cursor points to the last query returned
+    #[test]
+    fn limit() {
+        // { "id": 1, "db_id": 95 }
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?since=eyJpZCI6MSwiZGJfaWQiOjk1fQ==&limit=2")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_json(json!({
+                "history": expected_history(&[2, 3]),
+                "since": "eyJpZCI6MywiZGJfaWQiOjk3fQ==",
+                "more": true,
+                "expired": false,
+                "gap": false
+            }))
+            .test();
+    }
+
+    /// When the cursor is no longer in memory and has no database ID, the
+    /// queries start from the oldest query in memory and the gap is reported
+    #[test]
+    fn expired_gap() {
+        // { "id": 20, "db_id": null }
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?since=eyJpZCI6MjAsImRiX2lkIjpudWxsfQ==&limit=1")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_json(json!({
+                "history": expected_history(&[1]),
+                "since": "eyJpZCI6MSwiZGJfaWQiOjk1fQ==",
+                "more": true,
+                "expired": true,
+                "gap": true
+            }))
+            .test();
+    }
+
+    /// When the cursor is no longer in memory, the database ID is used to
+    /// continue from the database and then the queries in memory
+    #[test]
+    fn expired_database() {
+        // { "id": 20, "db_id": 94 }
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?since=eyJpZCI6MjAsImRiX2lkIjo5NH0=&limit=2")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_json(json!({
+                "history": expected_history(&[1, 2]),
+                "since": "eyJpZCI6MiwiZGJfaWQiOjk2fQ==",
+                "more": true,
+                "expired": true,
+                "gap": false
+            }))
+            .test();
+    }
+
+    /// Maximum privacy shows no queries and keeps the same cursor
+    #[test]
+    fn privacy_max() {
+        // { "id": 6, "db_id": 100 }
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?since=eyJpZCI6NiwiZGJfaWQiOjEwMH0=")
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=3")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_json(json!({
+                "history": [],
+                "since": "eyJpZCI6NiwiZGJfaWQiOjEwMH0=",
+                "more": false,
+                "expired": false,
+                "gap": false
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,485 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// History Stream Functionality
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::{
+    endpoints::HistoryParams, get_history::filter_queries, map_query_to_json::map_query_to_json
+};
+use crate::{
+    env::Env,
+    ftl::{FtlMemory, Validator},
+    routes::auth::{AuthData, Principal, Scope},
+    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
+    util::{Error, ErrorKind}
+};
+use rocket_contrib::json::JsonValue;
+use std::{
+    cmp,
+    io::{self, Read},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant}
+};
+
+/// How often shared memory is checked for new queries
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the stream can go without sending anything before a comment is
+/// sent. Writing to a closed connection fails, which ends the stream.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long clients should wait before reconnecting, in milliseconds
+const RECONNECT_DELAY: u32 = 5000;
+
+/// How often the stream checks that its session or token is still valid
+const AUTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Limits how many query streams can be open at once. Each open stream keeps
+/// one of Rocket's workers busy, so without a limit a few streams would leave
+/// no workers for other requests.
+pub struct StreamLimit {
+    open: AtomicUsize,
+    max: usize
+}
+
+/// A place held by an open stream. It is given back when the stream is
+/// dropped.
+struct StreamSlot<'r>(&'r AtomicUsize);
+
+impl StreamLimit {
+    /// Allow up to `max` streams to be open at once
+    pub fn new(max: usize) -> StreamLimit {
+        StreamLimit {
+            open: AtomicUsize::new(0),
+            max
+        }
+    }
+
+    /// Take a place for a new stream. If the limit has been reached, a
+    /// `TooManyRequests` error is returned.
+    fn acquire(&self) -> Result<StreamSlot, Error> {
+        let mut open = self.open.load(Ordering::SeqCst);
+
+        loop {
+            if open >= self.max {
+                return Err(Error::from(ErrorKind::TooManyRequests(
+                    (RECONNECT_DELAY / 1000) as u64
+                )));
+            }
+
+            match self
+                .open
+                .compare_exchange(open, open + 1, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return Ok(StreamSlot(&self.open)),
+                Err(current) => open = current
+            }
+        }
+    }
+}
+
+impl<'r> Drop for StreamSlot<'r> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A stream of the queries FTL receives, as Server-Sent Events. Shared memory
+/// is checked for new queries every second, and each new query which passes
+/// the history filters is sent as an event, using the same format as the
+/// query history.
+///
+/// The stream ends when the session or token which opened it is revoked.
+pub struct QueryStream<'r> {
+    ftl_memory: &'r FtlMemory,
+    env: &'r Env,
+    auth_data: &'r AuthData,
+    principal: Principal,
+    _slot: StreamSlot<'r>,
+    params: HistoryParams,
+    /// The ID of the newest query which has been checked
+    last_id: Option<i32>,
+    /// The events which are waiting to be read
+    buffer: Vec<u8>,
+    position: usize,
+    /// If the next read should be empty to end the current chunk
+    end_chunk: bool,
+    /// If the principal is no longer authorized, so the stream has ended
+    ended: bool,
+    last_sent: Instant,
+    last_auth_check: Instant
+}
+
+impl<'r> QueryStream<'r> {
+    /// Create a stream of the queries received from now on for `principal`.
+    /// The queries which are already in shared memory are not sent. If too
+    /// many streams are open, a `TooManyRequests` error is returned.
+    pub fn new(
+        ftl_memory: &'r FtlMemory,
+        env: &'r Env,
+        auth_data: &'r AuthData,
+        limit: &'r StreamLimit,
+        principal: Principal,
+        params: HistoryParams
+    ) -> Result<QueryStream<'r>, Error> {
+        let mut stream = QueryStream {
+            ftl_memory,
+            env,
+            auth_data,
+            principal,
+            _slot: limit.acquire()?,
+            params,
+            last_id: None,
+            buffer: format!("retry: {}\n\n", RECONNECT_DELAY).into_bytes(),
+            position: 0,
+            end_chunk: false,
+            ended: false,
+            last_sent: Instant::now(),
+            last_auth_check: Instant::now()
+        };
+
+        // Find the newest query, which is where the stream starts
+        stream.poll()?;
+
+        Ok(stream)
+    }
+
+    /// Get the queries received since the last poll which pass the filters,
+    /// oldest first
+    fn poll(&mut self) -> Result<Vec<JsonValue>, Error> {
+        let ftl_memory = self.ftl_memory;
+        let lock = ftl_memory.lock()?;
+        let validator = Validator::new(ftl_memory, &lock)?;
+        let queries = ftl_memory.queries(&lock)?;
+
+        let newest_id = match validator.queries(&queries).next_back() {
+            Some(query) => query.id,
+            None => return Ok(Vec::new())
+        };
+
+        // The first poll only finds where the stream starts
+        let last_id = match self.last_id.replace(newest_id) {
+            Some(last_id) => last_id,
+            None => return Ok(Vec::new())
+        };
+
+        // Check if query details are private
+        if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(self.env)?
+            >= FtlPrivacyLevel::Maximum
+        {
+            return Ok(Vec::new());
+        }
+
+        let queries_iter = Box::new(
+            validator
+                .queries(&queries)
+                .rev()
+                .take_while(move |query| query.id > last_id)
+        );
+        let queries_iter = filter_queries(queries_iter, &self.params, self.env, ftl_memory, &lock)?;
+
+        let mut events: Vec<JsonValue> = queries_iter
+            .map(map_query_to_json(ftl_memory, &lock)?)
+            .collect();
+        events.reverse();
+
+        Ok(events)
+    }
+
+    /// Wait until there are new events to send, or until a keep alive comment
+    /// needs to be sent, and put them in the buffer. If the principal is no
+    /// longer authorized, `false` is returned and nothing is put in the buffer.
+    fn fill_buffer(&mut self) -> Result<bool, Error> {
+        loop {
+            if self.last_auth_check.elapsed() >= AUTH_CHECK_INTERVAL {
+                if !self
+                    .principal
+                    .is_authorized(self.auth_data, self.env, Scope::StatsRead)?
+                {
+                    return Ok(false);
+                }
+
+                self.last_auth_check = Instant::now();
+            }
+
+            let events = self.poll()?;
+
+            if !events.is_empty() {
+                self.buffer = events
+                    .iter()
+                    .map(|event| format!("data: {}\n\n", event.0))
+                    .collect::<String>()
+                    .into_bytes();
+                break;
+            }
+
+            if self.last_sent.elapsed() >= KEEP_ALIVE_INTERVAL {
+                self.buffer = b": keep-alive\n\n".to_vec();
+                break;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        self.position = 0;
+        self.last_sent = Instant::now();
+
+        Ok(true)
+    }
+}
+
+impl<'r> Read for QueryStream<'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.buffer.len() {
+            // Rocket keeps reading until its buffer is full before it sends a
+            // chunk, so an empty read is used to send the events right away
+            if self.end_chunk || self.ended {
+                self.end_chunk = false;
+                return Ok(0);
+            }
+
+            // An empty read at the start of a chunk ends the response
+            self.ended = !self
+                .fill_buffer()
+                .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+
+            if self.ended {
+                return Ok(0);
+            }
+        }
+
+        let remaining = &self.buffer[self.position..];
+        let len = cmp::min(buf.len(), remaining.len());
+
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+
+        // When the events fill Rocket's buffer, the chunk is sent without
+        // another read
+        self.end_chunk = self.position == self.buffer.len() && len < buf.len();
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{QueryStream, StreamLimit, AUTH_CHECK_INTERVAL};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        ftl::{FtlMemory, FtlQueryType, ShmLockGuard},
+        routes::{
+            auth::{AuthData, Principal, SessionStore},
+            stats::history::{
+                endpoints::HistoryParams,
+                map_query_to_json::map_query_to_json,
+                testing::{test_memory, test_queries}
+            }
+        },
+        testing::TestEnvBuilder,
+        util::ErrorKind
+    };
+    use rocket_contrib::json::JsonValue;
+    use std::{io::Read, time::Instant};
+
+    /// Create auth data with one session, which has the ID 1
+    fn auth_data() -> AuthData {
+        let auth_data = AuthData::new(String::new(), false, SessionStore::new(60, 600));
+        auth_data.sessions().create(String::new(), None, None);
+
+        auth_data
+    }
+
+    /// Open a stream for the session with the ID 1
+    fn stream<'r>(
+        ftl_memory: &'r FtlMemory,
+        env: &'r Env,
+        auth_data: &'r AuthData,
+        limit: &'r StreamLimit,
+        params: HistoryParams
+    ) -> QueryStream<'r> {
+        QueryStream::new(
+            ftl_memory,
+            env,
+            auth_data,
+            limit,
+            Principal::User(1),
+            params
+        )
+        .unwrap()
+    }
+
+    /// Get the JSON of the test queries with the given IDs
+    fn expected_events(ids: &[i32]) -> Vec<JsonValue> {
+        let ftl_memory = test_memory();
+        let map_function = map_query_to_json(&ftl_memory, &ShmLockGuard::Test).unwrap();
+
+        test_queries()
+            .iter()
+            .filter(|query| ids.contains(&query.id))
+            .map(map_function)
+            .collect()
+    }
+
+    /// The queries which are in shared memory when the stream starts are not
+    /// sent
+    #[test]
+    fn existing_queries() {
+        let ftl_memory = test_memory();
+        let env = Env::Test(Config::default(), TestEnvBuilder::new().build());
+        let (auth_data, limit) = (auth_data(), StreamLimit::new(1));
+        let mut stream = stream(
+            &ftl_memory,
+            &env,
+            &auth_data,
+            &limit,
+            HistoryParams::default()
+        );
+
+        assert_eq!(stream.poll().unwrap(), Vec::<JsonValue>::new());
+    }
+
+    /// New queries are sent oldest first, without private queries
+    #[test]
+    fn new_queries() {
+        let ftl_memory = test_memory();
+        let env = Env::Test(Config::default(), TestEnvBuilder::new().build());
+        let (auth_data, limit) = (auth_data(), StreamLimit::new(1));
+        let mut stream = stream(
+            &ftl_memory,
+            &env,
+            &auth_data,
+            &limit,
+            HistoryParams::default()
+        );
+        stream.last_id = Some(6);
+
+        assert_eq!(stream.poll().unwrap(), expected_events(&[7, 8]));
+        assert_eq!(stream.poll().unwrap(), Vec::<JsonValue>::new());
+    }
+
+    /// The history filters are applied to new queries
+    #[test]
+    fn filters() {
+        let ftl_memory = test_memory();
+        let env = Env::Test(Config::default(), TestEnvBuilder::new().build());
+        let params = HistoryParams {
+            query_type: Some(FtlQueryType::AAAA),
+            ..HistoryParams::default()
+        };
+        let (auth_data, limit) = (auth_data(), StreamLimit::new(1));
+        let mut stream = stream(&ftl_memory, &env, &auth_data, &limit, params);
+        stream.last_id = Some(0);
+
+        assert_eq!(stream.poll().unwrap(), expected_events(&[2, 5, 6, 8]));
+    }
+
+    /// No queries are sent if the privacy level hides query details
+    #[test]
+    fn privacy_level() {
+        let ftl_memory = test_memory();
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=3")
+                .build()
+        );
+        let (auth_data, limit) = (auth_data(), StreamLimit::new(1));
+        let mut stream = stream(
+            &ftl_memory,
+            &env,
+            &auth_data,
+            &limit,
+            HistoryParams::default()
+        );
+        stream.last_id = Some(0);
+
+        assert_eq!(stream.poll().unwrap(), Vec::<JsonValue>::new());
+    }
+
+    /// Events are formatted as Server-Sent Events, and each batch of events
+    /// ends with an empty read so it is sent right away
+    #[test]
+    fn read_events() {
+        let ftl_memory = test_memory();
+        let env = Env::Test(Config::default(), TestEnvBuilder::new().build());
+        let (auth_data, limit) = (auth_data(), StreamLimit::new(1));
+        let mut stream = stream(
+            &ftl_memory,
+            &env,
+            &auth_data,
+            &limit,
+            HistoryParams::default()
+        );
+        let mut buffer = [0; 1024];
+
+        let len = stream.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"retry: 5000\n\n");
+        assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+
+        stream.last_id = Some(7);
+        let expected = format!("data: {}\n\n", expected_events(&[8])[0].0);
+        let len = stream.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], expected.as_bytes());
+        assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+    }
+
+    /// Only a limited number of streams can be open at once, and closing a
+    /// stream makes room for another
+    #[test]
+    fn stream_limit() {
+        let ftl_memory = test_memory();
+        let env = Env::Test(Config::default(), TestEnvBuilder::new().build());
+        let (auth_data, limit) = (auth_data(), StreamLimit::new(1));
+        let open = || {
+            QueryStream::new(
+                &ftl_memory,
+                &env,
+                &auth_data,
+                &limit,
+                Principal::User(1),
+                HistoryParams::default()
+            )
+        };
+
+        let first = open().unwrap();
+        assert_eq!(
+            open().map(|_| ()).map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyRequests(5))
+        );
+
+        drop(first);
+        assert!(open().is_ok());
+    }
+
+    /// The stream ends once its session is revoked
+    #[test]
+    fn revoked_session() {
+        let ftl_memory = test_memory();
+        let env = Env::Test(Config::default(), TestEnvBuilder::new().build());
+        let (auth_data, limit) = (auth_data(), StreamLimit::new(1));
+        let mut stream = stream(
+            &ftl_memory,
+            &env,
+            &auth_data,
+            &limit,
+            HistoryParams::default()
+        );
+        let mut buffer = [0; 1024];
+
+        stream.read(&mut buffer).unwrap();
+        stream.read(&mut buffer).unwrap();
+
+        auth_data.sessions().revoke(1);
+        stream.last_auth_check = Instant::now() - AUTH_CHECK_INTERVAL;
+
+        assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+        assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+    }
+}
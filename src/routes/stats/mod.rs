@@ -8,9 +8,9 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+mod client_details;
 mod clients;
 mod common;
-mod history;
 mod over_time_clients;
 mod over_time_history;
 mod query_types;
@@ -21,8 +21,9 @@ mod top_domains;
 mod upstreams;
 
 pub mod database;
+pub mod history;
 
 pub use self::{
-    clients::*, history::*, over_time_clients::*, over_time_history::*, query_types::*,
-    recent_blocked::*, summary::*, top_clients::*, top_domains::*, upstreams::*
+    client_details::*, clients::*, history::*, over_time_clients::*, over_time_history::*,
+    query_types::*, recent_blocked::*, summary::*, top_clients::*, top_domains::*, upstreams::*
 };
@@ -10,9 +10,9 @@
 
 use crate::{
     env::Env,
-    ftl::{ClientReply, FtlMemory},
+    ftl::{ClientReply, FtlMemory, Validator},
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::{
             clients::{filter_ftl_clients, ClientParams},
             common::get_current_over_time_slot
@@ -26,7 +26,11 @@ use std::cmp::Ordering;
 
 /// Get the client queries over time
 #[get("/stats/overTime/clients")]
-pub fn over_time_clients(_auth: User, ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
+pub fn over_time_clients(
+    _auth: Scoped<StatsRead>,
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>
+) -> Reply {
     // Check if client details are private
     if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)?
         >= FtlPrivacyLevel::HideDomainsAndClients
@@ -39,6 +43,7 @@ pub fn over_time_clients(_auth: User, ftl_memory: State<FtlMemory>, env: State<E
 
     // Load FTL shared memory
     let lock = ftl_memory.lock()?;
+    let validator = Validator::new(&ftl_memory, &lock)?;
     let strings = ftl_memory.strings(&lock)?;
     let over_time = ftl_memory.over_time(&lock)?;
     let ftl_clients = ftl_memory.clients(&lock)?;
@@ -47,6 +52,7 @@ pub fn over_time_clients(_auth: User, ftl_memory: State<FtlMemory>, env: State<E
     let clients = filter_ftl_clients(
         &ftl_memory,
         &lock,
+        &validator,
         &ftl_clients,
         &env,
         ClientParams::default()
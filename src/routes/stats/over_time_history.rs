@@ -11,13 +11,18 @@
 use crate::{
     ftl::FtlMemory,
     routes::stats::common::get_current_over_time_slot,
-    util::{reply_data, Reply}
+    util::{reply_result, Error, Reply}
 };
 use rocket::State;
 
 /// Get the query history over time (separated into blocked and not blocked)
 #[get("/stats/overTime/history")]
 pub fn over_time_history(ftl_memory: State<FtlMemory>) -> Reply {
+    reply_result(load_over_time_history(&ftl_memory))
+}
+
+/// Load the query history over time from shared memory
+pub fn load_over_time_history(ftl_memory: &FtlMemory) -> Result<Vec<OverTimeItem>, Error> {
     let lock = ftl_memory.lock()?;
     let over_time = ftl_memory.over_time(&lock)?;
 
@@ -37,7 +42,7 @@ pub fn over_time_history(ftl_memory: State<FtlMemory>) -> Reply {
         })
         .collect();
 
-    reply_data(over_time_data)
+    Ok(over_time_data)
 }
 
 #[derive(Serialize)]
@@ -10,14 +10,14 @@
 
 use crate::{
     ftl::{FtlMemory, FtlQueryType},
-    routes::auth::User,
+    routes::auth::{scopes::StatsRead, Scoped},
     util::{reply_result, Error, Reply}
 };
 use rocket::State;
 
 /// Get the query types
 #[get("/stats/query_types")]
-pub fn query_types(_auth: User, ftl_memory: State<FtlMemory>) -> Reply {
+pub fn query_types(_auth: Scoped<StatsRead>, ftl_memory: State<FtlMemory>) -> Reply {
     reply_result(query_types_impl(&ftl_memory))
 }
 
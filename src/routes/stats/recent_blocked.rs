@@ -10,8 +10,8 @@
 
 use crate::{
     env::Env,
-    ftl::FtlMemory,
-    routes::auth::User,
+    ftl::{FtlMemory, Validator},
+    routes::auth::{scopes::StatsRead, Scoped},
     settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
     util::{reply_data, Reply}
 };
@@ -20,7 +20,7 @@ use rocket::{request::Form, State};
 /// Get the `num` most recently blocked domains
 #[get("/stats/recent_blocked?<params..>")]
 pub fn recent_blocked(
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
     params: Form<RecentBlockedParams>
@@ -43,17 +43,16 @@ pub fn get_recent_blocked(ftl_memory: &FtlMemory, env: &Env, num: usize) -> Repl
     }
 
     let lock = ftl_memory.lock()?;
-    let counters = ftl_memory.counters(&lock)?;
+    let validator = Validator::new(ftl_memory, &lock)?;
     let queries = ftl_memory.queries(&lock)?;
     let strings = ftl_memory.strings(&lock)?;
     let domains = ftl_memory.domains(&lock)?;
 
-    let recent_blocked: Vec<&str> = queries
-        .iter()
+    let recent_blocked: Vec<&str> = validator
+        // Skip the uninitialized and invalid queries
+        .queries(&queries)
         // Get the most recent queries first
         .rev()
-        // Skip the uninitialized queries
-        .skip(queries.len() - counters.total_queries as usize)
         // Only get blocked queries
         .filter(|query| query.is_blocked())
         // Get up to num queries
@@ -10,15 +10,20 @@
 
 use crate::{
     env::Env,
-    ftl::{FtlMemory, FtlQueryType},
+    ftl::{FtlClient, FtlMemory, FtlQueryType, Validator},
     settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel, SetupVarsEntry},
-    util::{reply_data, Reply}
+    util::{reply_result, Error, Reply}
 };
 use rocket::State;
 
 /// Get the summary data
 #[get("/stats/summary")]
 pub fn get_summary(ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
+    reply_result(load_summary(&ftl_memory, &env))
+}
+
+/// Load the summary data from shared memory
+pub fn load_summary(ftl_memory: &FtlMemory, env: &Env) -> Result<Summary, Error> {
     let lock = ftl_memory.lock()?;
     let counters = ftl_memory.counters(&lock)?;
 
@@ -29,40 +34,38 @@ pub fn get_summary(ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
     };
 
     let (total_clients, active_clients) = {
-        if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)?
+        if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(env)?
             >= FtlPrivacyLevel::HideDomainsAndClients
         {
             // If clients are supposed to be hidden, pretend there are no clients
             (0, 0)
         } else {
             // Only show active clients, and ignore hidden clients
+            let validator = Validator::new(ftl_memory, &lock)?;
             let clients = ftl_memory.clients(&lock)?;
             let strings = ftl_memory.strings(&lock)?;
 
-            let hidden_client_count = clients
-                .iter()
-                .filter(|client| client.get_ip(&strings) == "0.0.0.0")
-                .count();
+            let clients: Vec<&FtlClient> = validator
+                .clients(&clients)
+                .filter(|client| client.get_ip(&strings) != "0.0.0.0")
+                .collect();
 
             let active_client_count = clients
                 .iter()
-                .filter(|client| client.query_count > 0 && client.get_ip(&strings) != "0.0.0.0")
+                .filter(|client| client.query_count > 0)
                 .count();
 
-            (
-                counters.total_clients as usize - hidden_client_count,
-                active_client_count
-            )
+            (clients.len(), active_client_count)
         }
     };
 
-    let status = if SetupVarsEntry::BlockingEnabled.is_true(&env)? {
+    let status = if SetupVarsEntry::BlockingEnabled.is_true(env)? {
         "enabled"
     } else {
         "disabled"
     };
 
-    reply_data(Summary {
+    Ok(Summary {
         gravity_size: counters.gravity_size as usize,
         total_queries: TotalQueries {
             A: counters.query_type(FtlQueryType::A),
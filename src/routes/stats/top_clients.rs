@@ -10,9 +10,9 @@
 
 use crate::{
     env::Env,
-    ftl::{FtlClient, FtlMemory},
+    ftl::{FtlClient, FtlMemory, Validator},
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::common::{remove_excluded_clients, remove_hidden_clients}
     },
     settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
@@ -23,7 +23,7 @@ use rocket::{request::Form, State};
 /// Get the top clients
 #[get("/stats/top_clients?<params..>")]
 pub fn top_clients(
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
     params: Form<TopClientParams>
@@ -61,7 +61,7 @@ pub struct TopClientItemReply {
 }
 
 /// Get the top clients according to the parameters
-fn get_top_clients(
+pub fn get_top_clients(
     ftl_memory: &FtlMemory,
     env: &Env,
     params: TopClientParams
@@ -88,14 +88,12 @@ fn get_top_clients(
         return Ok(reply);
     }
 
+    let validator = Validator::new(ftl_memory, &lock)?;
     let strings = ftl_memory.strings(&lock)?;
     let clients = ftl_memory.clients(&lock)?;
 
     // Get an array of valid client references (FTL allocates more than it uses)
-    let mut clients: Vec<&FtlClient> = clients
-        .iter()
-        .take(counters.total_clients as usize)
-        .collect();
+    let mut clients: Vec<&FtlClient> = validator.clients(&clients).collect();
 
     // Ignore inactive clients by default (retain active clients)
     if !inactive {
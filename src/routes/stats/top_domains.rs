@@ -10,9 +10,9 @@
 
 use crate::{
     env::{Env, PiholeFile},
-    ftl::{FtlDomain, FtlMemory},
+    ftl::{FtlDomain, FtlMemory, Validator},
     routes::{
-        auth::User,
+        auth::{scopes::StatsRead, Scoped},
         stats::common::{remove_excluded_domains, remove_hidden_domains}
     },
     settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel, SetupVarsEntry},
@@ -23,7 +23,7 @@ use rocket::{request::Form, State};
 /// Return the top domains
 #[get("/stats/top_domains?<params..>")]
 pub fn top_domains(
-    _auth: User,
+    _auth: Scoped<StatsRead>,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
     params: Form<TopDomainParams>
@@ -60,7 +60,7 @@ pub struct TopDomainItemReply {
 }
 
 /// Get the top domains (blocked or not)
-fn get_top_domains(
+pub fn get_top_domains(
     ftl_memory: &FtlMemory,
     env: &Env,
     params: TopDomainParams
@@ -94,14 +94,12 @@ fn get_top_domains(
         return Ok(reply);
     }
 
+    let validator = Validator::new(ftl_memory, &lock)?;
     let domains = ftl_memory.domains(&lock)?;
     let strings = ftl_memory.strings(&lock)?;
 
     // Get an array of valid domain references (FTL allocates more than it uses)
-    let mut domains: Vec<&FtlDomain> = domains
-        .iter()
-        .take(counters.total_domains as usize)
-        .collect();
+    let mut domains: Vec<&FtlDomain> = validator.domains(&domains).collect();
 
     // Remove excluded and hidden domains
     remove_excluded_domains(&mut domains, env, &strings)?;
@@ -9,24 +9,24 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    ftl::{FtlMemory, FtlUpstream},
-    routes::auth::User,
+    ftl::{FtlMemory, FtlUpstream, Validator},
+    routes::auth::{scopes::StatsRead, Scoped},
     util::{reply_data, Reply}
 };
 use rocket::State;
 
 /// Get the upstreams
 #[get("/stats/upstreams")]
-pub fn upstreams(_auth: User, ftl_memory: State<FtlMemory>) -> Reply {
+pub fn upstreams(_auth: Scoped<StatsRead>, ftl_memory: State<FtlMemory>) -> Reply {
     let lock = ftl_memory.lock()?;
     let ftl_upstreams = ftl_memory.upstreams(&lock)?;
     let strings = ftl_memory.strings(&lock)?;
     let counters = ftl_memory.counters(&lock)?;
+    let validator = Validator::new(&ftl_memory, &lock)?;
 
     // Get an array of valid upstream references (FTL allocates more than it uses)
-    let mut ftl_upstreams: Vec<&FtlUpstream> = ftl_upstreams
-        .iter()
-        .take(counters.total_upstreams as usize)
+    let mut ftl_upstreams: Vec<&FtlUpstream> = validator
+        .upstreams(&ftl_upstreams)
         // Remove upstreams with a zero count
         .filter(|upstream| upstream.query_count > 0)
         .collect();
@@ -43,20 +43,27 @@ fn read_api_version() -> Version {
     }
 }
 
+ftl_response! {
+    /// FTL's version information, as sent by the `version` command
+    struct FtlVersion {
+        _version: String,
+        tag: String,
+        branch: String,
+        hash: String,
+        _date: String
+    }
+}
+
 /// Read FTL version information from FTL's API
 fn read_ftl_version(ftl: &FtlConnectionType) -> Result<Version, Error> {
-    let mut con = ftl.connect("version")?;
-    let mut str_buffer = [0u8; 4096];
+    let version: FtlVersion = ftl.connect("version")?.read_response()?;
 
     // Ignore the version and date strings
-    let _hash_tag = con.read_str(&mut str_buffer)?.to_owned();
-    let tag = con.read_str(&mut str_buffer)?.to_owned();
-    let branch = con.read_str(&mut str_buffer)?.to_owned();
-    let hash = con.read_str(&mut str_buffer)?.to_owned();
-    let _date = con.read_str(&mut str_buffer)?.to_owned();
-    con.expect_eom()?;
-
-    Ok(Version { tag, branch, hash })
+    Ok(Version {
+        tag: version.tag,
+        branch: version.branch,
+        hash: version.hash
+    })
 }
 
 /// Read Web version information from the `VERSION` file in the web assets.
@@ -8,9 +8,11 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+use crate::env::Env;
 use rocket::{
     http::ContentType,
-    response::{Redirect, Response}
+    response::{Redirect, Response},
+    State
 };
 use std::{borrow::Cow, io::Cursor, path::PathBuf};
 
@@ -54,10 +56,15 @@ fn build_response<'r>(data: Cow<'static, [u8]>, content_type: ContentType) -> Re
 }
 
 /// Redirect root requests to the web interface. This allows http://pi.hole to
-/// redirect to http://pi.hole/admin
+/// redirect to http://pi.hole/admin. The URL prefix is included because
+/// `uri!` does not know where the route is mounted.
 #[get("/")]
-pub fn web_interface_redirect() -> Redirect {
-    Redirect::to(uri!(web_interface_index))
+pub fn web_interface_redirect(env: State<Env>) -> Redirect {
+    Redirect::to(format!(
+        "{}{}",
+        env.config().url_prefix(),
+        uri!(web_interface_index)
+    ))
 }
 
 /// Return the index page of the web interface
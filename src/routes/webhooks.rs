@@ -0,0 +1,201 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Webhook Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply},
+    webhooks::{Delivery, EventKind, Webhook, Webhooks}
+};
+use rocket::{request::Form, State};
+use rocket_contrib::json::Json;
+
+/// The public information about a webhook, used in API responses
+#[derive(Serialize)]
+pub struct WebhookReply<'a> {
+    id: &'a str,
+    url: &'a str,
+    events: &'a [EventKind],
+    created: u64
+}
+
+impl<'a> From<&'a Webhook> for WebhookReply<'a> {
+    fn from(webhook: &'a Webhook) -> Self {
+        WebhookReply {
+            id: &webhook.id,
+            url: &webhook.url,
+            events: &webhook.events,
+            created: webhook.created
+        }
+    }
+}
+
+/// Represents the API input for registering a webhook
+#[derive(Deserialize)]
+pub struct NewWebhook {
+    url: String,
+    events: Vec<EventKind>
+}
+
+/// Represents the possible GET parameters on `/webhooks/deliveries`
+#[derive(FromForm)]
+pub struct DeliveryParams {
+    webhook: Option<String>
+}
+
+/// Get the registered webhooks. The secrets are not included.
+#[get("/webhooks")]
+pub fn get_webhooks(_auth: User, env: State<Env>, webhooks: State<Webhooks>) -> Reply {
+    let registered = webhooks.registered(&env)?;
+    let replies: Vec<WebhookReply> = registered.iter().map(WebhookReply::from).collect();
+
+    reply_data(replies)
+}
+
+/// Register a webhook for the events. Only `http://` URLs are supported. The
+/// secret used to sign deliveries is only shown in this response.
+#[post("/webhooks", data = "<data>")]
+pub fn create_webhook(
+    _auth: User,
+    env: State<Env>,
+    webhooks: State<Webhooks>,
+    data: Json<NewWebhook>
+) -> Reply {
+    let data = data.into_inner();
+
+    if !data.url.starts_with("http://") || data.events.is_empty() {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    let mut registered = webhooks.registered(&env)?;
+    let webhook = Webhook::generate(data.url, data.events)?;
+
+    // The ID is short, so make sure it is unique
+    if registered.iter().any(|existing| existing.id == webhook.id) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    registered.push(webhook.clone());
+    webhooks.save(&env, registered)?;
+
+    reply_data(json!({
+        "id": webhook.id,
+        "url": webhook.url,
+        "events": webhook.events,
+        "created": webhook.created,
+        "secret": webhook.secret
+    }))
+}
+
+/// Remove a webhook
+#[delete("/webhooks/<id>")]
+pub fn delete_webhook(
+    _auth: User,
+    env: State<Env>,
+    webhooks: State<Webhooks>,
+    id: String
+) -> Reply {
+    let mut registered = webhooks.registered(&env)?;
+    let count = registered.len();
+
+    registered.retain(|webhook| webhook.id != id);
+
+    if registered.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    webhooks.save(&env, registered)?;
+    reply_success()
+}
+
+/// Get the recent deliveries, newest first, optionally only for one webhook.
+/// The log is kept in memory, so it starts empty when the API starts.
+#[get("/webhooks/deliveries?<params..>")]
+pub fn get_deliveries(
+    _auth: User,
+    webhooks: State<Webhooks>,
+    params: Form<DeliveryParams>
+) -> Reply {
+    let deliveries: Vec<Delivery> = webhooks
+        .deliveries()
+        .into_iter()
+        .filter(|delivery| {
+            params
+                .webhook
+                .as_ref()
+                .map_or(true, |id| &delivery.webhook_id == id)
+        })
+        .collect();
+
+    reply_data(deliveries)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+    use serde_json::Value;
+
+    /// A webhook file with one webhook
+    const WEBHOOKS: &str = r#"[{"id":"0a1b2c3d","url":"http://127.0.0.1:8000/hook","events":["blocking.disabled"],"secret":"secret","created":1550000000}]"#;
+
+    /// The webhook list does not include the secrets
+    #[test]
+    fn list_webhooks() {
+        TestBuilder::new()
+            .endpoint("/admin/api/webhooks")
+            .file(PiholeFile::ApiWebhooks, WEBHOOKS)
+            .expect_json(json!([{
+                "id": "0a1b2c3d",
+                "url": "http://127.0.0.1:8000/hook",
+                "events": ["blocking.disabled"],
+                "created": 1_550_000_000
+            }]))
+            .test();
+    }
+
+    /// Webhooks need an HTTP URL and at least one event
+    #[test]
+    fn create_invalid() {
+        TestBuilder::new()
+            .endpoint("/admin/api/webhooks")
+            .method(Method::Post)
+            .body(json!({ "url": "ftp://example.com", "events": ["client.new"] }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// Deleting a webhook removes it from the file
+    #[test]
+    fn delete_webhook() {
+        TestBuilder::new()
+            .endpoint("/admin/api/webhooks/0a1b2c3d")
+            .method(Method::Delete)
+            .file_expect(PiholeFile::ApiWebhooks, WEBHOOKS, "[]")
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// The delivery log starts empty
+    #[test]
+    fn no_deliveries() {
+        TestBuilder::new()
+            .endpoint("/admin/api/webhooks/deliveries")
+            .expect_json(json!([]))
+            .test();
+    }
+}
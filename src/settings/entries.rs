@@ -34,10 +34,14 @@ pub trait ConfigEntry {
     /// Get the default value of the entry
     fn get_default(&self) -> &str;
 
-    /// Check if the value is valid for this entry. An empty string is always
-    /// valid because it represents a deleted entry.
+    /// Check if the value is valid for this entry. An empty string is valid
+    /// because it represents a deleted entry, except for the web password,
+    /// which can only be changed through the password endpoint.
     fn is_valid(&self, value: &str) -> bool {
-        value.is_empty() || self.value_type().is_valid(value)
+        match self.value_type() {
+            ValueType::WebPassword => false,
+            value_type => value.is_empty() || value_type.is_valid(value)
+        }
     }
 
     /// Try to read the value and parse into a boolean.
@@ -111,6 +115,13 @@ pub trait ConfigEntry {
             return Err(Error::from(ErrorKind::InvalidSettingValue));
         }
 
+        self.write_unchecked(value, env)
+    }
+
+    /// Write a value to the config file without validating it. This is only
+    /// for values which can not be validated as settings, such as the web
+    /// password hash written by the password endpoint.
+    fn write_unchecked(&self, value: &str, env: &Env) -> Result<(), Error> {
         // Read specified file, removing any line matching the setting we are writing
         let key = self.key();
         let entry_equals = format!("{}=", key);
@@ -462,6 +473,25 @@ mod tests {
         test_file.assert_expected(&mut buffer);
     }
 
+    /// The web password can not be written or deleted as a setting
+    #[test]
+    fn write_web_password() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::SetupVars,
+            "WEBPASSWORD=abc\n",
+            "WEBPASSWORD=abc\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+        let hash = "43c1a7871221fb579dfd36c1dc7f59d29ca8327cb108ad97cd6cd5e51d79ae9b";
+
+        assert!(SetupVarsEntry::WebPassword.write(hash, &env).is_err());
+        assert!(SetupVarsEntry::WebPassword.delete(&env).is_err());
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+
     #[test]
     fn write_over_duplicate_keys() {
         let env_builder = TestEnvBuilder::new().file_expect(
@@ -311,6 +311,11 @@ mod tests {
             (ValueType::PortNumber, "65536", false),
             (ValueType::YesNo, "true", false),
             (ValueType::String(&["boxed", ""]), "lan", false),
+            (
+                ValueType::WebPassword,
+                "43c1a7871221fb579dfd36c1dc7f59d29ca8327cb108ad97cd6cd5e51d79ae9b",
+                false
+            ),
         ];
 
         for (setting, value, result) in tests {
@@ -9,18 +9,28 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    access::{self, AccessControl},
+    cors::CorsControl,
     databases::{ftl::FtlDatabase, load_databases},
     env::{Config, Env},
-    ftl::{FtlConnectionType, FtlMemory},
+    ftl::{FtlConnectionType, FtlMemory, FtlSimulator, FtlSnapshot, FtlSocket},
+    live::{LiveHub, TicketStore},
+    proxy::TrustedProxies,
     routes::{
-        auth::{self, AuthData},
-        dns, settings, stats, version, web
+        actions, audit,
+        auth::{self, AuthData, RetryAfter, SessionStore},
+        dns, live, memory, settings, snapshot, stats, version, web, webhooks
     },
     settings::{ConfigEntry, SetupVarsEntry},
-    util::{Error, ErrorKind}
+    tls::{self, ReloadableTls},
+    util::{Error, ErrorKind},
+    webhooks::{Watcher, Webhooks}
 };
-use rocket::config::{ConfigBuilder, Environment};
-use rocket_cors::Cors;
+use rocket::{
+    config::{ConfigBuilder, Environment},
+    Request
+};
+use std::{cmp, sync::Arc};
 
 #[cfg(test)]
 use crate::{databases::load_test_databases, env::PiholeFile};
@@ -33,6 +43,9 @@ use tempfile::NamedTempFile;
 
 const CONFIG_LOCATION: &str = "/etc/pihole/API.toml";
 
+/// The command line switch which enables the FTL simulator
+const SIMULATE_SWITCH: &str = "--simulate";
+
 #[catch(404)]
 fn not_found() -> Error {
     Error::from(ErrorKind::NotFound)
@@ -43,33 +56,110 @@ fn unauthorized() -> Error {
     Error::from(ErrorKind::Unauthorized)
 }
 
-/// Run the API normally (connect to FTL over the socket)
+#[catch(403)]
+fn forbidden() -> Error {
+    Error::from(ErrorKind::Forbidden)
+}
+
+#[catch(429)]
+fn too_many_requests(request: &Request) -> Error {
+    let RetryAfter(retry_after) = *request.local_cache(|| RetryAfter(0));
+    Error::from(ErrorKind::TooManyRequests(retry_after))
+}
+
+/// Run the API normally (connect to FTL over the socket). If the simulator is
+/// enabled in the config or with the `--simulate` switch, FTL is simulated
+/// instead.
 pub fn start() -> Result<(), Error> {
     let config = Config::parse(CONFIG_LOCATION)?;
     let env = Env::Production(config);
     let key = SetupVarsEntry::WebPassword.read(&env)?;
+    let simulate =
+        env.config().simulator_enabled() || std::env::args().any(|arg| arg == SIMULATE_SWITCH);
 
-    setup(
-        rocket::custom(
-            ConfigBuilder::new(Environment::Production)
-                .address(env.config().address())
-                .port(env.config().port() as u16)
-                .log_level(env.config().log_level()?)
-                .extra("databases", load_databases(&env)?)
-                .finalize()
-                .unwrap()
-        ),
-        FtlConnectionType::Socket,
-        FtlMemory::production(),
+    // The simulated socket and shared memory use the same data
+    let simulator = if simulate {
+        Some(Arc::new(FtlSimulator::new(
+            env.config().simulator_options()
+        )))
+    } else {
+        None
+    };
+
+    let ftl_socket = match simulator {
+        Some(ref simulator) => FtlConnectionType::Simulator(simulator.clone()),
+        None => FtlConnectionType::Socket(FtlSocket::new(env.config().ftl_socket_options()))
+    };
+    let ftl_memory = load_ftl_memory(&env, &simulator)?;
+
+    // Push dashboard updates to WebSocket clients if enabled. The live update
+    // hub runs outside of Rocket, so it gets a clone of the shared memory
+    // handle.
+    let tickets = TicketStore::default();
+    if env.config().live_enabled() {
+        let hub = LiveHub::new(ftl_memory.clone(), Env::Production(env.config().clone()));
+        crate::live::start(env.config(), Arc::new(hub), tickets.clone());
+    }
+
+    // Watch for the webhook events which do not come from requests
+    let webhooks = Webhooks::default();
+    Watcher::new(
+        ftl_memory.clone(),
+        Env::Production(env.config().clone()),
+        webhooks.clone()
+    )
+    .start();
+
+    let rocket_config = ConfigBuilder::new(Environment::Production)
+        .address(env.config().address())
+        .port(env.config().port() as u16)
+        .log_level(env.config().log_level()?)
+        .extra("databases", load_databases(&env)?);
+
+    // Serve over HTTPS if a certificate is configured
+    let tls = match tls::prepare(env.config())? {
+        Some((certificate, private_key)) => Some(ReloadableTls::load(certificate, private_key)?),
+        None => None
+    };
+    let reload_interval = env.config().tls_reload_interval();
+
+    let server = setup(
+        rocket::custom(rocket_config.finalize().unwrap()),
+        ftl_socket,
+        ftl_memory,
         env,
         key,
-        true
-    )
-    .launch();
+        tickets,
+        webhooks,
+        // The simulator has no FTL database
+        !simulate
+    );
+
+    match tls {
+        Some(tls) => tls::launch(server, tls, reload_interval)?,
+        None => {
+            server.launch();
+        }
+    }
 
     Ok(())
 }
 
+/// Get the shared memory to read from. This is the simulator's memory if it
+/// is running, otherwise FTL's shared memory or the configured snapshot of it.
+fn load_ftl_memory(env: &Env, simulator: &Option<Arc<FtlSimulator>>) -> Result<FtlMemory, Error> {
+    if let Some(simulator) = simulator {
+        return Ok(FtlMemory::Simulator(simulator.clone()));
+    }
+
+    // Use a shared memory snapshot instead of FTL's shared memory, such as to
+    // reproduce a problem seen on another Pi-hole
+    match env.config().ftl_snapshot() {
+        Some(snapshot) => Ok(FtlMemory::Snapshot(Arc::new(FtlSnapshot::load(snapshot)?))),
+        None => Ok(FtlMemory::production())
+    }
+}
+
 /// Setup the API with the testing data and return a Client to test with
 #[cfg(test)]
 pub fn test(
@@ -91,7 +181,9 @@ pub fn test(
         FtlConnectionType::Test(ftl_data),
         ftl_memory,
         Env::Test(toml::from_str("").unwrap(), env_data),
-        "test_key".to_owned(),
+        auth::hash_password("test_key"),
+        TicketStore::default(),
+        Webhooks::default(),
         needs_database
     ))
     .unwrap()
@@ -104,13 +196,29 @@ fn setup(
     ftl_memory: FtlMemory,
     env: Env,
     api_key: String,
+    tickets: TicketStore,
+    webhooks: Webhooks,
     needs_database: bool
 ) -> rocket::Rocket {
-    // Set up CORS
-    let cors = Cors {
-        allow_credentials: true,
-        ..Cors::default()
+    // Mount the web interface and API under the URL prefix, if there is one
+    let url_prefix = env.config().url_prefix().to_owned();
+    let web_mount = if url_prefix.is_empty() {
+        "/".to_owned()
+    } else {
+        url_prefix.clone()
     };
+    let api_mount = format!("{}/admin/api", url_prefix);
+
+    // Set up the source network access lists
+    let access_control = AccessControl::new(
+        env.config().read_access(),
+        env.config().write_access(),
+        &api_mount
+    );
+    let trusted_proxies = TrustedProxies(env.config().trusted_proxies());
+
+    // Set up CORS
+    let cors = CorsControl::new(env.config().allowed_origins());
 
     // Attach the databases if required
     let server = if needs_database {
@@ -119,44 +227,88 @@ fn setup(
         server
     };
 
+    let legacy_hash_login = env.config().legacy_hash_login();
+
+    // Create the session store using the configured timeouts
+    let sessions = SessionStore::new(
+        env.config().session_idle_timeout(),
+        env.config().session_absolute_timeout()
+    );
+
     // Create a scheduler for scheduling work (ex. disable for 10 minutes)
     let scheduler = task_scheduler::Scheduler::new();
 
+    // Leave at least half of the workers for requests other than query
+    // streams, which hold a worker for as long as they are open
+    let stream_limit = stats::StreamLimit::new(cmp::max(1, server.config().workers as usize / 2));
+
     // Set up the server
     server
+        // Attach the access control handler. This is attached first so that
+        // denied requests are not handled by anything else.
+        .attach(access_control)
         // Attach CORS handler
         .attach(cors)
         // Add custom error handlers
-        .register(catchers![not_found, unauthorized])
+        .register(catchers![
+            not_found,
+            unauthorized,
+            forbidden,
+            too_many_requests
+        ])
         // Manage the FTL socket configuration
         .manage(ftl_socket)
         // Manage the FTL shared memory configuration
         .manage(ftl_memory)
+        // Manage the trusted reverse proxies
+        .manage(trusted_proxies)
         // Manage the environment
         .manage(env)
-        // Manage the API key
-        .manage(AuthData::new(api_key))
+        // Manage the password hash and sessions
+        .manage(AuthData::new(api_key, legacy_hash_login, sessions))
         // Manage the scheduler
         .manage(scheduler)
+        // Manage the live update connection tickets
+        .manage(tickets)
+        // Manage the webhook deliveries
+        .manage(webhooks)
+        // Manage the number of open query streams
+        .manage(stream_limit)
         // Mount the web interface
-        .mount("/", routes![
+        .mount(&web_mount, routes![
             web::web_interface_redirect,
             web::web_interface_index,
             web::web_interface
         ])
         // Mount the API
-        .mount("/admin/api", routes![
+        .mount(&api_mount, routes![
+            access::access_denied,
             version::version,
+            audit::get_audit,
             auth::check,
             auth::logout,
+            auth::change_password,
+            auth::get_sessions,
+            auth::delete_sessions,
+            auth::delete_session,
+            auth::get_lockouts,
+            auth::get_totp,
+            auth::enroll_totp,
+            auth::verify_totp,
+            auth::disable_totp,
+            auth::get_tokens,
+            auth::create_token,
+            auth::delete_token,
             stats::get_summary,
             stats::top_domains,
             stats::top_clients,
             stats::upstreams,
             stats::query_types,
             stats::history,
+            stats::history_stream,
             stats::recent_blocked,
             stats::clients,
+            stats::client_details,
             stats::over_time_history,
             stats::over_time_clients,
             stats::database::get_summary_db,
@@ -177,6 +329,7 @@ fn setup(
             dns::delete_whitelist,
             dns::delete_blacklist,
             dns::delete_regexlist,
+            live::get_ticket,
             settings::get_dhcp,
             settings::put_dhcp,
             settings::get_dns,
@@ -185,6 +338,13 @@ fn setup(
             settings::get_ftl,
             settings::get_network,
             settings::get_web,
-            settings::put_web
+            settings::put_web,
+            actions::run_action,
+            snapshot::get_snapshot,
+            memory::get_memory,
+            webhooks::get_webhooks,
+            webhooks::create_webhook,
+            webhooks::delete_webhook,
+            webhooks::get_deliveries
         ])
 }
@@ -145,6 +145,7 @@ pub struct TestBuilder {
     test_config_builder: TestEnvBuilder,
     expected_json: serde_json::Value,
     expected_status: Status,
+    absent_headers: Vec<&'static str>,
     needs_database: bool
 }
 
@@ -174,6 +175,7 @@ impl TestBuilder {
             })
             .into(),
             expected_status: Status::Ok,
+            absent_headers: Vec::new(),
             needs_database: false
         }
     }
@@ -240,6 +242,11 @@ impl TestBuilder {
         self
     }
 
+    pub fn expect_no_header(mut self, name: &'static str) -> Self {
+        self.absent_headers.push(name);
+        self
+    }
+
     pub fn need_database(mut self, need_database: bool) -> Self {
         self.needs_database = need_database;
         self
@@ -285,6 +292,11 @@ impl TestBuilder {
         // Check the status
         assert_eq!(self.expected_status, response.status());
 
+        // Check the headers which should not be in the response
+        for name in self.absent_headers {
+            assert!(response.headers().get_one(name).is_none());
+        }
+
         // Check that something was returned
         let body = response.body_string();
         assert!(body.is_some());
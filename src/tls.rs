@@ -0,0 +1,227 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// HTTPS Certificate Management
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Config,
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use hostname::get_hostname;
+use hyper::{
+    net::{HttpStream, SslServer},
+    Server
+};
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use rocket::{
+    http::tls::{util, TlsServer},
+    Rocket
+};
+use std::{
+    fs::{self, Permissions},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock
+    },
+    thread,
+    time::{Duration, SystemTime}
+};
+
+/// How many days a generated certificate is valid for
+const SELF_SIGNED_DAYS: &str = "3650";
+
+/// Set when a SIGHUP is received, to reload the certificate
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Get the certificate and key locations to give to Rocket. If a self-signed
+/// certificate is enabled and the files do not exist, they are generated
+/// first. If TLS is not enabled, `None` is returned.
+pub fn prepare(config: &Config) -> Result<Option<(String, String)>, Error> {
+    let (certificate, key) = match config.tls_files() {
+        Some(files) => files,
+        None => return Ok(None)
+    };
+
+    if config.tls_self_signed() && !(Path::new(certificate).exists() && Path::new(key).exists()) {
+        generate_self_signed(certificate, key)?;
+    }
+
+    Ok(Some((certificate.to_owned(), key.to_owned())))
+}
+
+/// Generate a self-signed certificate for this host using `openssl` (a
+/// dependency of the package). The private key is only readable by the API's
+/// user.
+fn generate_self_signed(certificate: &str, key: &str) -> Result<(), Error> {
+    let hostname = get_hostname().unwrap_or_else(|| "pi.hole".to_owned());
+
+    let status = Command::new("openssl")
+        .args(&["req", "-x509", "-nodes", "-newkey", "rsa:2048", "-sha256"])
+        .args(&["-days", SELF_SIGNED_DAYS])
+        .args(&["-subj", &format!("/CN={}", hostname)])
+        .args(&["-keyout", key, "-out", certificate])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context(ErrorKind::CertificateGeneration)?;
+
+    if !status.success() {
+        return Err(Error::from(ErrorKind::CertificateGeneration));
+    }
+
+    fs::set_permissions(key, Permissions::from_mode(0o600))
+        .context(ErrorKind::FileWrite(key.to_owned()))?;
+
+    Ok(())
+}
+
+/// The certificate used to serve HTTPS. Clones share the certificate, so a
+/// reload is seen by every connection accepted afterwards. Connections which
+/// are already open keep the certificate they started with.
+#[derive(Clone)]
+pub struct ReloadableTls {
+    certificate: String,
+    key: String,
+    server: Arc<RwLock<TlsServer>>
+}
+
+impl ReloadableTls {
+    /// Load the certificate and private key files
+    pub fn load(certificate: String, key: String) -> Result<ReloadableTls, Error> {
+        let server = load_server(&certificate, &key)?;
+
+        Ok(ReloadableTls {
+            certificate,
+            key,
+            server: Arc::new(RwLock::new(server))
+        })
+    }
+
+    /// Load the files again and use them for new connections. If they can not
+    /// be loaded, the current certificate is kept.
+    fn reload(&self) -> Result<(), Error> {
+        let server = load_server(&self.certificate, &self.key)?;
+        *self.server.write().unwrap() = server;
+
+        Ok(())
+    }
+}
+
+impl SslServer for ReloadableTls {
+    type Stream = <TlsServer as SslServer>::Stream;
+
+    fn wrap_server(&self, stream: HttpStream) -> hyper::Result<Self::Stream> {
+        let server = self.server.read().unwrap().clone();
+        server.wrap_server(stream)
+    }
+}
+
+/// Read the certificate chain and private key into a TLS server config
+fn load_server(certificate: &str, key: &str) -> Result<TlsServer, Error> {
+    let certs = util::load_certs(certificate)
+        .map_err(|_| Error::from(ErrorKind::FileRead(certificate.to_owned())))?;
+    let private_key = util::load_private_key(key)
+        .map_err(|_| Error::from(ErrorKind::FileRead(key.to_owned())))?;
+
+    Ok(TlsServer::new(certs, private_key))
+}
+
+/// Serve the API over HTTPS, reloading the certificate when the certificate
+/// or key files change, or when a SIGHUP is received. This only returns if
+/// the server could not be started.
+///
+/// Rocket loads its TLS certificate once at launch and can not replace it, so
+/// instead of `Rocket::launch` the server is started here with a certificate
+/// which can be reloaded, and Rocket handles the requests.
+pub fn launch(rocket: Rocket, tls: ReloadableTls, reload_interval: u64) -> Result<(), Error> {
+    watch(tls.clone(), reload_interval)?;
+
+    let config = rocket.config();
+    let address = format!("{}:{}", config.address, config.port);
+    let workers = config.workers as usize;
+    let seconds = |value: Option<u32>| value.map(|value| Duration::from_secs(value as u64));
+
+    let mut server = Server::https(address.as_str(), tls).context(ErrorKind::HttpsServer)?;
+    server.keep_alive(seconds(config.keep_alive));
+    server.set_read_timeout(seconds(config.read_timeout));
+    server.set_write_timeout(seconds(config.write_timeout));
+
+    info!(target: "launch", "Serving HTTPS on https://{}", address);
+
+    // The listener blocks on drop until the server stops, which it never does
+    server
+        .handle_threads(rocket, workers)
+        .context(ErrorKind::HttpsServer)?;
+
+    Ok(())
+}
+
+/// Handle SIGHUP by requesting a reload. Only the flag is set here, because
+/// very little is safe to do in a signal handler.
+extern "C" fn handle_sighup(_: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Get the last modification time of the file, if it can be read
+fn modified(file: &str) -> Option<SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Reload the certificate when the certificate or key files change, or when
+/// a SIGHUP is received. The files are checked every `interval` seconds.
+fn watch(tls: ReloadableTls, interval: u64) -> Result<(), Error> {
+    let action = SigAction::new(
+        SigHandler::Handler(handle_sighup),
+        SaFlags::SA_RESTART,
+        SigSet::empty()
+    );
+
+    // This is safe because the handler only touches an atomic
+    unsafe { signal::sigaction(Signal::SIGHUP, &action) }.context(ErrorKind::Unknown)?;
+
+    thread::spawn(move || {
+        let mut last_modified = (modified(&tls.certificate), modified(&tls.key));
+        let mut elapsed = 0;
+
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            elapsed += 1;
+
+            let reload_requested = RELOAD_REQUESTED.swap(false, Ordering::SeqCst);
+
+            if !reload_requested && elapsed < interval {
+                continue;
+            }
+
+            elapsed = 0;
+            let current_modified = (modified(&tls.certificate), modified(&tls.key));
+
+            if !reload_requested && current_modified == last_modified {
+                continue;
+            }
+
+            last_modified = current_modified;
+
+            // The files may be in the middle of being replaced, in which case
+            // the next change will load them
+            match tls.reload() {
+                Ok(()) => info!("Reloaded the TLS certificate from {}", tls.certificate),
+                Err(e) => warn!("Keeping the current TLS certificate: {}", e)
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -34,7 +34,10 @@ pub fn reply<D: Serialize>(data: Result<D, Error>, status: Status) -> Reply {
         Err(e) => {
             // Only print out the error if it's not a common error
             match e.kind() {
-                ErrorKind::Unauthorized | ErrorKind::NotFound => (),
+                ErrorKind::Unauthorized
+                | ErrorKind::Forbidden
+                | ErrorKind::TooManyRequests(_)
+                | ErrorKind::NotFound => (),
                 _ => e.print_stacktrace()
             }
 
@@ -108,6 +111,8 @@ pub enum ErrorKind {
     FtlReadError,
     #[fail(display = "Read unexpected EOM from FTL")]
     FtlEomError,
+    #[fail(display = "Timed out waiting for FTL")]
+    FtlTimeout,
     #[fail(display = "Not found")]
     NotFound,
     #[fail(display = "Item already exists")]
@@ -118,6 +123,10 @@ pub enum ErrorKind {
     BadRequest,
     #[fail(display = "Unauthorized")]
     Unauthorized,
+    #[fail(display = "Forbidden")]
+    Forbidden,
+    #[fail(display = "Too many requests, try again in {} seconds", _0)]
+    TooManyRequests(u64),
     #[fail(display = "Error reading from {}", _0)]
     FileRead(String),
     #[fail(display = "Error writing to {}", _0)]
@@ -148,7 +157,13 @@ pub enum ErrorKind {
     )]
     SharedMemoryVersion(usize, usize),
     #[fail(display = "Error while interacting with the FTL database")]
-    FtlDatabase
+    FtlDatabase,
+    #[fail(display = "Failed to generate the TLS certificate")]
+    CertificateGeneration,
+    #[fail(display = "Failed to start the HTTPS server")]
+    HttpsServer,
+    #[fail(display = "Failed to start the live update server")]
+    LiveServer
 }
 
 impl Error {
@@ -186,7 +201,7 @@ impl Error {
     /// Get extra data about the error from the [`ErrorKind`]
     ///
     /// [`ErrorKind`]: enum.ErrorKind.html
-    fn data(&self) -> Option<JsonValue> {
+    pub fn data(&self) -> Option<JsonValue> {
         self.inner.get_context().data()
     }
 
@@ -219,11 +234,14 @@ impl ErrorKind {
             ErrorKind::FtlConnectionFail => "ftl_connection_fail",
             ErrorKind::FtlReadError => "ftl_read_error",
             ErrorKind::FtlEomError => "ftl_eom_error",
+            ErrorKind::FtlTimeout => "ftl_timeout",
             ErrorKind::NotFound => "not_found",
             ErrorKind::AlreadyExists => "already_exists",
             ErrorKind::InvalidDomain => "invalid_domain",
             ErrorKind::BadRequest => "bad_request",
             ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::Forbidden => "forbidden",
+            ErrorKind::TooManyRequests(_) => "too_many_requests",
             ErrorKind::FileRead(_) => "file_read",
             ErrorKind::FileWrite(_) => "file_write",
             ErrorKind::ConfigParsingError => "config_parsing_error",
@@ -235,7 +253,10 @@ impl ErrorKind {
             ErrorKind::SharedMemoryRead => "shared_memory_read",
             ErrorKind::SharedMemoryLock => "shared_memory_lock",
             ErrorKind::SharedMemoryVersion(_, _) => "shared_memory_version",
-            ErrorKind::FtlDatabase => "ftl_database"
+            ErrorKind::FtlDatabase => "ftl_database",
+            ErrorKind::CertificateGeneration => "certificate_generation",
+            ErrorKind::HttpsServer => "https_server",
+            ErrorKind::LiveServer => "live_server"
         }
     }
 
@@ -248,6 +269,9 @@ impl ErrorKind {
                 Status::BadRequest
             }
             ErrorKind::Unauthorized => Status::Unauthorized,
+            ErrorKind::Forbidden => Status::Forbidden,
+            ErrorKind::TooManyRequests(_) => Status::TooManyRequests,
+            ErrorKind::FtlTimeout => Status::GatewayTimeout,
             ErrorKind::Unknown
             | ErrorKind::GravityError
             | ErrorKind::FtlConnectionFail
@@ -263,7 +287,10 @@ impl ErrorKind {
             | ErrorKind::SharedMemoryRead
             | ErrorKind::SharedMemoryLock
             | ErrorKind::SharedMemoryVersion(_, _)
-            | ErrorKind::FtlDatabase => Status::InternalServerError
+            | ErrorKind::FtlDatabase
+            | ErrorKind::CertificateGeneration
+            | ErrorKind::HttpsServer
+            | ErrorKind::LiveServer => Status::InternalServerError
         }
     }
 
@@ -272,6 +299,7 @@ impl ErrorKind {
         match self {
             ErrorKind::FileRead(file) => Some(json!({ "file": file })),
             ErrorKind::FileWrite(file) => Some(json!({ "file": file })),
+            ErrorKind::TooManyRequests(retry_after) => Some(json!({ "retry_after": retry_after })),
             _ => None
         }
     }
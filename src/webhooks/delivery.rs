@@ -0,0 +1,476 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Webhook Delivery
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::hmac_sha256_hex,
+    util::Error,
+    webhooks::{EventKind, Webhook, WebhookEvent}
+};
+use hyper::{
+    header::{ContentType, Headers},
+    Client
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+/// How many times a delivery is attempted before giving up
+const MAX_ATTEMPTS: usize = 5;
+
+/// How long to wait before the first retry. The wait doubles after each
+/// failed attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How long to wait for the receiver to accept or answer a request
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many deliveries are kept in the delivery log
+const MAX_LOG_ENTRIES: usize = 500;
+
+/// The state of a delivery
+#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryOutcome {
+    /// The delivery has not succeeded yet, but will be retried
+    Pending,
+    Success,
+    /// Every attempt failed
+    Failed
+}
+
+/// A record of a delivery of an event to a webhook
+#[derive(Clone, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Delivery {
+    pub id: usize,
+    pub webhook_id: String,
+    pub event: EventKind,
+    pub timestamp: u64,
+    pub attempts: usize,
+    /// The HTTP status of the last response, if there was one
+    pub status: Option<u16>,
+    pub outcome: DeliveryOutcome,
+    /// Why the last attempt failed
+    pub error: Option<String>
+}
+
+/// Delivers events to the registered webhooks. Each delivery is sent as a
+/// JSON `POST` request:
+///
+/// ```json
+/// { "id": 1, "event": "blocking.disabled", "timestamp": 1550000000, "data": {} }
+/// ```
+///
+/// The request has an `X-Pihole-Signature` header with the HMAC-SHA256 of the
+/// body, keyed with the webhook's secret (`sha256=<hex>`). Failed deliveries
+/// are retried in the background with exponential backoff. The most recent
+/// deliveries are kept in memory for the delivery log.
+///
+/// The registered webhooks are read from [`PiholeFile::ApiWebhooks`] the
+/// first time they are needed, and then kept in memory. Changes must go
+/// through [`save`] so the kept webhooks stay up to date.
+///
+/// [`PiholeFile::ApiWebhooks`]: ../env/enum.PiholeFile.html
+/// [`save`]: #method.save
+#[derive(Clone)]
+pub struct Webhooks {
+    registered: Arc<Mutex<Option<Vec<Webhook>>>>,
+    deliveries: Arc<Mutex<VecDeque<Delivery>>>,
+    next_id: Arc<AtomicUsize>,
+    retry_delay: Duration
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Webhooks {
+            registered: Arc::new(Mutex::new(None)),
+            deliveries: Arc::new(Mutex::new(VecDeque::new())),
+            next_id: Arc::new(AtomicUsize::new(1)),
+            retry_delay: RETRY_DELAY
+        }
+    }
+}
+
+impl Webhooks {
+    /// Send the event to every webhook registered for it. Errors are printed
+    /// instead of returned, so that the action which caused the event does
+    /// not fail.
+    pub fn fire(&self, env: &Env, event: WebhookEvent) {
+        let webhooks = match self.registered(env) {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                e.print_stacktrace();
+                return;
+            }
+        };
+
+        for webhook in webhooks {
+            if webhook.wants(event.kind) {
+                self.deliver(webhook, &event);
+            }
+        }
+    }
+
+    /// Get the registered webhooks, reading them from the file if this is the
+    /// first time they are needed
+    pub fn registered(&self, env: &Env) -> Result<Vec<Webhook>, Error> {
+        let mut registered = self.registered.lock().unwrap();
+
+        if registered.is_none() {
+            *registered = Some(Webhook::load_all(env)?);
+        }
+
+        Ok(registered.as_ref().unwrap().clone())
+    }
+
+    /// Replace the registered webhooks, both in the file and in memory
+    pub fn save(&self, env: &Env, webhooks: Vec<Webhook>) -> Result<(), Error> {
+        let mut registered = self.registered.lock().unwrap();
+
+        Webhook::save_all(&webhooks, env)?;
+        *registered = Some(webhooks);
+
+        Ok(())
+    }
+
+    /// Check if any registered webhook wants the event. If the webhooks can
+    /// not be read, none want it.
+    pub fn is_wanted(&self, env: &Env, kind: EventKind) -> bool {
+        self.registered(env)
+            .map(|webhooks| webhooks.iter().any(|webhook| webhook.wants(kind)))
+            .unwrap_or(false)
+    }
+
+    /// Get the delivery log, newest first
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        self.deliveries
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .cloned()
+            .collect()
+    }
+
+    /// Start delivering the event to the webhook in the background
+    fn deliver(&self, webhook: Webhook, event: &WebhookEvent) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Current time is older than epoch")
+            .as_secs();
+        let body = json!({
+            "id": id,
+            "event": event.kind,
+            "timestamp": timestamp,
+            "data": event.data
+        })
+        .to_string();
+
+        {
+            let mut deliveries = self.deliveries.lock().unwrap();
+
+            if deliveries.len() == MAX_LOG_ENTRIES {
+                deliveries.pop_front();
+            }
+
+            deliveries.push_back(Delivery {
+                id,
+                webhook_id: webhook.id.clone(),
+                event: event.kind,
+                timestamp,
+                attempts: 0,
+                status: None,
+                outcome: DeliveryOutcome::Pending,
+                error: None
+            });
+        }
+
+        let webhooks = self.clone();
+        let kind = event.kind;
+
+        thread::spawn(move || {
+            let mut delay = webhooks.retry_delay;
+
+            for attempt in 1..=MAX_ATTEMPTS {
+                let result = send(&webhook, kind, id, &body);
+                let success = result
+                    .as_ref()
+                    .ok()
+                    .map_or(false, |status| is_success(*status));
+
+                webhooks.update(id, |delivery| {
+                    delivery.attempts = attempt;
+                    delivery.status = result.as_ref().ok().cloned();
+                    delivery.error = match &result {
+                        Ok(status) if !success => Some(format!("Received status {}", status)),
+                        Ok(_) => None,
+                        Err(e) => Some(e.clone())
+                    };
+                    delivery.outcome = if success {
+                        DeliveryOutcome::Success
+                    } else if attempt == MAX_ATTEMPTS {
+                        DeliveryOutcome::Failed
+                    } else {
+                        DeliveryOutcome::Pending
+                    };
+                });
+
+                if success {
+                    break;
+                }
+
+                if attempt < MAX_ATTEMPTS {
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+            }
+        });
+    }
+
+    /// Update a delivery in the log, if it is still there
+    fn update(&self, id: usize, update: impl FnOnce(&mut Delivery)) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+
+        if let Some(delivery) = deliveries.iter_mut().find(|delivery| delivery.id == id) {
+            update(delivery);
+        }
+    }
+}
+
+/// Send the request for a delivery. The response status is returned, or a
+/// description of the error if there was no response.
+fn send(webhook: &Webhook, kind: EventKind, id: usize, body: &str) -> Result<u16, String> {
+    let mut client = Client::new();
+    client.set_read_timeout(Some(TIMEOUT));
+    client.set_write_timeout(Some(TIMEOUT));
+
+    let mut headers = Headers::new();
+    headers.set(ContentType::json());
+    headers.set_raw("X-Pihole-Event", vec![kind.name().as_bytes().to_vec()]);
+    headers.set_raw("X-Pihole-Delivery", vec![id.to_string().into_bytes()]);
+    headers.set_raw(
+        "X-Pihole-Signature",
+        vec![format!(
+            "sha256={}",
+            hmac_sha256_hex(webhook.secret.as_bytes(), body.as_bytes())
+        )
+        .into_bytes()]
+    );
+
+    client
+        .post(webhook.url.as_str())
+        .headers(headers)
+        .body(body)
+        .send()
+        .map(|response| response.status.to_u16())
+        .map_err(|e| e.to_string())
+}
+
+/// Check if the HTTP status means the delivery was accepted
+fn is_success(status: u16) -> bool {
+    status >= 200 && status < 300
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DeliveryOutcome, Webhooks};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        routes::auth::hmac_sha256_hex,
+        testing::TestEnvBuilder,
+        webhooks::{EventKind, Webhook, WebhookEvent}
+    };
+    use serde_json::Value;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+        time::Duration
+    };
+
+    /// A request received by the test receiver
+    struct Received {
+        headers: Vec<String>,
+        body: String
+    }
+
+    /// Start a receiver which answers requests with the statuses, in order.
+    /// The address and a handle which returns the requests are returned.
+    fn receiver(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            statuses
+                .into_iter()
+                .map(|status| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    let mut headers = Vec::new();
+
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+
+                        if line.trim().is_empty() {
+                            break;
+                        }
+
+                        headers.push(line.trim().to_lowercase());
+                    }
+
+                    let length: usize = headers
+                        .iter()
+                        .find(|header| header.starts_with("content-length:"))
+                        .and_then(|header| header[15..].trim().parse().ok())
+                        .unwrap_or_default();
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+
+                    write!(
+                        reader.get_mut(),
+                        "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        status
+                    )
+                    .unwrap();
+
+                    Received {
+                        headers,
+                        body: String::from_utf8(body).unwrap()
+                    }
+                })
+                .collect()
+        });
+
+        (address, handle)
+    }
+
+    /// Create an environment with a webhook for the URL, registered for
+    /// blocking changes
+    fn env(url: &str) -> Env {
+        Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(
+                    PiholeFile::ApiWebhooks,
+                    &json!([{
+                        "id": "0a1b2c3d",
+                        "url": url,
+                        "events": ["blocking.enabled", "blocking.disabled"],
+                        "secret": "secret",
+                        "created": 1_550_000_000
+                    }])
+                    .to_string()
+                )
+                .build()
+        )
+    }
+
+    /// Wait for the delivery to stop being pending
+    fn wait_for_outcome(webhooks: &Webhooks) -> DeliveryOutcome {
+        for _ in 0..100 {
+            let outcome = webhooks.deliveries()[0].outcome;
+
+            if outcome != DeliveryOutcome::Pending {
+                return outcome;
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        DeliveryOutcome::Pending
+    }
+
+    /// Events are delivered to the receiver, signed with the webhook's secret
+    #[test]
+    fn signed_delivery() {
+        let (url, receiver) = receiver(vec![200]);
+        let webhooks = Webhooks::default();
+
+        webhooks.fire(
+            &env(&url),
+            WebhookEvent::new(EventKind::BlockingDisabled, json!({ "time": 60 }))
+        );
+
+        let received = receiver.join().unwrap();
+        let body: Value = serde_json::from_str(&received[0].body).unwrap();
+        let signature = format!(
+            "x-pihole-signature: sha256={}",
+            hmac_sha256_hex(b"secret", received[0].body.as_bytes())
+        );
+
+        assert_eq!(body["event"], "blocking.disabled");
+        assert_eq!(body["data"], json!({ "time": 60 }).0);
+        assert!(received[0].headers.contains(&signature));
+        assert!(received[0]
+            .headers
+            .contains(&"x-pihole-event: blocking.disabled".to_owned()));
+        assert_eq!(wait_for_outcome(&webhooks), DeliveryOutcome::Success);
+        assert_eq!(webhooks.deliveries()[0].status, Some(200));
+    }
+
+    /// Failed deliveries are retried
+    #[test]
+    fn retry() {
+        let (url, receiver) = receiver(vec![500, 503, 204]);
+        let webhooks = Webhooks {
+            retry_delay: Duration::from_millis(10),
+            ..Webhooks::default()
+        };
+
+        webhooks.fire(
+            &env(&url),
+            WebhookEvent::new(EventKind::BlockingEnabled, Value::Null)
+        );
+
+        assert_eq!(receiver.join().unwrap().len(), 3);
+        assert_eq!(wait_for_outcome(&webhooks), DeliveryOutcome::Success);
+        assert_eq!(webhooks.deliveries()[0].attempts, 3);
+    }
+
+    /// Webhooks are only sent the events they are registered for
+    #[test]
+    fn unregistered_event() {
+        let webhooks = Webhooks::default();
+
+        webhooks.fire(
+            &env("http://127.0.0.1:1/"),
+            WebhookEvent::new(EventKind::NewClient, Value::Null)
+        );
+
+        assert!(webhooks.deliveries().is_empty());
+    }
+
+    /// The webhooks are only read from the file once, and saving replaces
+    /// them in both the file and memory
+    #[test]
+    fn registered_webhooks() {
+        let env = env("http://127.0.0.1:1/");
+        let webhooks = Webhooks::default();
+
+        assert_eq!(webhooks.registered(&env).unwrap().len(), 1);
+        assert!(webhooks.is_wanted(&env, EventKind::BlockingEnabled));
+        assert!(!webhooks.is_wanted(&env, EventKind::NewClient));
+
+        webhooks.save(&env, Vec::new()).unwrap();
+
+        assert!(webhooks.registered(&env).unwrap().is_empty());
+        assert!(Webhook::load_all(&env).unwrap().is_empty());
+        assert!(!webhooks.is_wanted(&env, EventKind::BlockingEnabled));
+    }
+}
@@ -0,0 +1,92 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Webhook Events
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use serde_json::Value;
+
+/// The types of events which webhooks can be registered for
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum EventKind {
+    /// Blocking was enabled, either through the API or when a timed disable
+    /// ran out
+    #[serde(rename = "blocking.enabled")]
+    BlockingEnabled,
+    #[serde(rename = "blocking.disabled")]
+    BlockingDisabled,
+    #[serde(rename = "list.added")]
+    ListEntryAdded,
+    #[serde(rename = "list.removed")]
+    ListEntryRemoved,
+    #[serde(rename = "settings.changed")]
+    SettingsChanged,
+    /// A client appeared in shared memory for the first time
+    #[serde(rename = "client.new")]
+    NewClient,
+    /// Shared memory could not be read, after it was last read successfully
+    #[serde(rename = "ftl.unreachable")]
+    FtlUnreachable
+}
+
+impl EventKind {
+    /// Get the name of the event, as used in the API
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::BlockingEnabled => "blocking.enabled",
+            EventKind::BlockingDisabled => "blocking.disabled",
+            EventKind::ListEntryAdded => "list.added",
+            EventKind::ListEntryRemoved => "list.removed",
+            EventKind::SettingsChanged => "settings.changed",
+            EventKind::NewClient => "client.new",
+            EventKind::FtlUnreachable => "ftl.unreachable"
+        }
+    }
+}
+
+/// Something which happened in Pi-hole, along with the details of what
+/// happened. The details depend on the kind of event.
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct WebhookEvent {
+    pub kind: EventKind,
+    pub data: Value
+}
+
+impl WebhookEvent {
+    /// Create an event with the details in `data`
+    pub fn new<V: Into<Value>>(kind: EventKind, data: V) -> WebhookEvent {
+        WebhookEvent {
+            kind,
+            data: data.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EventKind;
+
+    /// The event names match how the events are serialized
+    #[test]
+    fn names() {
+        for kind in &[
+            EventKind::BlockingEnabled,
+            EventKind::BlockingDisabled,
+            EventKind::ListEntryAdded,
+            EventKind::ListEntryRemoved,
+            EventKind::SettingsChanged,
+            EventKind::NewClient,
+            EventKind::FtlUnreachable
+        ] {
+            assert_eq!(
+                serde_json::to_value(kind).unwrap(),
+                serde_json::Value::from(kind.name())
+            );
+        }
+    }
+}
@@ -0,0 +1,21 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Webhooks
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+mod delivery;
+mod event;
+mod watch;
+mod webhook;
+
+pub use self::{
+    delivery::{Delivery, DeliveryOutcome, Webhooks},
+    event::{EventKind, WebhookEvent},
+    watch::Watcher,
+    webhook::Webhook
+};
@@ -0,0 +1,196 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Webhook Event Watcher
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::{FtlMemory, Validator},
+    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
+    util::Error,
+    webhooks::{EventKind, WebhookEvent, Webhooks}
+};
+use std::{collections::HashSet, thread, time::Duration};
+
+/// How often shared memory is checked for events
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Watches shared memory for the events which do not come from API requests:
+/// new clients, and FTL becoming unreachable. Shared memory is only read while
+/// a webhook wants one of these events.
+pub struct Watcher {
+    ftl_memory: FtlMemory,
+    env: Env,
+    webhooks: Webhooks,
+    /// The IP addresses of the clients seen so far. This is `None` until
+    /// shared memory is first read, so the existing clients are not reported
+    /// as new.
+    known_clients: Option<HashSet<String>>,
+    reachable: bool
+}
+
+impl Watcher {
+    /// Create a watcher which reads shared memory outside of Rocket
+    pub fn new(ftl_memory: FtlMemory, env: Env, webhooks: Webhooks) -> Watcher {
+        Watcher {
+            ftl_memory,
+            env,
+            webhooks,
+            known_clients: None,
+            reachable: true
+        }
+    }
+
+    /// Start watching in the background
+    pub fn start(mut self) {
+        thread::spawn(move || loop {
+            self.check();
+            thread::sleep(WATCH_INTERVAL);
+        });
+    }
+
+    /// Check shared memory and fire the events which happened since the last
+    /// check
+    fn check(&mut self) {
+        // Start over when no webhook wants the events, so the clients which
+        // connect in the meantime are not reported once a webhook is added
+        if !self.webhooks.is_wanted(&self.env, EventKind::NewClient)
+            && !self
+                .webhooks
+                .is_wanted(&self.env, EventKind::FtlUnreachable)
+        {
+            self.known_clients = None;
+            self.reachable = true;
+            return;
+        }
+
+        let clients = match self.read_clients() {
+            Ok(clients) => clients,
+            Err(e) => {
+                // Only report FTL becoming unreachable, not every failed check
+                if self.reachable {
+                    self.reachable = false;
+                    self.webhooks.fire(
+                        &self.env,
+                        WebhookEvent::new(
+                            EventKind::FtlUnreachable,
+                            json!({ "error": e.to_string() })
+                        )
+                    );
+                }
+
+                return;
+            }
+        };
+
+        self.reachable = true;
+
+        // Client details are hidden at this privacy level
+        let hide_clients = FtlConfEntry::PrivacyLevel
+            .read_as::<FtlPrivacyLevel>(&self.env)
+            .map(|level| level >= FtlPrivacyLevel::HideDomainsAndClients)
+            .unwrap_or(true);
+
+        let known_clients = match self.known_clients {
+            Some(ref mut known_clients) => known_clients,
+            None => {
+                self.known_clients = Some(clients.into_iter().map(|(ip, _)| ip).collect());
+                return;
+            }
+        };
+
+        for (ip, name) in clients {
+            if known_clients.insert(ip.clone()) && !hide_clients {
+                self.webhooks.fire(
+                    &self.env,
+                    WebhookEvent::new(EventKind::NewClient, json!({ "ip": ip, "name": name }))
+                );
+            }
+        }
+    }
+
+    /// Read the IP address and name of each client in shared memory. Hidden
+    /// clients are skipped.
+    fn read_clients(&self) -> Result<Vec<(String, Option<String>)>, Error> {
+        let lock = self.ftl_memory.lock()?;
+        let validator = Validator::new(&self.ftl_memory, &lock)?;
+        let clients = self.ftl_memory.clients(&lock)?;
+        let strings = self.ftl_memory.strings(&lock)?;
+
+        Ok(validator
+            .clients(&clients)
+            .map(|client| {
+                (
+                    client.get_ip(&strings).to_owned(),
+                    client.get_name(&strings).map(str::to_owned)
+                )
+            })
+            .filter(|(ip, _)| ip != "0.0.0.0")
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Watcher;
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        ftl::{FtlMemory, FtlSettings},
+        routes::stats::history::testing::{test_clients, test_counters, test_memory, test_strings},
+        testing::TestEnvBuilder,
+        webhooks::{EventKind, Webhooks}
+    };
+
+    /// Only the clients which appear after the first check are reported, and
+    /// hidden clients are not reported
+    #[test]
+    fn new_clients() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(
+                    PiholeFile::ApiWebhooks,
+                    r#"[{"id":"0a1b2c3d","url":"http://127.0.0.1:1/","events":["client.new"],"secret":"secret","created":1550000000}]"#
+                )
+                .build()
+        );
+        let first_clients = FtlMemory::Test {
+            clients: test_clients()[..2].to_vec(),
+            counters: test_counters(),
+            domains: Vec::new(),
+            over_time: Vec::new(),
+            strings: test_strings(),
+            queries: Vec::new(),
+            upstreams: Vec::new(),
+            settings: FtlSettings::default()
+        };
+        let webhooks = Webhooks::default();
+        let mut watcher = Watcher::new(first_clients, env, webhooks.clone());
+
+        watcher.check();
+        assert!(webhooks.deliveries().is_empty());
+
+        watcher.ftl_memory = test_memory();
+        watcher.check();
+        watcher.check();
+
+        let deliveries = webhooks.deliveries();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].event, EventKind::NewClient);
+    }
+
+    /// Shared memory is not read when no webhook wants the events
+    #[test]
+    fn no_webhooks() {
+        let env = Env::Test(Config::default(), TestEnvBuilder::new().build());
+        let mut watcher = Watcher::new(test_memory(), env, Webhooks::default());
+
+        watcher.check();
+        assert!(watcher.known_clients.is_none());
+    }
+}
@@ -0,0 +1,99 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Webhook Storage
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    routes::auth::{hex_encode, random_bytes},
+    util::{Error, ErrorKind},
+    webhooks::EventKind
+};
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use failure::ResultExt;
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// A registered webhook, as stored in [`PiholeFile::ApiWebhooks`]. The secret
+/// is used to sign deliveries, so unlike token secrets it is stored as is.
+///
+/// [`PiholeFile::ApiWebhooks`]: ../env/enum.PiholeFile.html
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<EventKind>,
+    pub secret: String,
+    pub created: u64
+}
+
+impl Webhook {
+    /// Load all webhooks from [`PiholeFile::ApiWebhooks`]. A missing or empty
+    /// file means there are no webhooks.
+    ///
+    /// [`PiholeFile::ApiWebhooks`]: ../env/enum.PiholeFile.html
+    pub fn load_all(env: &Env) -> Result<Vec<Webhook>, Error> {
+        if !env.file_exists(PiholeFile::ApiWebhooks) {
+            return Ok(Vec::new());
+        }
+
+        let mut buffer = String::new();
+        env.read_file(PiholeFile::ApiWebhooks)?
+            .read_to_string(&mut buffer)
+            .context(ErrorKind::FileRead(
+                env.file_location(PiholeFile::ApiWebhooks).to_owned()
+            ))?;
+
+        if buffer.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        serde_json::from_str(&buffer)
+            .context(ErrorKind::FileRead(
+                env.file_location(PiholeFile::ApiWebhooks).to_owned()
+            ))
+            .map_err(Error::from)
+    }
+
+    /// Overwrite [`PiholeFile::ApiWebhooks`] with the webhooks
+    ///
+    /// [`PiholeFile::ApiWebhooks`]: ../env/enum.PiholeFile.html
+    pub fn save_all(webhooks: &[Webhook], env: &Env) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(webhooks).context(ErrorKind::Unknown)?;
+
+        env.write_file(PiholeFile::ApiWebhooks, false)?
+            .write_all(&data)
+            .context(ErrorKind::FileWrite(
+                env.file_location(PiholeFile::ApiWebhooks).to_owned()
+            ))?;
+
+        Ok(())
+    }
+
+    /// Create a webhook with a new ID and secret
+    pub fn generate(url: String, events: Vec<EventKind>) -> Result<Webhook, Error> {
+        Ok(Webhook {
+            id: hex_encode(&random_bytes(4)?),
+            url,
+            events,
+            secret: encode_config(&random_bytes(32)?, URL_SAFE_NO_PAD),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Current time is older than epoch")
+                .as_secs()
+        })
+    }
+
+    /// Check if the webhook is registered for the event
+    pub fn wants(&self, kind: EventKind) -> bool {
+        self.events.contains(&kind)
+    }
+}